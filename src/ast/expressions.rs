@@ -1,12 +1,20 @@
 use std::{collections::HashMap, fmt::Display, hash::Hash};
 
 use super::statements::BlockStatement;
-use crate::lexer::{keywords, token};
+use crate::{
+    lexer::{
+        keywords,
+        token::{self, Span},
+    },
+    object::bigint::BigInt,
+};
 
 #[derive(PartialEq, Eq, Hash, Clone)]
 pub enum AllExpressions {
     Identifier(Identifier),
     IntegerLiteral(IntegerLiteral),
+    BigIntegerLiteral(BigIntegerLiteral),
+    FloatLiteral(FloatLiteral),
     StringLiteral(StringLiteral),
     PrefixExpression(PrefixExpression),
     InfixExpression(InfixExpression),
@@ -19,7 +27,34 @@ pub enum AllExpressions {
     IndexExpression(IndexExpression),
     RangeExpression(RangeExpression),
     HashLiteral(HashLiteral),
-    NullLiteral,
+    NullLiteral(NullLiteral),
+}
+
+impl AllExpressions {
+    /// Returns the span of the token that the expression node was created from, which is the
+    /// operator for infix expressions and the opening bracket for calls and indexes.
+    pub fn span(&self) -> Span {
+        let token = match self {
+            AllExpressions::Identifier(v) => &v.token,
+            AllExpressions::IntegerLiteral(v) => &v.token,
+            AllExpressions::BigIntegerLiteral(v) => &v.token,
+            AllExpressions::FloatLiteral(v) => &v.token,
+            AllExpressions::StringLiteral(v) => &v.token,
+            AllExpressions::PrefixExpression(v) => &v.token,
+            AllExpressions::InfixExpression(v) => &v.token,
+            AllExpressions::Boolean(v) => &v.token,
+            AllExpressions::Assignment(v) => &v.token,
+            AllExpressions::IfExpression(v) => &v.token,
+            AllExpressions::FunctionLiteral(v) => &v.token,
+            AllExpressions::CallExpression(v) => &v.token,
+            AllExpressions::ArrayLiteral(v) => &v.token,
+            AllExpressions::IndexExpression(v) => &v.token,
+            AllExpressions::RangeExpression(v) => &v.token,
+            AllExpressions::HashLiteral(v) => &v.token,
+            AllExpressions::NullLiteral(v) => &v.token,
+        };
+        token.span
+    }
 }
 
 impl Display for AllExpressions {
@@ -27,6 +62,8 @@ impl Display for AllExpressions {
         let out = match self {
             AllExpressions::Identifier(v) => v.to_string(),
             AllExpressions::IntegerLiteral(v) => v.to_string(),
+            AllExpressions::BigIntegerLiteral(v) => v.to_string(),
+            AllExpressions::FloatLiteral(v) => v.to_string(),
             AllExpressions::StringLiteral(v) => v.to_string(),
             AllExpressions::PrefixExpression(v) => v.to_string(),
             AllExpressions::InfixExpression(v) => v.to_string(),
@@ -35,7 +72,7 @@ impl Display for AllExpressions {
             AllExpressions::FunctionLiteral(v) => v.to_string(),
             AllExpressions::CallExpression(v) => v.to_string(),
             AllExpressions::ArrayLiteral(v) => v.to_string(),
-            AllExpressions::NullLiteral => keywords::NULL.to_string(),
+            AllExpressions::NullLiteral(v) => v.to_string(),
             AllExpressions::IndexExpression(v) => v.to_string(),
             AllExpressions::Assignment(v) => v.to_string(),
             AllExpressions::RangeExpression(v) => v.to_string(),
@@ -69,6 +106,45 @@ impl Display for IntegerLiteral {
     }
 }
 
+/// An integer literal that doesn't fit in an `i64`
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub struct BigIntegerLiteral {
+    pub token: token::Token, // Int token
+    pub value: BigInt,
+}
+
+impl Display for BigIntegerLiteral {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[derive(Clone)]
+pub struct FloatLiteral {
+    pub token: token::Token, // Float token
+    pub value: f64,
+}
+
+impl PartialEq for FloatLiteral {
+    fn eq(&self, other: &Self) -> bool {
+        self.value.to_bits() == other.value.to_bits()
+    }
+}
+
+impl Eq for FloatLiteral {}
+
+impl Hash for FloatLiteral {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.value.to_bits().hash(state);
+    }
+}
+
+impl Display for FloatLiteral {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.value)
+    }
+}
+
 #[derive(PartialEq, Eq, Hash, Clone)]
 pub struct StringLiteral {
     pub token: token::Token, // String token
@@ -164,10 +240,9 @@ impl Display for IfExpression {
 
         let mut out = format!("if {} {{ \n{}}}", self.condition, consequence);
 
-        match &self.alternative {
-            Some(v) => out.push_str(format!("else {}", v).as_str()),
-            None => {}
-        };
+        if let Some(v) = &self.alternative {
+            out.push_str(format!("else {}", v).as_str());
+        }
 
         write!(f, "{}", out)
     }
@@ -253,13 +328,20 @@ impl Display for IndexExpression {
 pub struct RangeExpression {
     pub token: token::Token,
     pub left: Box<AllExpressions>,
-    pub left_index: Box<AllExpressions>,
-    pub right_index: Box<AllExpressions>,
+    pub left_index: Option<Box<AllExpressions>>, // None for `[:end]`
+    pub right_index: Option<Box<AllExpressions>>, // None for `[start:]`
 }
 
 impl Display for RangeExpression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let out = format!("({}[{}:{}])", self.left, self.left_index, self.right_index);
+        let left_index = self.left_index.as_ref().map(|v| v.to_string());
+        let right_index = self.right_index.as_ref().map(|v| v.to_string());
+        let out = format!(
+            "({}[{}:{}])",
+            self.left,
+            left_index.unwrap_or_default(),
+            right_index.unwrap_or_default()
+        );
         write!(f, "{}", out)
     }
 }
@@ -296,3 +378,14 @@ impl Display for HashLiteral {
         write!(f, "{{{}}}", pairs)
     }
 }
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub struct NullLiteral {
+    pub token: token::Token,
+}
+
+impl Display for NullLiteral {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", keywords::NULL)
+    }
+}
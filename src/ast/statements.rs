@@ -1,7 +1,7 @@
 use std::fmt::Display;
 
 use super::expressions::{self, AllExpressions};
-use crate::lexer::token;
+use crate::lexer::token::{self, Span};
 
 #[derive(PartialEq, Eq, Hash, Clone)]
 pub enum AllStatements {
@@ -10,6 +10,9 @@ pub enum AllStatements {
     Expression(ExpressionStatement),
     Block(BlockStatement),
     While(WhileStatement),
+    For(ForStatement),
+    Break(BreakStatement),
+    Continue(ContinueStatement),
 }
 
 impl Display for AllStatements {
@@ -20,12 +23,32 @@ impl Display for AllStatements {
             AllStatements::Expression(v) => v.to_string(),
             AllStatements::Block(v) => v.to_string(),
             AllStatements::While(v) => v.to_string(),
+            AllStatements::For(v) => v.to_string(),
+            AllStatements::Break(v) => v.to_string(),
+            AllStatements::Continue(v) => v.to_string(),
         };
 
         write!(f, "{}", out)
     }
 }
 
+impl AllStatements {
+    /// Returns the span of the token that the statement node was created from
+    pub fn span(&self) -> Span {
+        let token = match self {
+            AllStatements::Let(v) => &v.token,
+            AllStatements::Return(v) => &v.token,
+            AllStatements::Expression(v) => &v.token,
+            AllStatements::Block(v) => &v.token,
+            AllStatements::While(v) => &v.token,
+            AllStatements::For(v) => &v.token,
+            AllStatements::Break(v) => &v.token,
+            AllStatements::Continue(v) => &v.token,
+        };
+        token.span
+    }
+}
+
 #[derive(PartialEq, Eq, Hash, Clone)]
 pub struct LetStatement {
     pub token: token::Token, // Let token
@@ -107,3 +130,54 @@ impl Display for WhileStatement {
         write!(f, "{}", out)
     }
 }
+
+/// A `for (value in iterable)` or a `for (key, value in iterable)` loop.
+///
+/// Arrays, strings and ranges yield their elements with their indexes as keys, while hash maps
+/// yield their keys when there's a single variable and their keys and values otherwise.
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub struct ForStatement {
+    pub token: token::Token,
+    pub key: Option<expressions::Identifier>,
+    pub value: expressions::Identifier,
+    pub iterable: Box<AllExpressions>,
+    pub body: BlockStatement,
+}
+
+impl Display for ForStatement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let variables = match &self.key {
+            Some(key) => format!("{}, {}", key, self.value),
+            None => self.value.to_string(),
+        };
+        let out = format!(
+            "for ({} in {}) {{\n {} }}",
+            variables, self.iterable, self.body
+        );
+        write!(f, "{}", out)
+    }
+}
+
+/// Exits the innermost enclosing loop
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub struct BreakStatement {
+    pub token: token::Token,
+}
+
+impl Display for BreakStatement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "break;")
+    }
+}
+
+/// Skips to the next iteration of the innermost enclosing loop
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub struct ContinueStatement {
+    pub token: token::Token,
+}
+
+impl Display for ContinueStatement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "continue;")
+    }
+}
@@ -2,7 +2,7 @@ use byteorder::ByteOrder;
 
 /// Reads an unsigned 8 bit value from the buffer and return a general usize.
 pub fn read_u8(buf: &[u8]) -> usize {
-    buf[0].try_into().unwrap()
+    buf[0].into()
 }
 
 /// Reads an unsigned 16 bit value from the buffer and return a general usize.
@@ -1,5 +1,6 @@
 pub mod helpers;
 
+use crate::lexer::token::Span;
 use anyhow::anyhow;
 use byteorder::{BigEndian, ByteOrder};
 
@@ -42,6 +43,22 @@ pub const OP_GET_BUILTIN: Opcode = 28;
 pub const OP_CLOSURE: Opcode = 29;
 pub const OP_GET_FREE: Opcode = 30;
 pub const OP_CURRENT_CLOSURE: Opcode = 31;
+pub const OP_SLICE: Opcode = 32;
+pub const OP_ASSIGN_LOCAL: Opcode = 33;
+pub const OP_ASSIGN_FREE: Opcode = 34;
+pub const OP_GET_ITER: Opcode = 35;
+pub const OP_ITER_NEXT: Opcode = 36;
+pub const OP_JUMP_NOT_TRUTHY_OR_POP: Opcode = 37;
+pub const OP_JUMP_TRUTHY_OR_POP: Opcode = 38;
+pub const OP_MOD: Opcode = 39;
+pub const OP_GREATER_THAN_OR_EQUAL: Opcode = 40;
+pub const OP_WIDE: Opcode = 41;
+pub const OP_LESS_THAN: Opcode = 42;
+pub const OP_LESS_THAN_OR_EQUAL: Opcode = 43;
+
+/// The largest operand of any instruction, as the operands that take a single byte take two when
+/// the instruction is prefixed with `OP_WIDE`
+pub const MAX_OPERAND: usize = u16::MAX as usize;
 
 /// An opcode definition for debugging and testing purposes
 pub struct Definition {
@@ -60,6 +77,15 @@ impl Definition {
             operand_widths: widths,
         }
     }
+
+    /// Returns the definition of the instruction when it's prefixed with `OP_WIDE`, where the
+    /// operands that take a single byte take two
+    pub fn widen(mut self) -> Self {
+        for width in self.operand_widths.iter_mut().filter(|w| **w == 1) {
+            *width = 2;
+        }
+        self
+    }
 }
 
 /// Return the definition based on the Opcode provided
@@ -92,22 +118,73 @@ pub fn lookup(op: Opcode) -> anyhow::Result<Definition> {
         OP_GET_LOCAL => Ok(Definition::new("OpGetLocal", vec![1])), // 256 local bindings
         OP_SET_LOCAL => Ok(Definition::new("OpSetLocal", vec![1])),
         OP_ASSIGN_GLOBAL => Ok(Definition::new("OpAssignGlobal", vec![2])),
+        OP_ASSIGN_LOCAL => Ok(Definition::new("OpAssignLocal", vec![1])),
+        OP_ASSIGN_FREE => Ok(Definition::new("OpAssignFree", vec![1])),
         OP_GET_BUILTIN => Ok(Definition::new("OpGetBuiltIn", vec![1])),
         OP_CLOSURE => Ok(Definition::new("OpClosure", vec![2, 1])), // constant_index_of_fn, num_free_vars
         OP_GET_FREE => Ok(Definition::new("OpGetFree", vec![1])),
         OP_CURRENT_CLOSURE => Ok(Definition::new("OpCurrentClosure", vec![])),
+        OP_SLICE => Ok(Definition::new("OpSlice", vec![])), // sliceable, start, end on the stack
+        OP_GET_ITER => Ok(Definition::new("OpGetIter", vec![])),
+        OP_ITER_NEXT => Ok(Definition::new("OpIterNext", vec![2, 1])), // exit_position, num_vars
+        OP_JUMP_NOT_TRUTHY_OR_POP => Ok(Definition::new("OpJumpNotTruthyOrPop", vec![2])),
+        OP_JUMP_TRUTHY_OR_POP => Ok(Definition::new("OpJumpTruthyOrPop", vec![2])),
+        OP_MOD => Ok(Definition::new("OpMod", vec![])),
+        OP_GREATER_THAN_OR_EQUAL => Ok(Definition::new("OpGreaterThanOrEqual", vec![])),
+        OP_WIDE => Ok(Definition::new("OpWide", vec![])), // prefix of the next instruction
+        OP_LESS_THAN => Ok(Definition::new("OpLessThan", vec![])),
+        OP_LESS_THAN_OR_EQUAL => Ok(Definition::new("OpLessThanOrEqual", vec![])),
         _ => Err(anyhow!("opcode must be defined")),
     }
 }
 
+/// Returns the opcode of the instruction that starts at the given offset, along with its
+/// definition and the offset of its operands. The opcode of an instruction that is prefixed with
+/// `OP_WIDE` is the one after the prefix, and its definition has the widened operands.
+pub fn lookup_instruction(
+    ins: &[u8],
+    offset: usize,
+) -> anyhow::Result<(Opcode, Definition, usize)> {
+    if ins[offset] != OP_WIDE {
+        return Ok((ins[offset], lookup(ins[offset])?, offset + 1));
+    }
+    match ins.get(offset + 1) {
+        Some(&op) if op != OP_WIDE => Ok((op, lookup(op)?.widen(), offset + 2)),
+        _ => Err(anyhow!("OpWide must be followed by another opcode")),
+    }
+}
+
+/// Returns whether the first operand of the opcode is the position of an instruction to jump to
+pub fn is_jump(op: Opcode) -> bool {
+    matches!(
+        op,
+        OP_JUMP
+            | OP_JUMP_NOT_TRUTHY
+            | OP_JUMP_NOT_TRUTHY_OR_POP
+            | OP_JUMP_TRUTHY_OR_POP
+            | OP_ITER_NEXT
+    )
+}
+
 /// Creates a single bytecode instruction with the `Opcode` at start,
 ///
-/// following the operands encoded, based on the width specified in the `Opcode` definition.
+/// following the operands encoded, based on the width specified in the `Opcode` definition. When
+/// a single byte operand doesn't fit in a byte, the instruction is prefixed with `OP_WIDE` and
+/// those operands take two bytes. Operands larger than `MAX_OPERAND` can't be encoded at all.
 pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
-    let Ok(def) = lookup(op) else {
+    let Ok(mut def) = lookup(op) else {
         return vec![];
     };
 
+    let wide = def
+        .operand_widths
+        .iter()
+        .zip(operands)
+        .any(|(width, o)| *width == 1 && *o > u8::MAX as usize);
+    if wide {
+        def = def.widen();
+    }
+
     let mut instruction_len = 1; // first byte is for the op_code
     for w in &def.operand_widths {
         instruction_len += w
@@ -127,14 +204,197 @@ pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
         offset += width;
     }
 
+    if wide {
+        instructions.insert(0, OP_WIDE);
+    }
     instructions
 }
 
+/// Returns a string representation of the instructions, without resolving constants and jumps
+/// like `ByteCode::disassemble` does.
+#[cfg(test)]
+pub fn instructions_to_string(ins: &Instructions) -> String {
+    let mut out = String::new();
+
+    let mut i = 0;
+    while i < ins.len() {
+        let (def, start) = match lookup_instruction(ins, i) {
+            Ok((_, def, start)) => (def, start),
+            Err(e) => {
+                out.push_str(format!("{:04} ERROR: {e}\n", i).as_str());
+                i += 1;
+                continue;
+            }
+        };
+
+        let (operands, read) = read_operands(&def, &ins[start..]);
+        let formatted_instruction = format_instruction(&def, &operands);
+
+        let prefix = if ins[i] == OP_WIDE { "OpWide " } else { "" };
+        out.push_str(format!("{:04} {}{}\n", i, prefix, formatted_instruction).as_str());
+        i = start + read;
+    }
+
+    out
+}
+
+/// Decodes operands based on the information provided by the definition and returns
+/// the operands and the number of bytes read.
+pub fn read_operands(def: &Definition, ins: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands = vec![0; def.operand_widths.len()];
+    let mut offset = 0;
+
+    for (i, width) in def.operand_widths.iter().enumerate() {
+        match width {
+            1 => operands[i] = helpers::read_u8(&ins[offset..]),
+            2 => operands[i] = helpers::read_u16(&ins[offset..]),
+            _ => {}
+        };
+        offset += width;
+    }
+
+    (operands, offset)
+}
+
+/// Return the formatted instruction along with the passed operands.
+///
+/// Return an error string if the operand count is different from the definition
+pub fn format_instruction(def: &Definition, operands: &[usize]) -> String {
+    let operand_count = def.operand_widths.len();
+    if operands.len() != operand_count {
+        return format!(
+            "ERROR: operand len {} does not match defined {}\n",
+            operands.len(),
+            operand_count
+        );
+    }
+
+    match operand_count {
+        0 => def.name.to_string(),
+        1 => format!("{} {}", def.name, operands[0]),
+        2 => format!("{} {} {}", def.name, operands[0], operands[1]),
+        _ => format!("ERROR: unhandled operandCount for {}\n", def.name),
+    }
+}
+
+/// Decodes the instructions into their offsets, opcodes and operands, stopping at the first
+/// undefined or incomplete instruction. The offset of an instruction that is prefixed with
+/// `OP_WIDE` is the offset of the prefix.
+pub fn decode(ins: &Instructions) -> Vec<(usize, Opcode, Vec<usize>)> {
+    let mut decoded = Vec::new();
+
+    let mut offset = 0;
+    while offset < ins.len() {
+        let Ok((opcode, def, start)) = lookup_instruction(ins, offset) else {
+            break;
+        };
+        if start + def.operand_widths.iter().sum::<usize>() > ins.len() {
+            break;
+        }
+
+        let (operands, read) = read_operands(&def, &ins[start..]);
+        decoded.push((offset, opcode, operands));
+        offset = start + read;
+    }
+
+    decoded
+}
+
+/// Returns the offset of the instruction that contains the byte at the given offset.
+pub fn instruction_start(instructions: &Instructions, offset: usize) -> usize {
+    let mut start = 0;
+    while start < instructions.len() {
+        let Ok((_, def, operands_start)) = lookup_instruction(instructions, start) else {
+            break;
+        };
+        let end = operands_start + def.operand_widths.iter().sum::<usize>();
+        if offset < end {
+            return start;
+        }
+        start = end;
+    }
+    offset
+}
+
+/// Maps instruction offsets to the source code they were compiled from.
+///
+/// Entries are sorted by offset and each entry covers all the instructions until the next one.
+///
+/// Identical function literals share a constant, which only has the name and the source map of
+/// the first one. The `OP_CLOSURE` instructions of the others are mapped to their own name and
+/// source map, so that runtime errors point to the function that was actually called.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SourceMap {
+    entries: Vec<(usize, Span)>,
+    functions: Vec<(usize, FunctionSite)>,
+}
+
+/// The name and the source map of the function created by an `OP_CLOSURE` instruction
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct FunctionSite {
+    pub name: String,
+    pub source_map: SourceMap,
+}
+
+impl SourceMap {
+    /// Records that the instruction at the given offset was compiled from the given span.
+    pub fn add(&mut self, offset: usize, span: Span) {
+        if let Some((last_offset, last_span)) = self.entries.last_mut() {
+            if *last_span == span {
+                return;
+            }
+            // a later instruction at the same offset replaces the earlier one
+            if *last_offset == offset {
+                *last_span = span;
+                return;
+            }
+        }
+        self.entries.push((offset, span));
+    }
+
+    /// Records the function that the `OP_CLOSURE` instruction at the given offset creates, when
+    /// it differs from the function in the constant pool.
+    pub fn add_function(&mut self, offset: usize, site: FunctionSite) {
+        self.functions.push((offset, site));
+    }
+
+    /// Removes the entries of the instructions that start at or after the given offset.
+    pub fn truncate(&mut self, offset: usize) {
+        self.entries.retain(|(o, _)| *o < offset);
+        self.functions.retain(|(o, _)| *o < offset);
+    }
+
+    /// Returns the offsets and the spans of all the entries.
+    pub fn entries(&self) -> &[(usize, Span)] {
+        &self.entries
+    }
+
+    /// Returns the offsets of the `OP_CLOSURE` instructions with their own functions, along with
+    /// those functions.
+    pub fn functions(&self) -> &[(usize, FunctionSite)] {
+        &self.functions
+    }
+
+    /// Returns the function created by the `OP_CLOSURE` instruction at the given offset, if it
+    /// differs from the function in the constant pool.
+    pub fn function_at(&self, offset: usize) -> Option<&FunctionSite> {
+        self.functions
+            .iter()
+            .find(|(o, _)| *o == offset)
+            .map(|(_, site)| site)
+    }
+
+    /// Returns the span of the instruction that covers the given offset.
+    pub fn lookup(&self, offset: usize) -> Option<Span> {
+        let index = self.entries.partition_point(|(o, _)| *o <= offset);
+        index.checked_sub(1).map(|i| self.entries[i].1)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::compiler::test_helpers::concat_instructions;
-    use test_helpers::*;
 
     #[test]
     fn test_make() {
@@ -148,6 +408,18 @@ mod tests {
                 vec![65532, 255],
                 vec![OP_CLOSURE, 255, 252, 255],
             ),
+            // single byte operands that don't fit take two bytes after the prefix
+            (OP_GET_LOCAL, vec![256], vec![OP_WIDE, OP_GET_LOCAL, 1, 0]),
+            (
+                OP_CLOSURE,
+                vec![2, 65535],
+                vec![OP_WIDE, OP_CLOSURE, 0, 2, 255, 255],
+            ),
+            (
+                OP_ITER_NEXT,
+                vec![65535, 300],
+                vec![OP_WIDE, OP_ITER_NEXT, 255, 255, 1, 44],
+            ),
         ];
 
         for tc in test_cases {
@@ -164,6 +436,8 @@ mod tests {
             make(OP_CONSTANT, &[2]),
             make(OP_CONSTANT, &[65535]),
             make(OP_CLOSURE, &[65535, 255]),
+            make(OP_CLOSURE, &[65535, 256]),
+            make(OP_CALL, &[1]),
         ];
 
         let expected = "0000 OpAdd
@@ -171,6 +445,8 @@ mod tests {
 0003 OpConstant 2
 0006 OpConstant 65535
 0009 OpClosure 65535 255
+0013 OpWide OpClosure 65535 256
+0019 OpCall 1
 ";
 
         let concatted = concat_instructions(instructions);
@@ -194,72 +470,53 @@ mod tests {
             }
         }
     }
-}
 
-#[cfg(test)]
-mod test_helpers {
-    use super::{helpers, lookup, Definition, Instructions};
-
-    /// Returns a string representation of the instructions
-    pub fn instructions_to_string(ins: &Instructions) -> String {
-        let mut out = String::new();
-
-        let mut i = 0;
-        while i < ins.len() {
-            let def = match lookup(ins[i]) {
-                Ok(v) => v,
-                Err(e) => {
-                    out.push_str(format!("ERROR: {e}\n").as_str());
-                    continue;
-                }
-            };
-
-            let (operands, read) = read_operands(&def, &ins[i + 1..]);
-            let formatted_instruction = format_instruction(&def, &operands);
-
-            out.push_str(format!("{:04} {}\n", i, formatted_instruction).as_str());
-            i += 1 + read;
-        }
+    #[test]
+    fn test_instruction_start() {
+        let instructions = concat_instructions(vec![
+            make(OP_ADD, &[]),
+            make(OP_CONSTANT, &[2]),
+            make(OP_CALL, &[1]),
+            make(OP_CALL, &[300]),
+            make(OP_POP, &[]),
+        ]);
 
-        out
+        // offset, expected start of the instruction
+        let test_cases = [
+            (0, 0),
+            (1, 1),
+            (2, 1),
+            (3, 1),
+            (4, 4),
+            (5, 4),
+            (6, 6),
+            (9, 6),
+            (10, 10),
+        ];
+        for tc in test_cases {
+            assert_eq!(instruction_start(&instructions, tc.0), tc.1);
+        }
     }
 
-    /// Decodes operands based on the information provided by the definition and returns
-    /// the operands and the number of bytes read.
-    pub fn read_operands(def: &Definition, ins: &[u8]) -> (Vec<usize>, usize) {
-        let mut operands = vec![0; def.operand_widths.len()];
-        let mut offset = 0;
-
-        for (i, width) in def.operand_widths.iter().enumerate() {
-            match width {
-                1 => operands[i] = helpers::read_u8(&ins[offset..]),
-                2 => operands[i] = helpers::read_u16(&ins[offset..]),
-                _ => {}
-            };
-            offset += width;
-        }
+    #[test]
+    fn test_source_map() {
+        let first = Span::new(1, 1, 0, 1);
+        let second = Span::new(1, 5, 4, 1);
 
-        (operands, offset)
-    }
+        let mut source_map = SourceMap::default();
+        assert_eq!(source_map.lookup(0), None);
 
-    /// Return the formatted instruction along with the passed operands.
-    ///
-    /// Return an error string if the operand count is different from the definition
-    pub fn format_instruction(def: &Definition, operands: &Vec<usize>) -> String {
-        let operand_count = def.operand_widths.len();
-        if operands.len() != operand_count {
-            return format!(
-                "ERROR: operand len {} does not match defined {}\n",
-                operands.len(),
-                operand_count
-            );
-        }
+        source_map.add(0, first);
+        source_map.add(3, first);
+        source_map.add(4, second);
+        source_map.add(6, first);
 
-        match operand_count {
-            0 => def.name.to_string(),
-            1 => format!("{} {}", def.name, operands[0]),
-            2 => format!("{} {} {}", def.name, operands[0], operands[1]),
-            _ => format!("ERROR: unhandled operandCount for {}\n", def.name),
-        }
+        assert_eq!(source_map.lookup(0), Some(first));
+        assert_eq!(source_map.lookup(3), Some(first));
+        assert_eq!(source_map.lookup(5), Some(second));
+        assert_eq!(source_map.lookup(100), Some(first));
+
+        source_map.truncate(6);
+        assert_eq!(source_map.lookup(100), Some(second));
     }
 }
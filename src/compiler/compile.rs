@@ -1,6 +1,7 @@
 use super::{
+    optimize::Checkpoint,
     symbol_table::{self, Symbol},
-    Compiler,
+    Compiler, LoopContext, Result,
 };
 use crate::{
     ast::{
@@ -9,21 +10,54 @@ use crate::{
         AllNodes,
     },
     code::*,
+    diagnostics::Diagnostic,
+    errors::CompileError,
+    lexer::token::TokenType,
     object::{
-        objects::{CompiledFunctionObj, Integer, StringObj},
+        objects::{BigInteger, CompiledFunctionObj, Float, FreeVariable, Integer, StringObj},
         AllObjects,
     },
 };
-use anyhow::{anyhow, Result};
+use std::rc::Rc;
 
 impl Compiler {
     /// Entrypoint for the compilation process. This method will be called
     /// iteratively by all branches.
     pub fn compile(&mut self, node: AllNodes) -> Result<()> {
+        // emitted instructions and errors are attributed to the innermost node being compiled
+        let enclosing_span = self.current_span;
+        let enclosing_depth = self.scopes[self.scope_index].stack_depth;
+        match &node {
+            AllNodes::Program(_) => {}
+            AllNodes::Statements(stmt) => self.current_span = stmt.span(),
+            AllNodes::Expressions(expr) => self.current_span = expr.span(),
+        }
+
+        let is_expression = matches!(node, AllNodes::Expressions(_));
+        self.compile_node(node)?;
+        self.current_span = enclosing_span;
+
+        // an expression leaves its value on the stack, while statements clean up after themselves
+        self.scopes[self.scope_index].stack_depth = enclosing_depth + usize::from(is_expression);
+        Ok(())
+    }
+
+    fn compile_node(&mut self, node: AllNodes) -> Result<()> {
         match node {
             AllNodes::Program(p) => {
+                let mut removed_last_loop = false;
                 for stmt in p.statements {
+                    let is_loop = matches!(stmt, AllStatements::While(_) | AllStatements::For(_));
+                    let position = self.current_instructions().len();
                     self.compile(AllNodes::Statements(stmt))?;
+                    removed_last_loop = is_loop && self.current_instructions().len() == position;
+                }
+
+                // a program that ends with a loop has no result, so a loop removed as dead code
+                // can't leave the pop of the statement before it as the last instruction
+                if removed_last_loop {
+                    self.emit(OP_NULL, &[])?;
+                    self.emit(OP_POP, &[])?;
                 }
             }
             AllNodes::Statements(stmt) => match stmt {
@@ -36,12 +70,17 @@ impl Compiler {
                 AllStatements::Expression(stmt) => self.compile_expression_statement(stmt)?,
                 AllStatements::Return(s) => {
                     self.compile(AllNodes::Expressions(*s.return_value))?;
-                    self.emit(OP_RETURN_VALUE, &[]);
+                    self.emit(OP_RETURN_VALUE, &[])?;
                 }
-                AllStatements::While(_) => unimplemented!(),
+                AllStatements::While(s) => self.compile_while_statement(s)?,
+                AllStatements::For(s) => self.compile_for_statement(s)?,
+                AllStatements::Break(_) => self.compile_break_statement()?,
+                AllStatements::Continue(_) => self.compile_continue_statement()?,
             },
             AllNodes::Expressions(expr) => match expr {
                 AllExpressions::IntegerLiteral(v) => self.compile_integer_literal(v)?,
+                AllExpressions::BigIntegerLiteral(v) => self.compile_big_integer_literal(v)?,
+                AllExpressions::FloatLiteral(v) => self.compile_float_literal(v)?,
                 AllExpressions::StringLiteral(v) => self.compile_string_literal(v)?,
                 AllExpressions::Boolean(v) => self.compile_boolean_literal(v)?,
                 AllExpressions::PrefixExpression(v) => self.compile_prefix_expression(v)?,
@@ -51,10 +90,13 @@ impl Compiler {
                 AllExpressions::HashLiteral(mut v) => self.compile_hash_literal(&mut v)?,
                 AllExpressions::Identifier(v) => self.compile_identifier(v)?,
                 AllExpressions::IndexExpression(v) => self.compile_index_expression(v)?,
+                AllExpressions::RangeExpression(v) => self.compile_range_expression(v)?,
                 AllExpressions::FunctionLiteral(v) => self.compile_function_literals(v)?,
                 AllExpressions::CallExpression(v) => self.compile_call_expressions(v)?,
                 AllExpressions::Assignment(v) => self.compile_assignment_expression(v)?,
-                _ => unimplemented!(),
+                AllExpressions::NullLiteral(_) => {
+                    self.emit(OP_NULL, &[])?;
+                }
             },
         }
         Ok(())
@@ -65,10 +107,13 @@ impl Compiler {
         stmt: statements::ExpressionStatement,
     ) -> Result<()> {
         let Some(expr) = stmt.expression else {
-            return Err(anyhow!("expression statement should contain an expression"));
+            return Err(self.error(CompileError::MissingExpression {
+                node: "expression statement",
+                part: "an expression",
+            }));
         };
         self.compile(AllNodes::Expressions(*expr))?;
-        self.emit(OP_POP, &[]);
+        self.emit(OP_POP, &[])?;
         Ok(())
     }
 
@@ -77,62 +122,263 @@ impl Compiler {
         self.compile(AllNodes::Expressions(*s.value))?;
 
         if symbol.scope == symbol_table::LOCAL_SCOPE {
-            self.emit(OP_SET_LOCAL, &[symbol.index]);
+            self.emit(OP_SET_LOCAL, &[symbol.index])?;
+        } else {
+            self.emit(OP_SET_GLOBAL, &[symbol.index])?;
+        }
+
+        Ok(())
+    }
+
+    fn compile_while_statement(&mut self, s: statements::WhileStatement) -> Result<()> {
+        let checkpoint = self.checkpoint();
+        let loop_start_pos = self.current_instructions().len();
+        self.compile(AllNodes::Expressions(*s.condition))?;
+        let constant_condition = self.constant_condition(&checkpoint);
+
+        // a loop that always runs doesn't check its condition, and can only be left with `break`
+        let jump_not_truthy_position = if constant_condition == Some(true) {
+            self.rollback(&checkpoint);
+            None
         } else {
-            self.emit(OP_SET_GLOBAL, &[symbol.index]);
+            // Emit an `OP_JUMP_NOT_TRUTHY` with a bogus value
+            let position = self.emit(OP_JUMP_NOT_TRUTHY, &[9999])?;
+            self.scopes[self.scope_index].stack_depth -= 1;
+            Some(position)
+        };
+
+        // every statement in the body cleans up after itself, so nothing is left on the stack
+        let depth = self.scopes[self.scope_index].stack_depth;
+        let context = LoopContext::new(loop_start_pos, depth, depth);
+        self.scopes[self.scope_index].loops.push(context);
+        self.compile(AllNodes::Statements(AllStatements::Block(s.body)))?;
+        self.emit(OP_JUMP, &[loop_start_pos])?;
+
+        let after_body_pos = self.current_instructions().len();
+        if let Some(position) = jump_not_truthy_position {
+            self.change_operand(position, after_body_pos)?;
+        }
+        self.patch_breaks(after_body_pos)?;
+
+        // a loop that never runs is only compiled to report its errors
+        if constant_condition == Some(false) {
+            self.rollback(&checkpoint);
+        }
+
+        Ok(())
+    }
+
+    /// Compiles a `for` loop, which keeps an iterator on the stack while the loop runs. Every
+    /// iteration pushes the next value (preceded by the key when there are two variables) and the
+    /// iterator is removed once it's exhausted.
+    fn compile_for_statement(&mut self, s: statements::ForStatement) -> Result<()> {
+        let depth = self.scopes[self.scope_index].stack_depth;
+        let iterable_span = s.iterable.span();
+        self.compile(AllNodes::Expressions(*s.iterable))?;
+        // objects that aren't iterable are reported at the iterable rather than the loop
+        let for_span = std::mem::replace(&mut self.current_span, iterable_span);
+        self.emit(OP_GET_ITER, &[])?;
+        self.current_span = for_span;
+
+        let num_vars = if s.key.is_some() { 2 } else { 1 };
+        let loop_start_pos = self.current_instructions().len();
+        // Emit an `OP_ITER_NEXT` with a bogus exit position
+        let iter_next_position = self.emit(OP_ITER_NEXT, &[9999, num_vars])?;
+
+        // the value is on top of the key
+        for variable in std::iter::once(s.value).chain(s.key) {
+            let symbol = self.symbol_table.define(&variable.value);
+            if symbol.scope == symbol_table::LOCAL_SCOPE {
+                self.emit(OP_SET_LOCAL, &[symbol.index])?;
+            } else {
+                self.emit(OP_SET_GLOBAL, &[symbol.index])?;
+            }
+        }
+
+        // `break` removes the iterator itself, which is what `OP_ITER_NEXT` does once it's exhausted
+        let context = LoopContext::new(loop_start_pos, depth, depth + 1);
+        self.scopes[self.scope_index].loops.push(context);
+        self.compile(AllNodes::Statements(AllStatements::Block(s.body)))?;
+        self.emit(OP_JUMP, &[loop_start_pos])?;
+
+        let after_body_pos = self.current_instructions().len();
+        self.check_operands(OP_ITER_NEXT, &[after_body_pos, num_vars])?;
+        let iter_next = make(OP_ITER_NEXT, &[after_body_pos, num_vars]);
+        self.replace_instruction(iter_next_position, iter_next);
+        self.patch_breaks(after_body_pos)?;
+
+        Ok(())
+    }
+
+    /// Compiles `break` as a jump to the end of the innermost loop, which is patched once the loop
+    /// is compiled. Values that are left on the stack by the enclosing expressions are dropped first.
+    fn compile_break_statement(&mut self) -> Result<()> {
+        let Some(context) = self.scopes[self.scope_index].loops.last() else {
+            return Err(self.loop_control_error("break"));
+        };
+        self.pop_values(context.break_depth)?;
+
+        // Emit an `OP_JUMP` with a bogus value
+        let jump_position = self.emit(OP_JUMP, &[9999])?;
+        let context = self.scopes[self.scope_index].loops.last_mut().unwrap();
+        context.breaks.push(jump_position);
+
+        Ok(())
+    }
+
+    /// Compiles `continue` as a jump back to the condition of a `while` loop, or to the next
+    /// iteration of a `for` loop.
+    fn compile_continue_statement(&mut self) -> Result<()> {
+        let Some(context) = self.scopes[self.scope_index].loops.last() else {
+            return Err(self.loop_control_error("continue"));
+        };
+        let loop_start_pos = context.start;
+        self.pop_values(context.continue_depth)?;
+
+        self.emit(OP_JUMP, &[loop_start_pos])?;
+        Ok(())
+    }
+
+    /// Emits pops until the stack is down to the given depth
+    fn pop_values(&mut self, depth: usize) -> Result<()> {
+        for _ in depth..self.scopes[self.scope_index].stack_depth {
+            self.emit(OP_POP, &[])?;
         }
+        Ok(())
+    }
 
+    /// Leaves the innermost loop and points its `break` jumps to the given position
+    fn patch_breaks(&mut self, position: usize) -> Result<()> {
+        let context = self.scopes[self.scope_index].loops.pop().unwrap();
+        for jump_position in context.breaks {
+            self.change_operand(jump_position, position)?;
+        }
         Ok(())
     }
 
+    /// Returns the error for a `break` or a `continue` outside of a loop, which is reported
+    /// differently when the loop is outside of the function being compiled
+    fn loop_control_error(&self, keyword: &'static str) -> Diagnostic<CompileError> {
+        let in_outer_loop = self.scopes.iter().any(|scope| !scope.loops.is_empty());
+        if in_outer_loop {
+            self.error(CompileError::LoopAcrossFunction { keyword })
+        } else {
+            self.error(CompileError::OutsideLoop { keyword })
+        }
+    }
+
     fn compile_identifier(&mut self, v: expressions::Identifier) -> Result<()> {
         let Some(symbol) = self.symbol_table.resolve(&v.value) else {
-            return Err(anyhow!("undefined variable {}", &v.value));
+            return Err(self.error(CompileError::UndefinedVariable { name: v.value }));
         };
-        self.load_symbol(symbol);
+        self.load_symbol(symbol)?;
         Ok(())
     }
 
     fn compile_infix_expression(&mut self, expr: expressions::InfixExpression) -> Result<()> {
         let Some(left) = expr.left else {
-            return Err(anyhow!("infix expression should contain a left expression"));
+            return Err(self.error(CompileError::MissingExpression {
+                node: "infix expression",
+                part: "a left expression",
+            }));
         };
         let Some(right) = expr.right else {
-            return Err(anyhow!("infix expression should contain a right expression"));
+            return Err(self.error(CompileError::MissingExpression {
+                node: "infix expression",
+                part: "a right expression",
+            }));
         };
 
-        if expr.operator == "<" {
-            self.compile(AllNodes::Expressions(*right))?;
-            self.compile(AllNodes::Expressions(*left))?;
-        } else {
-            self.compile(AllNodes::Expressions(*left))?;
-            self.compile(AllNodes::Expressions(*right))?;
+        let checkpoint = self.checkpoint();
+        let logical_jump = match expr.operator.as_str() {
+            "&&" => Some(OP_JUMP_NOT_TRUTHY_OR_POP),
+            "||" => Some(OP_JUMP_TRUTHY_OR_POP),
+            _ => None,
+        };
+        if let Some(jump_op) = logical_jump {
+            return self.compile_logical_expression(*left, *right, jump_op, checkpoint);
         }
 
-        match expr.operator.as_str() {
-            "+" => self.emit(OP_ADD, &[]),
-            "-" => self.emit(OP_SUB, &[]),
-            "*" => self.emit(OP_MUL, &[]),
-            "/" => self.emit(OP_DIV, &[]),
-            ">" | "<" => self.emit(OP_GREATER_THAN, &[]),
-            "==" => self.emit(OP_EQUAL, &[]),
-            "!=" => self.emit(OP_NOT_EQUAL, &[]),
-            v => return Err(anyhow!("unknown arithmetic operator: {v}")),
+        // operands are evaluated from left to right, so their side effects happen in that order
+        self.compile(AllNodes::Expressions(*left))?;
+        self.compile(AllNodes::Expressions(*right))?;
+
+        let op = match expr.operator.as_str() {
+            "+" => OP_ADD,
+            "-" => OP_SUB,
+            "*" => OP_MUL,
+            "/" => OP_DIV,
+            "%" => OP_MOD,
+            ">" => OP_GREATER_THAN,
+            ">=" => OP_GREATER_THAN_OR_EQUAL,
+            "<" => OP_LESS_THAN,
+            "<=" => OP_LESS_THAN_OR_EQUAL,
+            "==" => OP_EQUAL,
+            "!=" => OP_NOT_EQUAL,
+            v => {
+                let operator = v.to_string();
+                return Err(self.error(CompileError::UnknownInfixOperator { operator }));
+            }
         };
+        if !self.fold_operator(&checkpoint, op)? {
+            self.emit(op, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Compiles `&&` and `||`, where the right side is only evaluated if the left side doesn't
+    /// decide the result. The result is the deciding operand itself, rather than a boolean.
+    fn compile_logical_expression(
+        &mut self,
+        left: AllExpressions,
+        right: AllExpressions,
+        jump_op: Opcode,
+        checkpoint: Checkpoint,
+    ) -> Result<()> {
+        self.compile(AllNodes::Expressions(left))?;
+
+        // a constant left side is either the result or replaced by the right side
+        if let Some(truthy) = self.constant_condition(&checkpoint) {
+            if truthy == (jump_op == OP_JUMP_TRUTHY_OR_POP) {
+                return self.compile_dead_code(AllNodes::Expressions(right));
+            }
+            self.rollback(&checkpoint);
+            return self.compile(AllNodes::Expressions(right));
+        }
+
+        // Emit the jump with a bogus value, the left side is popped if the jump isn't taken
+        let jump_position = self.emit(jump_op, &[9999])?;
+        self.scopes[self.scope_index].stack_depth -= 1;
+
+        self.compile(AllNodes::Expressions(right))?;
+
+        let after_right_pos = self.current_instructions().len();
+        self.change_operand(jump_position, after_right_pos)?;
+
         Ok(())
     }
 
     fn compile_prefix_expression(&mut self, expr: expressions::PrefixExpression) -> Result<()> {
         let Some(right) = expr.right else {
-            return Err(anyhow!("prefix expression should contain a right expression"));
+            return Err(self.error(CompileError::MissingExpression {
+                node: "prefix expression",
+                part: "a right expression",
+            }));
         };
+        let checkpoint = self.checkpoint();
         self.compile(AllNodes::Expressions(*right))?;
 
-        match expr.operator.as_str() {
-            "-" => self.emit(OP_MINUS, &[]),
-            "!" => self.emit(OP_BANG, &[]),
-            v => return Err(anyhow!("unknown prefix expression: {v}")),
+        let op = match expr.operator.as_str() {
+            "-" => OP_MINUS,
+            "!" => OP_BANG,
+            v => {
+                let operator = v.to_string();
+                return Err(self.error(CompileError::UnknownPrefixOperator { operator }));
+            }
         };
+        if !self.fold_operator(&checkpoint, op)? {
+            self.emit(op, &[])?;
+        }
 
         Ok(())
     }
@@ -142,7 +388,7 @@ impl Compiler {
         for e in expr.elements {
             self.compile(AllNodes::Expressions(e))?;
         }
-        self.emit(OP_ARRAY, &[n_elements]);
+        self.emit(OP_ARRAY, &[n_elements])?;
         Ok(())
     }
 
@@ -157,14 +403,32 @@ impl Compiler {
             self.compile(AllNodes::Expressions(value))?;
         }
 
-        self.emit(OP_HASH, &[n_keys]);
+        self.emit(OP_HASH, &[n_keys])?;
         Ok(())
     }
 
     fn compile_index_expression(&mut self, expr: expressions::IndexExpression) -> Result<()> {
         self.compile(AllNodes::Expressions(*expr.left))?;
         self.compile(AllNodes::Expressions(*expr.index))?;
-        self.emit(OP_INDEX, &[]);
+        self.emit(OP_INDEX, &[])?;
+        Ok(())
+    }
+
+    /// Compiles `left[start:end]` by pushing the sliceable and both bounds. A missing bound is
+    /// pushed as null and defaults to the start or the end of the sliceable in the VM.
+    fn compile_range_expression(&mut self, expr: expressions::RangeExpression) -> Result<()> {
+        self.compile(AllNodes::Expressions(*expr.left))?;
+
+        for bound in [expr.left_index, expr.right_index] {
+            match bound {
+                Some(v) => self.compile(AllNodes::Expressions(*v))?,
+                None => {
+                    self.emit(OP_NULL, &[])?;
+                }
+            }
+        }
+
+        self.emit(OP_SLICE, &[])?;
         Ok(())
     }
 
@@ -190,93 +454,176 @@ impl Compiler {
 
         // empty function case
         if !self.last_instruction_is(OP_RETURN_VALUE) {
-            self.emit(OP_RETURN, &[]);
+            self.emit(OP_RETURN, &[])?;
         }
 
-        let free_symbols = self
+        // free symbols hold the original symbols, as seen from the enclosing scope
+        let free_variables = self
             .symbol_table
             .free_symbols
             .borrow()
             .iter()
-            .cloned()
-            .collect::<Vec<Symbol>>();
-        let num_free_symbols = free_symbols.len();
-
-        let fn_instructions = self.leave_scope();
+            .map(Self::free_variable)
+            .collect::<Vec<FreeVariable>>();
+        let num_free_symbols = free_variables.len();
+        let num_locals = self.symbol_table.num_definitions();
+
+        let (fn_instructions, source_map) = self.leave_scope();
+        let site = FunctionSite {
+            name: expr.name,
+            source_map,
+        };
 
-        for s in free_symbols {
-            self.load_symbol(s);
+        let mut compiled_fn = CompiledFunctionObj::new(fn_instructions, expr.parameters.len());
+        compiled_fn.num_locals = num_locals;
+        compiled_fn.free_variables = free_variables;
+        compiled_fn.source_map = site.source_map.clone();
+        compiled_fn.name = site.name.clone();
+
+        let constant_index = self.add_constant(AllObjects::CompiledFunction(compiled_fn));
+        let position = self.emit(OP_CLOSURE, &[constant_index, num_free_symbols])?;
+
+        // an identical function that was compiled before may have another name and locations
+        if let AllObjects::CompiledFunction(shared) = &self.constants[constant_index] {
+            if shared.name != site.name || shared.source_map != site.source_map {
+                self.scopes[self.scope_index]
+                    .source_map
+                    .add_function(position, site);
+            }
         }
 
-        let compiled_fn = AllObjects::CompiledFunction(CompiledFunctionObj::new(
-            fn_instructions,
-            expr.parameters.len(),
-        ));
-        let constant_index = self.add_constant(compiled_fn);
-        self.emit(OP_CLOSURE, &[constant_index, num_free_symbols]);
-
         Ok(())
     }
 
+    /// Compiles an if expression along with the `else if` branches that follow it. Every branch
+    /// jumps to the end of the whole chain, so the chain is compiled flat rather than nested.
     fn compile_if_expression(&mut self, expr: expressions::IfExpression) -> Result<()> {
-        self.compile(AllNodes::Expressions(*expr.condition))?;
-
-        // Emit an `OP_JUMP_NOT_TRUTHY` with a bogus value
-        let jump_not_truthy_position = self.emit(OP_JUMP_NOT_TRUTHY, &[9999]);
-
-        self.compile(AllNodes::Statements(AllStatements::Block(expr.consequence)))?;
-        if self.last_instruction_is(OP_POP) {
-            self.remove_last_pop();
-        }
+        let mut jump_positions = vec![];
+        let mut branch = expr;
+
+        loop {
+            let checkpoint = self.checkpoint();
+            self.compile(AllNodes::Expressions(*branch.condition))?;
+            let consequence = AllStatements::Block(branch.consequence);
+
+            match self.constant_condition(&checkpoint) {
+                // the branch is always taken, so the branches after it are dead
+                Some(true) => {
+                    self.rollback(&checkpoint);
+                    self.compile(AllNodes::Statements(consequence))?;
+                    self.produce_block_value()?;
+                    if let Some(alternative) = branch.alternative {
+                        let alternative = AllStatements::Block(alternative);
+                        self.compile_dead_code(AllNodes::Statements(alternative))?;
+                    }
+                    break;
+                }
+                Some(false) => {
+                    self.rollback(&checkpoint);
+                    self.compile_dead_code(AllNodes::Statements(consequence))?;
+                }
+                None => {
+                    // Emit an `OP_JUMP_NOT_TRUTHY` with a bogus value
+                    let jump_not_truthy_position = self.emit(OP_JUMP_NOT_TRUTHY, &[9999])?;
+                    self.scopes[self.scope_index].stack_depth -= 1;
 
-        // Emit an `OP_JUMP` with a bogus value
-        let jump_position = self.emit(OP_JUMP, &[9999]);
+                    self.compile(AllNodes::Statements(consequence))?;
+                    self.produce_block_value()?;
 
-        let after_consequence_pos = self.current_instructions().len();
-        self.change_operand(jump_not_truthy_position, after_consequence_pos);
+                    // Emit an `OP_JUMP` with a bogus value
+                    jump_positions.push(self.emit(OP_JUMP, &[9999])?);
 
-        if expr.alternative.is_none() {
-            self.emit(OP_NULL, &[]);
-        } else {
-            self.compile(AllNodes::Statements(AllStatements::Block(
-                expr.alternative.unwrap(),
-            )))?;
+                    let after_consequence_pos = self.current_instructions().len();
+                    self.change_operand(jump_not_truthy_position, after_consequence_pos)?;
+                }
+            }
 
-            if self.last_instruction_is(OP_POP) {
-                self.remove_last_pop();
+            let Some(alternative) = branch.alternative else {
+                self.emit(OP_NULL, &[])?;
+                break;
+            };
+            if alternative.token.token_type != TokenType::If {
+                self.compile(AllNodes::Statements(AllStatements::Block(alternative)))?;
+                self.produce_block_value()?;
+                break;
             }
+
+            // the alternative of an `else if` only holds the if expression of the next branch
+            let Some(AllStatements::Expression(stmt)) = alternative.statements.into_iter().next()
+            else {
+                unreachable!();
+            };
+            let Some(AllExpressions::IfExpression(nested)) = stmt.expression.map(|v| *v) else {
+                unreachable!();
+            };
+            self.current_span = nested.token.span;
+            branch = nested;
         }
 
         let after_alternative_pos = self.current_instructions().len();
-        self.change_operand(jump_position, after_alternative_pos);
+        for jump_position in jump_positions {
+            self.change_operand(jump_position, after_alternative_pos)?;
+        }
+
+        Ok(())
+    }
 
+    /// Makes sure a just compiled if-branch leaves exactly one value on the stack.
+    ///
+    /// The value of the last expression statement is kept by removing its pop, while a block that
+    /// is empty or ends with a statement (`let`, `while`) evaluates to null.
+    fn produce_block_value(&mut self) -> Result<()> {
+        if self.last_instruction_is(OP_POP) {
+            self.remove_last_pop();
+        } else {
+            self.emit(OP_NULL, &[])?;
+        }
         Ok(())
     }
 
-    fn change_operand(&mut self, op_pos: usize, operand: usize) {
+    /// Replaces the target of the jump at the given position, which was emitted with a bogus one
+    fn change_operand(&mut self, op_pos: usize, operand: usize) -> Result<()> {
         let op = self.current_instructions()[op_pos];
+        self.check_operands(op, &[operand])?;
         let new_instruction = make(op, &[operand]);
         self.replace_instruction(op_pos, new_instruction);
+        Ok(())
     }
 
     fn compile_integer_literal(&mut self, v: expressions::IntegerLiteral) -> Result<()> {
         let integer = AllObjects::Integer(Integer { value: v.value });
         let constant_index = self.add_constant(integer);
-        self.emit(OP_CONSTANT, &[constant_index]);
+        self.emit(OP_CONSTANT, &[constant_index])?;
+        Ok(())
+    }
+
+    fn compile_big_integer_literal(&mut self, v: expressions::BigIntegerLiteral) -> Result<()> {
+        let integer = AllObjects::BigInteger(BigInteger {
+            value: Rc::new(v.value),
+        });
+        let constant_index = self.add_constant(integer);
+        self.emit(OP_CONSTANT, &[constant_index])?;
+        Ok(())
+    }
+
+    fn compile_float_literal(&mut self, v: expressions::FloatLiteral) -> Result<()> {
+        let float = AllObjects::Float(Float { value: v.value });
+        let constant_index = self.add_constant(float);
+        self.emit(OP_CONSTANT, &[constant_index])?;
         Ok(())
     }
 
     fn compile_string_literal(&mut self, v: expressions::StringLiteral) -> Result<()> {
         let string_obj = AllObjects::StringObj(StringObj::new(&v.token.literal));
         let constant_index = self.add_constant(string_obj);
-        self.emit(OP_CONSTANT, &[constant_index]);
+        self.emit(OP_CONSTANT, &[constant_index])?;
         Ok(())
     }
 
     fn compile_boolean_literal(&mut self, v: expressions::Boolean) -> Result<()> {
         match v.value {
-            true => self.emit(OP_TRUE, &[]),
-            false => self.emit(OP_FALSE, &[]),
+            true => self.emit(OP_TRUE, &[])?,
+            false => self.emit(OP_FALSE, &[])?,
         };
         Ok(())
     }
@@ -289,7 +636,7 @@ impl Compiler {
             self.compile(AllNodes::Expressions(arg))?;
         }
 
-        self.emit(OP_CALL, &[num_args]);
+        self.emit(OP_CALL, &[num_args])?;
         Ok(())
     }
 
@@ -298,21 +645,46 @@ impl Compiler {
         v: expressions::AssignmentExpression,
     ) -> Result<()> {
         self.compile(AllNodes::Expressions(*v.value))?;
-        let Some(resolved )= self.symbol_table.resolve(&v.ident.value) else {
-             return Err(anyhow!("variable with name {}, not found",&v.ident.value));
+        let Some(resolved) = self.symbol_table.resolve(&v.ident.value) else {
+            let name = v.ident.value;
+            return Err(self.error(CompileError::AssignmentToUndeclared { name }));
+        };
+
+        match resolved.scope {
+            symbol_table::GLOBAL_SCOPE => self.emit(OP_ASSIGN_GLOBAL, &[resolved.index])?,
+            symbol_table::LOCAL_SCOPE => self.emit(OP_ASSIGN_LOCAL, &[resolved.index])?,
+            symbol_table::FREE_SCOPE => self.emit(OP_ASSIGN_FREE, &[resolved.index])?,
+            symbol_table::BUILTIN_SCOPE => {
+                let name = resolved.name;
+                return Err(self.error(CompileError::AssignmentToBuiltin { name }));
+            }
+            _ => {
+                let name = resolved.name;
+                return Err(self.error(CompileError::AssignmentToFunction { name }));
+            }
         };
-        self.emit(OP_ASSIGN_GLOBAL, &[resolved.index]);
         Ok(())
     }
 
-    fn load_symbol(&mut self, s: Symbol) {
+    /// Returns how a closure should capture the given symbol of the enclosing scope
+    fn free_variable(s: &Symbol) -> FreeVariable {
         match s.scope {
-            symbol_table::GLOBAL_SCOPE => self.emit(OP_GET_GLOBAL, &[s.index]),
-            symbol_table::LOCAL_SCOPE => self.emit(OP_GET_LOCAL, &[s.index]),
-            symbol_table::BUILTIN_SCOPE => self.emit(OP_GET_BUILTIN, &[s.index]),
-            symbol_table::FREE_SCOPE => self.emit(OP_GET_FREE, &[s.index]),
-            symbol_table::FUNCTION_SCOPE => self.emit(OP_CURRENT_CLOSURE, &[]),
+            symbol_table::LOCAL_SCOPE => FreeVariable::Local(s.index),
+            symbol_table::FREE_SCOPE => FreeVariable::Free(s.index),
+            symbol_table::FUNCTION_SCOPE => FreeVariable::CurrentClosure,
+            _ => unreachable!(), // globals and builtins are never captured
+        }
+    }
+
+    fn load_symbol(&mut self, s: Symbol) -> Result<()> {
+        match s.scope {
+            symbol_table::GLOBAL_SCOPE => self.emit(OP_GET_GLOBAL, &[s.index])?,
+            symbol_table::LOCAL_SCOPE => self.emit(OP_GET_LOCAL, &[s.index])?,
+            symbol_table::BUILTIN_SCOPE => self.emit(OP_GET_BUILTIN, &[s.index])?,
+            symbol_table::FREE_SCOPE => self.emit(OP_GET_FREE, &[s.index])?,
+            symbol_table::FUNCTION_SCOPE => self.emit(OP_CURRENT_CLOSURE, &[])?,
             _ => unreachable!(),
         };
+        Ok(())
     }
 }
@@ -0,0 +1,247 @@
+use super::ByteCode;
+use crate::{
+    code::{self, Instructions, SourceMap},
+    object::{builtins::BUILTIN_FUNCTIONS, AllObjects, Object},
+};
+use std::{collections::BTreeMap, fmt::Write};
+
+impl ByteCode {
+    /// Returns a human readable listing of the main instructions, followed by the instructions of
+    /// every compiled function in the constant pool.
+    ///
+    /// Functions are listed in the order they are first referenced, starting from the main
+    /// instructions. Constant operands are annotated with their values and jump targets are
+    /// resolved to labels, which are numbered per instruction stream.
+    pub fn disassemble(&self) -> String {
+        let mut out = String::from("== <main> ==\n");
+        disassemble_instructions(
+            &self.instructions,
+            &self.source_map,
+            &self.constants,
+            &mut out,
+        );
+
+        let mut functions = Vec::new();
+        collect_functions(&self.instructions, &self.constants, &mut functions);
+        // functions that are never referenced, like the ones left by earlier lines of the REPL
+        for (index, constant) in self.constants.iter().enumerate() {
+            if let AllObjects::CompiledFunction(func) = constant {
+                if !functions.contains(&index) {
+                    functions.push(index);
+                    collect_functions(&func.instructions, &self.constants, &mut functions);
+                }
+            }
+        }
+
+        for index in functions {
+            let AllObjects::CompiledFunction(func) = &self.constants[index] else {
+                continue;
+            };
+            let _ = writeln!(
+                out,
+                "\n== fn {} (constant {}, {} args, {} locals, {} free) ==",
+                function_name(&func.name),
+                index,
+                func.num_args,
+                func.num_locals,
+                func.free_variables.len()
+            );
+            disassemble_instructions(
+                &func.instructions,
+                &func.source_map,
+                &self.constants,
+                &mut out,
+            );
+        }
+
+        out
+    }
+}
+
+/// Appends the indexes of the functions that are created by the instructions, followed by the
+/// functions they create in turn, skipping the ones that were already collected.
+fn collect_functions(ins: &Instructions, constants: &[AllObjects], functions: &mut Vec<usize>) {
+    for (_, opcode, operands) in code::decode(ins) {
+        if opcode != code::OP_CLOSURE || functions.contains(&operands[0]) {
+            continue;
+        }
+        if let Some(AllObjects::CompiledFunction(func)) = constants.get(operands[0]) {
+            functions.push(operands[0]);
+            collect_functions(&func.instructions, constants, functions);
+        }
+    }
+}
+
+fn disassemble_instructions(
+    ins: &Instructions,
+    source_map: &SourceMap,
+    constants: &[AllObjects],
+    out: &mut String,
+) {
+    let decoded = code::decode(ins);
+
+    // every distinct jump target gets a label, numbered in the order of the targets
+    let mut labels = BTreeMap::new();
+    for (_, opcode, operands) in &decoded {
+        if code::is_jump(*opcode) {
+            labels.insert(operands[0], 0);
+        }
+    }
+    for (i, label) in labels.values_mut().enumerate() {
+        *label = i;
+    }
+
+    let mut end = 0;
+    for (offset, opcode, operands) in &decoded {
+        if let Some(label) = labels.get(offset) {
+            let _ = writeln!(out, "L{}:", label);
+        }
+
+        let (_, def, start) = code::lookup_instruction(ins, *offset).unwrap();
+        let mut instruction = code::format_instruction(&def, operands);
+        if ins[*offset] == code::OP_WIDE {
+            instruction.insert_str(0, "OpWide ");
+        }
+        // a function that shares its constant is named after its own literal
+        let annotation = match source_map.function_at(*offset) {
+            Some(site) => Some(format!("fn {}", function_name(&site.name))),
+            None => annotation(*opcode, operands, constants, &labels),
+        };
+        let _ = match annotation {
+            Some(v) => writeln!(out, "{:04} {:<24} ; {}", offset, instruction, v),
+            None => writeln!(out, "{:04} {}", offset, instruction),
+        };
+
+        end = start + def.operand_widths.iter().sum::<usize>();
+
+        // targets that aren't the start of an instruction, which only malformed bytecode has
+        for (target, label) in labels.range(offset + 1..end) {
+            let _ = writeln!(
+                out,
+                "{:04} ERROR: jump into the middle of an instruction ; L{}",
+                target, label
+            );
+        }
+    }
+
+    if end < ins.len() {
+        let _ = writeln!(out, "{:04} ERROR: invalid instruction", end);
+    } else if let Some(label) = labels.get(&ins.len()) {
+        // a jump past the last instruction, which happens for a trailing if or while
+        let _ = writeln!(out, "L{}:", label);
+    }
+}
+
+/// Describes what the operands of the instruction refer to, if it's not obvious from the numbers
+fn annotation(
+    opcode: code::Opcode,
+    operands: &[usize],
+    constants: &[AllObjects],
+    labels: &BTreeMap<usize, usize>,
+) -> Option<String> {
+    match opcode {
+        code::OP_CONSTANT | code::OP_CLOSURE => {
+            let constant = match constants.get(operands[0]) {
+                Some(AllObjects::CompiledFunction(v)) => format!("fn {}", function_name(&v.name)),
+                Some(v) => v.repr(),
+                None => "<missing constant>".to_string(),
+            };
+            Some(constant)
+        }
+        op if code::is_jump(op) => Some(format!("-> L{}", labels[&operands[0]])),
+        code::OP_GET_BUILTIN => BUILTIN_FUNCTIONS
+            .iter()
+            .find(|(index, _)| *index == operands[0])
+            .map(|(_, name)| name.to_string()),
+        _ => None,
+    }
+}
+
+fn function_name(name: &str) -> &str {
+    if name.is_empty() {
+        "<anonymous>"
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        code::{make, SourceMap, OP_GET_GLOBAL, OP_JUMP},
+        compiler::{test_helpers::parse, ByteCode, Compiler},
+    };
+
+    fn compile(input: &str) -> ByteCode {
+        let program = parse(input);
+        let mut comp = Compiler::new();
+        comp.compile(program.make_node()).unwrap();
+        comp.byte_code()
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let input = r#"
+            let greet = fn(name) {
+                let loud = fn() { len(name) };
+                if (loud() > 3) { "hi " + name } else { "hey" }
+            };
+            greet("monkey");
+            while (false) { 1 }
+        "#;
+
+        let expected = r#"== <main> ==
+0000 OpClosure 4 0            ; fn greet
+0004 OpSetGlobal 0
+0007 OpGetGlobal 0
+0010 OpConstant 5             ; "monkey"
+0013 OpCall 1
+0015 OpPop
+L0:
+0016 OpFalse
+0017 OpJumpNotTruthy 27       ; -> L1
+0020 OpConstant 6             ; 1
+0023 OpPop
+0024 OpJump 16                ; -> L0
+L1:
+
+== fn greet (constant 4, 1 args, 2 locals, 0 free) ==
+0000 OpClosure 0 1            ; fn loud
+0004 OpSetLocal 1
+0006 OpGetLocal 1
+0008 OpCall 0
+0010 OpConstant 1             ; 3
+0013 OpGreaterThan
+0014 OpJumpNotTruthy 26       ; -> L0
+0017 OpConstant 2             ; "hi "
+0020 OpGetLocal 0
+0022 OpAdd
+0023 OpJump 29                ; -> L1
+L0:
+0026 OpConstant 3             ; "hey"
+L1:
+0029 OpReturnValue
+
+== fn loud (constant 0, 0 args, 0 locals, 1 free) ==
+0000 OpGetBuiltIn 1           ; len
+0002 OpGetFree 0
+0004 OpCall 1
+0006 OpReturnValue
+"#;
+
+        assert_eq!(compile(input).disassemble(), expected);
+
+        // a jump into the operand of the instruction that follows it
+        let bytecode = ByteCode {
+            instructions: [make(OP_JUMP, &[5]), make(OP_GET_GLOBAL, &[1])].concat(),
+            constants: vec![],
+            source_map: SourceMap::default(),
+        };
+        let expected = "== <main> ==
+0000 OpJump 5                 ; -> L0
+0003 OpGetGlobal 1
+0005 ERROR: jump into the middle of an instruction ; L0
+";
+        assert_eq!(bytecode.disassemble(), expected);
+    }
+}
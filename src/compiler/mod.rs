@@ -1,14 +1,25 @@
 mod compile;
+mod disassemble;
+mod optimize;
+mod peephole;
+mod serialize;
 mod symbol_table;
 
 use crate::{
-    code::{self, make, Instructions, Opcode},
-    object::AllObjects,
+    code::{self, make, Instructions, Opcode, SourceMap},
+    diagnostics::Diagnostic,
+    errors::CompileError,
+    lexer::token::Span,
+    object::{bigint::BigInt, objects::FreeVariable, AllObjects},
 };
-use std::rc::Rc;
+use std::{collections::HashMap, rc::Rc};
 
+pub use self::serialize::MAGIC;
 pub use self::symbol_table::SymbolTable;
 
+/// Result of the compilation steps, with errors pointing to the node that caused them
+type Result<T> = std::result::Result<T, Diagnostic<CompileError>>;
+
 #[derive(Default, Clone)]
 struct EmittedInstruction {
     opcode: Opcode,
@@ -21,10 +32,51 @@ impl EmittedInstruction {
     }
 }
 
+/// Identifies the constants that are stored only once in the constant pool.
+///
+/// Functions are the same constant when their code is identical, whatever their names and
+/// locations are. Those are kept in the source map of the enclosing function instead, see
+/// [`SourceMap`]. Floats are compared by their bits, as `0.0` and `-0.0` are equal but print
+/// differently.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Integer(i64),
+    BigInteger(Rc<BigInt>),
+    Float(u64),
+    String(Rc<String>),
+    Function {
+        instructions: Instructions,
+        num_args: usize,
+        num_locals: usize,
+        free_variables: Vec<FreeVariable>,
+    },
+}
+
+impl ConstantKey {
+    fn new(obj: &AllObjects) -> Option<Self> {
+        match obj {
+            AllObjects::Integer(v) => Some(Self::Integer(v.value)),
+            AllObjects::BigInteger(v) => Some(Self::BigInteger(v.value.clone())),
+            AllObjects::Float(v) => Some(Self::Float(v.value.to_bits())),
+            AllObjects::StringObj(v) => Some(Self::String(v.value.clone())),
+            AllObjects::CompiledFunction(func) => Some(Self::Function {
+                instructions: func.instructions.clone(),
+                num_args: func.num_args,
+                num_locals: func.num_locals,
+                free_variables: func.free_variables.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
 pub struct Compiler {
     /// constants is a slice that serves as our constant pool.
     pub constants: Vec<AllObjects>,
 
+    /// indexes of the interned constants in the pool
+    constant_indexes: HashMap<ConstantKey, usize>,
+
     /// symbol table for all scopes
     pub symbol_table: Rc<SymbolTable>,
 
@@ -33,6 +85,13 @@ pub struct Compiler {
 
     /// current active scope index
     scope_index: usize,
+
+    /// span of the node that is being compiled
+    current_span: Span,
+
+    /// constant expressions are folded and dead branches are removed from level 1 onwards, and the
+    /// emitted instructions go through the peephole pass from level 2 onwards
+    pub optimization_level: u8,
 }
 
 impl Compiler {
@@ -41,40 +100,89 @@ impl Compiler {
         let main_scope = CompilationScope::default();
         Self {
             constants: vec![],
+            constant_indexes: HashMap::new(),
             symbol_table: Rc::new(SymbolTable::new()),
             scopes: vec![main_scope],
             scope_index: 0,
+            current_span: Span::default(),
+            optimization_level: 0,
         }
     }
 
-    /// Creates a new compiler with the given state (for the REPL)
+    /// Creates a new compiler with the given state (for the REPL). The constants of the earlier
+    /// lines are reused by the new line like its own.
     pub fn new_with_state(symbol_table: Rc<SymbolTable>, constants: Vec<AllObjects>) -> Self {
         let main_scope = CompilationScope::default();
+        let mut constant_indexes = HashMap::new();
+        for (index, constant) in constants.iter().enumerate() {
+            if let Some(key) = ConstantKey::new(constant) {
+                constant_indexes.entry(key).or_insert(index);
+            }
+        }
+
         Self {
             constants,
+            constant_indexes,
             symbol_table,
             scopes: vec![main_scope],
             scope_index: 0,
+            current_span: Span::default(),
+            optimization_level: 0,
         }
     }
 
     /// Emits the byte-code instructions after compilation has finished.
     pub fn byte_code(mut self) -> ByteCode {
+        let scope = self.scopes.pop().unwrap();
+        let (instructions, source_map) = self.finish_scope(scope);
         ByteCode {
-            instructions: self.current_instructions().clone(),
+            instructions,
             constants: self.constants,
+            source_map,
         }
     }
 
     /// Generates an instruction and add it to the current scope and updates the last instruction.
-    /// the position of the just-emitted instruction will be returned.
-    fn emit(&mut self, op: Opcode, operands: &[usize]) -> usize {
+    /// the position of the just-emitted instruction will be returned, or an error if one of the
+    /// operands is too large to be encoded.
+    fn emit(&mut self, op: Opcode, operands: &[usize]) -> Result<usize> {
+        self.check_operands(op, operands)?;
         let instructions = make(op, operands);
         let pos_new_instruction = self.current_instructions().len();
         self.current_instructions().extend_from_slice(&instructions);
+        self.scopes[self.scope_index]
+            .source_map
+            .add(pos_new_instruction, self.current_span);
 
         self.set_last_instruction(op, pos_new_instruction);
-        pos_new_instruction
+        Ok(pos_new_instruction)
+    }
+
+    /// Returns an error for the first operand that is larger than any operand can be, naming what
+    /// the operand stands for in the instruction
+    fn check_operands(&self, op: Opcode, operands: &[usize]) -> Result<()> {
+        let Some(i) = operands.iter().position(|o| *o > code::MAX_OPERAND) else {
+            return Ok(());
+        };
+        let operand = match (op, i) {
+            (code::OP_CONSTANT, _) | (code::OP_CLOSURE, 0) => "constant index",
+            (code::OP_CLOSURE, _) => "number of free variables",
+            (code::OP_GET_GLOBAL | code::OP_SET_GLOBAL | code::OP_ASSIGN_GLOBAL, _) => {
+                "global index"
+            }
+            (code::OP_GET_LOCAL | code::OP_SET_LOCAL | code::OP_ASSIGN_LOCAL, _) => "local index",
+            (code::OP_GET_FREE | code::OP_ASSIGN_FREE, _) => "free variable index",
+            (code::OP_ARRAY, _) => "number of array elements",
+            (code::OP_HASH, _) => "number of hash map entries",
+            (code::OP_CALL, _) => "number of arguments",
+            (op, 0) if code::is_jump(op) => "jump target",
+            _ => "operand",
+        };
+
+        Err(self.error(CompileError::OperandOverflow {
+            operand,
+            value: operands[i],
+        }))
     }
 
     /// Create a new scope and make it active
@@ -85,12 +193,24 @@ impl Compiler {
         self.scope_index += 1;
     }
 
-    /// Remove the last created scope and make the second-to-last one active
-    fn leave_scope(&mut self) -> Instructions {
+    /// Remove the last created scope and make the second-to-last one active.
+    ///
+    /// The instructions of the removed scope are returned along with their source map.
+    fn leave_scope(&mut self) -> (Instructions, SourceMap) {
         let s = self.scopes.pop().unwrap(); // will always have at least one scope
         self.symbol_table = self.symbol_table.outer.as_ref().unwrap().clone(); // all functions will have a global scope
         self.scope_index -= 1;
-        s.instructions
+        self.finish_scope(s)
+    }
+
+    /// Returns the instructions of a scope that is done along with their source map, which go
+    /// through the peephole pass from optimization level 2 onwards
+    fn finish_scope(&self, scope: CompilationScope) -> (Instructions, SourceMap) {
+        if self.optimization_level > 1 {
+            peephole::optimize(&scope.instructions, &scope.source_map)
+        } else {
+            (scope.instructions, scope.source_map)
+        }
     }
 
     /// Set the last instruction and the last-to-previous instruction
@@ -111,13 +231,36 @@ impl Compiler {
         let new = &old[..last.position];
 
         self.scopes[self.scope_index].instructions = new.to_vec();
+        self.scopes[self.scope_index]
+            .source_map
+            .truncate(last.position);
         self.scopes[self.scope_index].last_instruction = previous;
     }
 
-    /// Add the given constant to the constant pool and return it's index position.
+    /// Add the given constant to the constant pool and return it's index position. A constant that
+    /// is already in the pool is not added again.
     fn add_constant(&mut self, obj: AllObjects) -> usize {
+        let key = ConstantKey::new(&obj);
+        if let Some(index) = key.as_ref().and_then(|k| self.constant_indexes.get(k)) {
+            return *index;
+        }
+
         self.constants.push(obj);
-        self.constants.len() - 1
+        let index = self.constants.len() - 1;
+        if let Some(key) = key {
+            self.constant_indexes.insert(key, index);
+        }
+        index
+    }
+
+    /// Removes the constants from the given index onwards
+    fn truncate_constants(&mut self, len: usize) {
+        for constant in self.constants.drain(len..) {
+            if let Some(key) = ConstantKey::new(&constant) {
+                // an interned constant is only ever added once
+                self.constant_indexes.remove(&key);
+            }
+        }
     }
 
     /// Return the instruction set of the current active scope
@@ -133,6 +276,11 @@ impl Compiler {
         self.scopes[self.scope_index].last_instruction.opcode == op
     }
 
+    /// Returns an error that points to the node being compiled
+    fn error(&self, error: CompileError) -> Diagnostic<CompileError> {
+        Diagnostic::new(error, self.current_span)
+    }
+
     /// Replace the current instruction slice with the given instruction slice starting from the given position
     fn replace_instruction(&mut self, position: usize, ins: Instructions) {
         let current_ins = self.current_instructions();
@@ -144,13 +292,48 @@ impl Compiler {
 pub struct ByteCode {
     pub instructions: code::Instructions,
     pub constants: Vec<AllObjects>,
+    pub source_map: SourceMap,
 }
 
 #[derive(Default)]
 struct CompilationScope {
     instructions: code::Instructions,
+    source_map: SourceMap,
     last_instruction: EmittedInstruction,
     previous_instruction: EmittedInstruction,
+
+    /// number of values that the instructions emitted so far leave on the stack, which are the
+    /// operands of the expressions that are still being compiled
+    stack_depth: usize,
+
+    /// the loops that enclose the node being compiled, innermost last
+    loops: Vec<LoopContext>,
+}
+
+/// The jump targets of a loop, which `break` and `continue` statements are compiled against
+struct LoopContext {
+    /// position that `continue` jumps back to
+    start: usize,
+
+    /// positions of the jumps emitted for `break`, which are patched once the end of the loop is known
+    breaks: Vec<usize>,
+
+    /// stack depth outside of the loop, which `break` drops the stack down to
+    break_depth: usize,
+
+    /// stack depth at the start of every iteration, which `continue` drops the stack down to
+    continue_depth: usize,
+}
+
+impl LoopContext {
+    fn new(start: usize, break_depth: usize, continue_depth: usize) -> Self {
+        Self {
+            start,
+            breaks: vec![],
+            break_depth,
+            continue_depth,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -158,6 +341,9 @@ mod tests {
     use super::code::*;
     use super::test_helpers::*;
     use super::Compiler;
+    use crate::errors::CompileError;
+    use crate::lexer::token::Span;
+    use crate::object::{objects::FreeVariable, AllObjects, Object};
 
     #[test]
     fn test_integer_arithmetic() {
@@ -194,6 +380,16 @@ mod tests {
                     make(OP_POP, &[]),
                 ],
             ),
+            (
+                "7 % 3",
+                vec![Int(7), Int(3)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_MOD, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
             (
                 "7 * 8",
                 vec![Int(7), Int(8)],
@@ -228,6 +424,35 @@ mod tests {
         run_compiler_tests(test_cases);
     }
 
+    #[test]
+    fn test_float_arithmetic() {
+        use Literal::{Float, Int};
+
+        let test_cases = vec![
+            (
+                "1.5 * 2",
+                vec![Float(1.5), Int(2)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_MUL, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            (
+                "-1e-3",
+                vec![Float(0.001)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_MINUS, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(test_cases);
+    }
+
     #[test]
     fn test_boolean_expressions() {
         use Literal::Int;
@@ -250,11 +475,31 @@ mod tests {
             ),
             (
                 "1 < 2",
-                vec![Int(2), Int(1)],
+                vec![Int(1), Int(2)],
                 vec![
                     make(OP_CONSTANT, &[0]),
                     make(OP_CONSTANT, &[1]),
-                    make(OP_GREATER_THAN, &[]),
+                    make(OP_LESS_THAN, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            (
+                "1 >= 2",
+                vec![Int(1), Int(2)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_GREATER_THAN_OR_EQUAL, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            (
+                "1 <= 2",
+                vec![Int(1), Int(2)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_LESS_THAN_OR_EQUAL, &[]),
                     make(OP_POP, &[]),
                 ],
             ),
@@ -307,6 +552,41 @@ mod tests {
         run_compiler_tests(test_cases);
     }
 
+    #[test]
+    fn test_logical_expressions() {
+        use Literal::Int;
+
+        let test_cases: Vec<CompilerTestCase> = vec![
+            (
+                "true && false; 1 || 2",
+                vec![Int(1), Int(2)],
+                vec![
+                    make(OP_TRUE, &[]),                    // 0000
+                    make(OP_JUMP_NOT_TRUTHY_OR_POP, &[5]), // 0001
+                    make(OP_FALSE, &[]),                   // 0004
+                    make(OP_POP, &[]),                     // 0005
+                    make(OP_CONSTANT, &[0]),               // 0006
+                    make(OP_JUMP_TRUTHY_OR_POP, &[15]),    // 0009
+                    make(OP_CONSTANT, &[1]),               // 0012
+                    make(OP_POP, &[]),                     // 0015
+                ],
+            ),
+            (
+                "true || false && true",
+                vec![],
+                vec![
+                    make(OP_TRUE, &[]),                    // 0000
+                    make(OP_JUMP_TRUTHY_OR_POP, &[9]),     // 0001
+                    make(OP_FALSE, &[]),                   // 0004
+                    make(OP_JUMP_NOT_TRUTHY_OR_POP, &[9]), // 0005
+                    make(OP_TRUE, &[]),                    // 0008
+                    make(OP_POP, &[]),                     // 0009
+                ],
+            ),
+        ];
+        run_compiler_tests(test_cases);
+    }
+
     #[test]
     fn test_conditionals() {
         use Literal::Int;
@@ -340,10 +620,239 @@ mod tests {
                     make(OP_POP, &[]),               // 0017
                 ],
             ),
+            (
+                "if (true) { let x = 1; }",
+                vec![Int(1)],
+                vec![
+                    make(OP_TRUE, &[]),              // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[14]), // 0001
+                    make(OP_CONSTANT, &[0]),         // 0004
+                    make(OP_SET_GLOBAL, &[0]),       // 0007
+                    make(OP_NULL, &[]),              // 0010
+                    make(OP_JUMP, &[15]),            // 0011
+                    make(OP_NULL, &[]),              // 0014
+                    make(OP_POP, &[]),               // 0015
+                ],
+            ),
+            // every branch of an else-if chain jumps straight to the end
+            (
+                "if (true) { 10 } else if (false) { 20 } else { 30 }; 3333;",
+                vec![Int(10), Int(20), Int(30), Int(3333)],
+                vec![
+                    make(OP_TRUE, &[]),              // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[10]), // 0001
+                    make(OP_CONSTANT, &[0]),         // 0004
+                    make(OP_JUMP, &[23]),            // 0007
+                    make(OP_FALSE, &[]),             // 0010
+                    make(OP_JUMP_NOT_TRUTHY, &[20]), // 0011
+                    make(OP_CONSTANT, &[1]),         // 0014
+                    make(OP_JUMP, &[23]),            // 0017
+                    make(OP_CONSTANT, &[2]),         // 0020
+                    make(OP_POP, &[]),               // 0023
+                    make(OP_CONSTANT, &[3]),         // 0024
+                    make(OP_POP, &[]),               // 0027
+                ],
+            ),
+            (
+                "if (false) { 10 } else if (true) { 20 }",
+                vec![Int(10), Int(20)],
+                vec![
+                    make(OP_FALSE, &[]),             // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[10]), // 0001
+                    make(OP_CONSTANT, &[0]),         // 0004
+                    make(OP_JUMP, &[21]),            // 0007
+                    make(OP_TRUE, &[]),              // 0010
+                    make(OP_JUMP_NOT_TRUTHY, &[20]), // 0011
+                    make(OP_CONSTANT, &[1]),         // 0014
+                    make(OP_JUMP, &[21]),            // 0017
+                    make(OP_NULL, &[]),              // 0020
+                    make(OP_POP, &[]),               // 0021
+                ],
+            ),
+        ];
+        run_compiler_tests(test_cases);
+    }
+
+    #[test]
+    fn test_while_statements() {
+        use Literal::{Ins, Int};
+
+        let test_cases: Vec<CompilerTestCase> = vec![
+            (
+                "while (true) { 10; } 3333;",
+                vec![Int(10), Int(3333)],
+                vec![
+                    make(OP_TRUE, &[]),              // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[11]), // 0001
+                    make(OP_CONSTANT, &[0]),         // 0004
+                    make(OP_POP, &[]),               // 0007
+                    make(OP_JUMP, &[0]),             // 0008
+                    make(OP_CONSTANT, &[1]),         // 0011
+                    make(OP_POP, &[]),               // 0014
+                ],
+            ),
+            (
+                "let i = 0; while (i < 2) { i = i + 1; }",
+                vec![Int(0), Int(2), Int(1)],
+                vec![
+                    make(OP_CONSTANT, &[0]),         // 0000
+                    make(OP_SET_GLOBAL, &[0]),       // 0003
+                    make(OP_GET_GLOBAL, &[0]),       // 0006
+                    make(OP_CONSTANT, &[1]),         // 0009
+                    make(OP_LESS_THAN, &[]),         // 0012
+                    make(OP_JUMP_NOT_TRUTHY, &[30]), // 0013
+                    make(OP_GET_GLOBAL, &[0]),       // 0016
+                    make(OP_CONSTANT, &[2]),         // 0019
+                    make(OP_ADD, &[]),               // 0022
+                    make(OP_ASSIGN_GLOBAL, &[0]),    // 0023
+                    make(OP_POP, &[]),               // 0026
+                    make(OP_JUMP, &[6]),             // 0027
+                ],
+            ),
+            (
+                "fn() { while (false) { 1; } }",
+                vec![
+                    Int(1),
+                    Ins(vec![
+                        make(OP_FALSE, &[]),             // 0000
+                        make(OP_JUMP_NOT_TRUTHY, &[11]), // 0001
+                        make(OP_CONSTANT, &[0]),         // 0004
+                        make(OP_POP, &[]),               // 0007
+                        make(OP_JUMP, &[0]),             // 0008
+                        make(OP_RETURN, &[]),            // 0011
+                    ]),
+                ],
+                vec![make(OP_CLOSURE, &[1, 0]), make(OP_POP, &[])],
+            ),
+        ];
+        run_compiler_tests(test_cases);
+    }
+
+    #[test]
+    fn test_for_statements() {
+        use Literal::{Ins, Int};
+
+        let test_cases: Vec<CompilerTestCase> = vec![
+            (
+                "for (x in [1]) { x; }",
+                vec![Int(1)],
+                vec![
+                    make(OP_CONSTANT, &[0]),      // 0000
+                    make(OP_ARRAY, &[1]),         // 0003
+                    make(OP_GET_ITER, &[]),       // 0006
+                    make(OP_ITER_NEXT, &[21, 1]), // 0007
+                    make(OP_SET_GLOBAL, &[0]),    // 0011
+                    make(OP_GET_GLOBAL, &[0]),    // 0014
+                    make(OP_POP, &[]),            // 0017
+                    make(OP_JUMP, &[7]),          // 0018
+                ],
+            ),
+            (
+                "fn(m) { for (k, v in m) { v; } }",
+                vec![Ins(vec![
+                    make(OP_GET_LOCAL, &[0]),     // 0000
+                    make(OP_GET_ITER, &[]),       // 0002
+                    make(OP_ITER_NEXT, &[17, 2]), // 0003
+                    make(OP_SET_LOCAL, &[1]),     // 0007
+                    make(OP_SET_LOCAL, &[2]),     // 0009
+                    make(OP_GET_LOCAL, &[1]),     // 0011
+                    make(OP_POP, &[]),            // 0013
+                    make(OP_JUMP, &[3]),          // 0014
+                    make(OP_RETURN, &[]),         // 0017
+                ])],
+                vec![make(OP_CLOSURE, &[0, 0]), make(OP_POP, &[])],
+            ),
+        ];
+        run_compiler_tests(test_cases);
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        use Literal::Int;
+
+        let test_cases: Vec<CompilerTestCase> = vec![
+            (
+                "while (true) { break; continue; }",
+                vec![],
+                vec![
+                    make(OP_TRUE, &[]),              // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[13]), // 0001
+                    make(OP_JUMP, &[13]),            // 0004
+                    make(OP_JUMP, &[0]),             // 0007
+                    make(OP_JUMP, &[0]),             // 0010
+                ],
+            ),
+            // `break` drops the iterator, while `continue` keeps it for the next iteration
+            (
+                "for (x in []) { if (x) { continue; } break; }",
+                vec![],
+                vec![
+                    make(OP_ARRAY, &[0]),            // 0000
+                    make(OP_GET_ITER, &[]),          // 0003
+                    make(OP_ITER_NEXT, &[33, 1]),    // 0004
+                    make(OP_SET_GLOBAL, &[0]),       // 0008
+                    make(OP_GET_GLOBAL, &[0]),       // 0011
+                    make(OP_JUMP_NOT_TRUTHY, &[24]), // 0014
+                    make(OP_JUMP, &[4]),             // 0017
+                    make(OP_NULL, &[]),              // 0020
+                    make(OP_JUMP, &[25]),            // 0021
+                    make(OP_NULL, &[]),              // 0024
+                    make(OP_POP, &[]),               // 0025
+                    make(OP_POP, &[]),               // 0026
+                    make(OP_JUMP, &[33]),            // 0027
+                    make(OP_JUMP, &[4]),             // 0030
+                ],
+            ),
+            // the operands of the enclosing expressions are dropped before leaving the loop
+            (
+                "while (true) { [1, if (true) { break; } else { 2 }]; }",
+                vec![Int(1), Int(2)],
+                vec![
+                    make(OP_TRUE, &[]),              // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[29]), // 0001
+                    make(OP_CONSTANT, &[0]),         // 0004
+                    make(OP_TRUE, &[]),              // 0007
+                    make(OP_JUMP_NOT_TRUTHY, &[19]), // 0008
+                    make(OP_POP, &[]),               // 0011
+                    make(OP_JUMP, &[29]),            // 0012
+                    make(OP_NULL, &[]),              // 0015
+                    make(OP_JUMP, &[22]),            // 0016
+                    make(OP_CONSTANT, &[1]),         // 0019
+                    make(OP_ARRAY, &[2]),            // 0022
+                    make(OP_POP, &[]),               // 0025
+                    make(OP_JUMP, &[0]),             // 0026
+                ],
+            ),
         ];
         run_compiler_tests(test_cases);
     }
 
+    #[test]
+    fn test_invalid_loop_control() {
+        // input, expected error
+        let test_cases = [
+            ("break;", "break outside of a loop"),
+            ("if (true) { continue; }", "continue outside of a loop"),
+            (
+                "while (true) { fn() { break; } }",
+                "break cannot cross a function boundary",
+            ),
+            (
+                "for (x in []) { let f = fn() { continue; }; }",
+                "continue cannot cross a function boundary",
+            ),
+        ];
+
+        for tc in test_cases {
+            let program = parse(tc.0);
+            let mut compiler = Compiler::new();
+            match compiler.compile(program.make_node()) {
+                Ok(_) => panic!("expected a compiler error for {}", tc.0),
+                Err(e) => assert_eq!(e.to_string(), tc.1),
+            }
+        }
+    }
+
     #[test]
     fn test_global_let_statements() {
         use Literal::Int;
@@ -498,14 +1007,14 @@ mod tests {
         let test_cases: Vec<CompilerTestCase> = vec![
             (
                 "[1, 2, 3][1 + 1]",
-                vec![Int(1), Int(2), Int(3), Int(1), Int(1)],
+                vec![Int(1), Int(2), Int(3)],
                 vec![
                     make(OP_CONSTANT, &[0]),
                     make(OP_CONSTANT, &[1]),
                     make(OP_CONSTANT, &[2]),
                     make(OP_ARRAY, &[3]),
-                    make(OP_CONSTANT, &[3]),
-                    make(OP_CONSTANT, &[4]),
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_CONSTANT, &[0]),
                     make(OP_ADD, &[]),
                     make(OP_INDEX, &[]),
                     make(OP_POP, &[]),
@@ -513,13 +1022,13 @@ mod tests {
             ),
             (
                 "{1: 2}[2 - 1]",
-                vec![Int(1), Int(2), Int(2), Int(1)],
+                vec![Int(1), Int(2)],
                 vec![
                     make(OP_CONSTANT, &[0]),
                     make(OP_CONSTANT, &[1]),
                     make(OP_HASH, &[1]),
-                    make(OP_CONSTANT, &[2]),
-                    make(OP_CONSTANT, &[3]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_CONSTANT, &[0]),
                     make(OP_SUB, &[]),
                     make(OP_INDEX, &[]),
                     make(OP_POP, &[]),
@@ -530,31 +1039,77 @@ mod tests {
     }
 
     #[test]
-    fn test_function_literals() {
-        use Literal::{Ins, Int};
+    fn test_range_expressions() {
+        use Literal::{Int, Str};
         let test_cases: Vec<CompilerTestCase> = vec![
             (
-                "fn() { return 5 + 10 }",
+                "[1, 2, 3][0 : 1 + 1]",
+                vec![Int(1), Int(2), Int(3), Int(0)],
                 vec![
-                    Int(5),
-                    Int(10),
-                    Ins(vec![
-                        make(OP_CONSTANT, &[0]),
-                        make(OP_CONSTANT, &[1]),
-                        make(OP_ADD, &[]),
-                        make(OP_RETURN_VALUE, &[]),
-                    ]),
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_CONSTANT, &[2]),
+                    make(OP_ARRAY, &[3]),
+                    make(OP_CONSTANT, &[3]),
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_ADD, &[]),
+                    make(OP_SLICE, &[]),
+                    make(OP_POP, &[]),
                 ],
-                vec![make(OP_CLOSURE, &[2, 0]), make(OP_POP, &[])],
             ),
             (
-                "fn() { 5 + 10 }",
+                r#""monkey"[2:]"#,
+                vec![Str("monkey"), Int(2)],
                 vec![
-                    Int(5),
-                    Int(10),
-                    Ins(vec![
-                        make(OP_CONSTANT, &[0]),
-                        make(OP_CONSTANT, &[1]),
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_NULL, &[]),
+                    make(OP_SLICE, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            (
+                r#""monkey"[:3]"#,
+                vec![Str("monkey"), Int(3)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_NULL, &[]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_SLICE, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+        ];
+        run_compiler_tests(test_cases);
+    }
+
+    #[test]
+    fn test_function_literals() {
+        use Literal::{Ins, Int};
+        let test_cases: Vec<CompilerTestCase> = vec![
+            (
+                "fn() { return 5 + 10 }",
+                vec![
+                    Int(5),
+                    Int(10),
+                    Ins(vec![
+                        make(OP_CONSTANT, &[0]),
+                        make(OP_CONSTANT, &[1]),
+                        make(OP_ADD, &[]),
+                        make(OP_RETURN_VALUE, &[]),
+                    ]),
+                ],
+                vec![make(OP_CLOSURE, &[2, 0]), make(OP_POP, &[])],
+            ),
+            (
+                "fn() { 5 + 10 }",
+                vec![
+                    Int(5),
+                    Int(10),
+                    Ins(vec![
+                        make(OP_CONSTANT, &[0]),
+                        make(OP_CONSTANT, &[1]),
                         make(OP_ADD, &[]),
                         make(OP_RETURN_VALUE, &[]),
                     ]),
@@ -575,7 +1130,7 @@ mod tests {
         let mut compiler = Compiler::new();
         assert_eq!(compiler.scope_index, 0);
 
-        compiler.emit(OP_MUL, &[]);
+        compiler.emit(OP_MUL, &[]).unwrap();
         assert_eq!(compiler.scopes[0].instructions.len(), 1);
         assert_eq!(compiler.scopes[0].last_instruction.opcode, OP_MUL);
 
@@ -583,7 +1138,7 @@ mod tests {
         assert_eq!(compiler.scope_index, 1);
         assert!(compiler.symbol_table.outer.is_some());
 
-        compiler.emit(OP_SUB, &[]);
+        compiler.emit(OP_SUB, &[]).unwrap();
         assert_eq!(compiler.scopes[1].instructions.len(), 1);
         assert_eq!(compiler.scopes[1].last_instruction.opcode, OP_SUB);
 
@@ -592,7 +1147,7 @@ mod tests {
         assert_eq!(compiler.scope_index, 0);
         assert!(compiler.symbol_table.outer.is_none());
 
-        compiler.emit(OP_ADD, &[]);
+        compiler.emit(OP_ADD, &[]).unwrap();
         assert_eq!(compiler.scopes[0].instructions.len(), 2);
         assert_eq!(compiler.scopes[0].last_instruction.opcode, OP_ADD);
         assert_eq!(compiler.scopes[0].previous_instruction.opcode, OP_MUL);
@@ -734,10 +1289,197 @@ mod tests {
                     make(OP_POP, &[]),
                 ],
             ),
+            // compound assignments apply the operator to the current value
+            (
+                "let x = 10;
+                x += 2;",
+                vec![Int(10), Int(2)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_SET_GLOBAL, &[0]),
+                    make(OP_GET_GLOBAL, &[0]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_ADD, &[]),
+                    make(OP_ASSIGN_GLOBAL, &[0]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+        ];
+        run_compiler_tests(test_cases);
+    }
+
+    #[test]
+    fn test_local_and_free_assignments() {
+        use Literal::{Ins, Int};
+        let test_cases: Vec<CompilerTestCase> = vec![
+            (
+                "let i = 5;
+                fn() {
+                    let i = 0;
+                    i = i + 1;
+                }",
+                vec![
+                    Int(5),
+                    Int(0),
+                    Int(1),
+                    Ins(vec![
+                        make(OP_CONSTANT, &[1]),
+                        make(OP_SET_LOCAL, &[0]),
+                        make(OP_GET_LOCAL, &[0]),
+                        make(OP_CONSTANT, &[2]),
+                        make(OP_ADD, &[]),
+                        make(OP_ASSIGN_LOCAL, &[0]),
+                        make(OP_RETURN_VALUE, &[]),
+                    ]),
+                ],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_SET_GLOBAL, &[0]),
+                    make(OP_CLOSURE, &[3, 0]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            (
+                "fn(count) {
+                    fn() { count = 10; }
+                }",
+                vec![
+                    Int(10),
+                    Ins(vec![
+                        make(OP_CONSTANT, &[0]),
+                        make(OP_ASSIGN_FREE, &[0]),
+                        make(OP_RETURN_VALUE, &[]),
+                    ]),
+                    Ins(vec![make(OP_CLOSURE, &[1, 1]), make(OP_RETURN_VALUE, &[])]),
+                ],
+                vec![make(OP_CLOSURE, &[2, 0]), make(OP_POP, &[])],
+            ),
         ];
         run_compiler_tests(test_cases);
     }
 
+    #[test]
+    fn test_invalid_assignments() {
+        // input, expected error
+        let test_cases = [
+            ("len = 1;", "cannot assign to builtin function len"),
+            ("let f = fn() { f = 1; };", "cannot assign to function f"),
+            ("x = 1;", "variable with name x, not found"),
+        ];
+
+        for tc in test_cases {
+            let program = parse(tc.0);
+            let mut compiler = Compiler::new();
+            match compiler.compile(program.make_node()) {
+                Ok(_) => panic!("expected a compiler error for {}", tc.0),
+                Err(e) => assert_eq!(e.to_string(), tc.1),
+            }
+        }
+    }
+
+    #[test]
+    fn test_wide_operands() {
+        use Literal::{Ins, Int};
+
+        let params: Vec<String> = (0..300).map(|i| format!("a{}", i)).collect();
+        let function = format!("fn({}) {{ a299 = a255; a256 }}", params.join(", "));
+        let call_builtin = format!("len({})", vec!["1"; 300].join(", "));
+
+        let mut call = vec![make(OP_GET_BUILTIN, &[1])];
+        call.extend(std::iter::repeat_n(make(OP_CONSTANT, &[0]), 300));
+        call.push(vec![OP_WIDE, OP_CALL, 1, 44]);
+        call.push(make(OP_POP, &[]));
+
+        let test_cases: Vec<CompilerTestCase> = vec![
+            (
+                &function,
+                vec![Ins(vec![
+                    vec![OP_GET_LOCAL, 255],
+                    vec![OP_WIDE, OP_ASSIGN_LOCAL, 1, 43],
+                    make(OP_POP, &[]),
+                    vec![OP_WIDE, OP_GET_LOCAL, 1, 0],
+                    make(OP_RETURN_VALUE, &[]),
+                ])],
+                vec![make(OP_CLOSURE, &[0, 0]), make(OP_POP, &[])],
+            ),
+            (&call_builtin, vec![Int(1)], call),
+        ];
+        run_compiler_tests_with_level(test_cases, 0);
+    }
+
+    #[test]
+    fn test_operand_overflow() {
+        let constants: Vec<String> = (0..=65536).map(|i| i.to_string()).collect();
+
+        // input, expected error
+        let test_cases = [
+            (
+                format!("[{}]", vec!["1"; 65536].join(", ")),
+                "number of array elements 65536 is larger than the maximum of 65535",
+            ),
+            (
+                format!("{};", constants.join("; ")),
+                "constant index 65536 is larger than the maximum of 65535",
+            ),
+            (
+                format!("let x = true; if (x) {{ {} }}", "1; ".repeat(16384)),
+                "jump target 65548 is larger than the maximum of 65535",
+            ),
+        ];
+
+        for tc in test_cases {
+            let program = parse(&tc.0);
+            let mut compiler = Compiler::new();
+            match compiler.compile(program.make_node()) {
+                Ok(_) => panic!("expected a compiler error for {}", tc.1),
+                Err(e) => assert_eq!(e.to_string(), tc.1),
+            }
+        }
+    }
+
+    #[test]
+    fn test_compiler_error_locations() {
+        let program = parse("let f = fn(a) {\n  a + undefined_var\n};");
+        let mut compiler = Compiler::new();
+
+        let diagnostic = compiler.compile(program.make_node()).unwrap_err();
+        let name = "undefined_var".to_string();
+        assert_eq!(diagnostic.error, CompileError::UndefinedVariable { name });
+        assert_eq!(diagnostic.span, Span::new(2, 7, 22, 13));
+    }
+
+    #[test]
+    fn test_closure_free_variables() {
+        let program = parse(
+            "let f = fn(a) {
+                fn(b) {
+                    let inner = fn(c) { a + b + c + inner(c) };
+                    fn() { inner }
+                }
+            }",
+        );
+        let mut compiler = Compiler::new();
+        compiler.compile(program.make_node()).unwrap();
+        let constants = compiler.byte_code().constants;
+
+        let free_variables = |i: usize| match &constants[i] {
+            AllObjects::CompiledFunction(v) => v.free_variables.clone(),
+            _ => panic!("expected a compiled function at {i}"),
+        };
+
+        // `inner`, which refers to itself as the current closure
+        assert_eq!(
+            free_variables(0),
+            vec![FreeVariable::Free(0), FreeVariable::Local(0)]
+        );
+        // fn() { inner }
+        assert_eq!(free_variables(1), vec![FreeVariable::Local(1)]);
+        // fn(b)
+        assert_eq!(free_variables(2), vec![FreeVariable::Local(0)]);
+        // fn(a)
+        assert_eq!(free_variables(3), vec![]);
+    }
+
     #[test]
     fn test_builtins() {
         use Literal::{Ins, Int};
@@ -786,11 +1528,7 @@ mod tests {
                         make(OP_ADD, &[]),
                         make(OP_RETURN_VALUE, &[]),
                     ]),
-                    Ins(vec![
-                        make(OP_GET_LOCAL, &[0]),
-                        make(OP_CLOSURE, &[0, 1]),
-                        make(OP_RETURN_VALUE, &[]),
-                    ]),
+                    Ins(vec![make(OP_CLOSURE, &[0, 1]), make(OP_RETURN_VALUE, &[])]),
                 ],
                 vec![make(OP_CLOSURE, &[1, 0]), make(OP_POP, &[])],
             ),
@@ -805,17 +1543,8 @@ mod tests {
                         make(OP_ADD, &[]),
                         make(OP_RETURN_VALUE, &[]),
                     ]),
-                    Ins(vec![
-                        make(OP_GET_FREE, &[0]),
-                        make(OP_GET_LOCAL, &[0]),
-                        make(OP_CLOSURE, &[0, 2]),
-                        make(OP_RETURN_VALUE, &[]),
-                    ]),
-                    Ins(vec![
-                        make(OP_GET_LOCAL, &[0]),
-                        make(OP_CLOSURE, &[1, 1]),
-                        make(OP_RETURN_VALUE, &[]),
-                    ]),
+                    Ins(vec![make(OP_CLOSURE, &[0, 2]), make(OP_RETURN_VALUE, &[])]),
+                    Ins(vec![make(OP_CLOSURE, &[1, 1]), make(OP_RETURN_VALUE, &[])]),
                 ],
                 vec![make(OP_CLOSURE, &[2, 0]), make(OP_POP, &[])],
             ),
@@ -851,15 +1580,12 @@ mod tests {
                     Ins(vec![
                         make(OP_CONSTANT, &[2]),
                         make(OP_SET_LOCAL, &[0]),
-                        make(OP_GET_FREE, &[0]),
-                        make(OP_GET_LOCAL, &[0]),
                         make(OP_CLOSURE, &[4, 2]),
                         make(OP_RETURN_VALUE, &[]),
                     ]),
                     Ins(vec![
                         make(OP_CONSTANT, &[1]),
                         make(OP_SET_LOCAL, &[0]),
-                        make(OP_GET_LOCAL, &[0]),
                         make(OP_CLOSURE, &[5, 1]),
                         make(OP_RETURN_VALUE, &[]),
                     ]),
@@ -894,13 +1620,12 @@ mod tests {
                         make(OP_CALL, &[1]),
                         make(OP_RETURN_VALUE, &[]),
                     ]),
-                    Int(1),
                 ],
                 vec![
                     make(OP_CLOSURE, &[1, 0]),
                     make(OP_SET_GLOBAL, &[0]),
                     make(OP_GET_GLOBAL, &[0]),
-                    make(OP_CONSTANT, &[2]),
+                    make(OP_CONSTANT, &[0]),
                     make(OP_CALL, &[1]),
                     make(OP_POP, &[]),
                 ],
@@ -921,18 +1646,17 @@ mod tests {
                         make(OP_CALL, &[1]),
                         make(OP_RETURN_VALUE, &[]),
                     ]),
-                    Int(1),
                     Ins(vec![
                         make(OP_CLOSURE, &[1, 0]),
                         make(OP_SET_LOCAL, &[0]),
                         make(OP_GET_LOCAL, &[0]),
-                        make(OP_CONSTANT, &[2]),
+                        make(OP_CONSTANT, &[0]),
                         make(OP_CALL, &[1]),
                         make(OP_RETURN_VALUE, &[]),
                     ]),
                 ],
                 vec![
-                    make(OP_CLOSURE, &[3, 0]),
+                    make(OP_CLOSURE, &[2, 0]),
                     make(OP_SET_GLOBAL, &[0]),
                     make(OP_GET_GLOBAL, &[0]),
                     make(OP_CALL, &[0]),
@@ -942,6 +1666,389 @@ mod tests {
         ];
         run_compiler_tests(test_cases);
     }
+
+    #[test]
+    fn test_constant_folding() {
+        use Literal::{Float, Ins, Int, Str};
+
+        // without optimizations, every operator is evaluated at runtime
+        run_compiler_tests(vec![(
+            "1 + 2 * 3",
+            vec![Int(1), Int(2), Int(3)],
+            vec![
+                make(OP_CONSTANT, &[0]),
+                make(OP_CONSTANT, &[1]),
+                make(OP_CONSTANT, &[2]),
+                make(OP_MUL, &[]),
+                make(OP_ADD, &[]),
+                make(OP_POP, &[]),
+            ],
+        )]);
+
+        let test_cases: Vec<CompilerTestCase> = vec![
+            (
+                "1 + 2 * 3",
+                vec![Int(7)],
+                vec![make(OP_CONSTANT, &[0]), make(OP_POP, &[])],
+            ),
+            (
+                r#""mon" + "key"; 2.5 * 2"#,
+                vec![Str("monkey"), Float(5.0)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_POP, &[]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            (
+                r#"-5; !true; !!0; 1 < 2 == true; "a" >= "b""#,
+                vec![Int(-5)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_POP, &[]),
+                    make(OP_FALSE, &[]),
+                    make(OP_POP, &[]),
+                    make(OP_TRUE, &[]),
+                    make(OP_POP, &[]),
+                    make(OP_TRUE, &[]),
+                    make(OP_POP, &[]),
+                    make(OP_FALSE, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            // only the constant parts of an expression are folded
+            (
+                "let x = 1; x * (2 + 3)",
+                vec![Int(1), Int(5)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_SET_GLOBAL, &[0]),
+                    make(OP_GET_GLOBAL, &[0]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_MUL, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            // errors are still raised at runtime
+            (
+                "1 / 0",
+                vec![Int(1), Int(0)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_DIV, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            // a constant left side either decides a logical expression or is dropped
+            (
+                "let x = 1; false && x; true || x; true && x; null || x",
+                vec![Int(1)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_SET_GLOBAL, &[0]),
+                    make(OP_FALSE, &[]),
+                    make(OP_POP, &[]),
+                    make(OP_TRUE, &[]),
+                    make(OP_POP, &[]),
+                    make(OP_GET_GLOBAL, &[0]),
+                    make(OP_POP, &[]),
+                    make(OP_GET_GLOBAL, &[0]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            (
+                "fn() { 10 - 2 * 3 }",
+                vec![
+                    Int(4),
+                    Ins(vec![make(OP_CONSTANT, &[0]), make(OP_RETURN_VALUE, &[])]),
+                ],
+                vec![make(OP_CLOSURE, &[1, 0]), make(OP_POP, &[])],
+            ),
+        ];
+        run_compiler_tests_with_level(test_cases, 1);
+
+        // results that need a big integer are folded into a big integer constant
+        let mut compiler = Compiler::new();
+        compiler.optimization_level = 1;
+        let program = parse("9223372036854775807 + 1");
+        compiler.compile(program.make_node()).unwrap();
+        let bytecode = compiler.byte_code();
+        assert_eq!(
+            bytecode.instructions,
+            concat_instructions(vec![make(OP_CONSTANT, &[0]), make(OP_POP, &[])])
+        );
+        assert_eq!(bytecode.constants.len(), 1);
+        assert_eq!(bytecode.constants[0].inspect(), "9223372036854775808");
+    }
+
+    #[test]
+    fn test_dead_branch_elimination() {
+        use Literal::{Ins, Int};
+
+        // the unoptimized output of the same conditionals is in `test_conditionals` and
+        // `test_while_statements`
+        let test_cases: Vec<CompilerTestCase> = vec![
+            (
+                "if (true) { 10; }; 3333;",
+                vec![Int(10), Int(3333)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_POP, &[]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            (
+                "if (true) { 10; } else { 20 }; 3333;",
+                vec![Int(10), Int(3333)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_POP, &[]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            (
+                "if (false) { 10 } else if (true) { 20 }",
+                vec![Int(20)],
+                vec![make(OP_CONSTANT, &[0]), make(OP_POP, &[])],
+            ),
+            (
+                "if (1 > 2) { 10 }; if (true) { }",
+                vec![],
+                vec![
+                    make(OP_NULL, &[]),
+                    make(OP_POP, &[]),
+                    make(OP_NULL, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            (
+                "let x = 1; if (x) { 10 } else if (false) { 20 } else { 30 }",
+                vec![Int(1), Int(10), Int(30)],
+                vec![
+                    make(OP_CONSTANT, &[0]),         // 0000
+                    make(OP_SET_GLOBAL, &[0]),       // 0003
+                    make(OP_GET_GLOBAL, &[0]),       // 0006
+                    make(OP_JUMP_NOT_TRUTHY, &[18]), // 0009
+                    make(OP_CONSTANT, &[1]),         // 0012
+                    make(OP_JUMP, &[21]),            // 0015
+                    make(OP_CONSTANT, &[2]),         // 0018
+                    make(OP_POP, &[]),               // 0021
+                ],
+            ),
+            (
+                "while (true) { break; } 3333;",
+                vec![Int(3333)],
+                vec![
+                    make(OP_JUMP, &[6]),     // 0000
+                    make(OP_JUMP, &[0]),     // 0003
+                    make(OP_CONSTANT, &[0]), // 0006
+                    make(OP_POP, &[]),       // 0009
+                ],
+            ),
+            // a removed loop isn't the value of the function
+            (
+                "fn() { 1; while (false) { 2; } }",
+                vec![
+                    Int(1),
+                    Ins(vec![
+                        make(OP_CONSTANT, &[0]),
+                        make(OP_POP, &[]),
+                        make(OP_RETURN, &[]),
+                    ]),
+                ],
+                vec![make(OP_CLOSURE, &[1, 0]), make(OP_POP, &[])],
+            ),
+            // the breaks of a removed branch don't belong to the loop anymore
+            (
+                "let x = true; while (x) { if (false) { break; } }",
+                vec![],
+                vec![
+                    make(OP_TRUE, &[]),              // 0000
+                    make(OP_SET_GLOBAL, &[0]),       // 0001
+                    make(OP_GET_GLOBAL, &[0]),       // 0004
+                    make(OP_JUMP_NOT_TRUTHY, &[15]), // 0007
+                    make(OP_NULL, &[]),              // 0010
+                    make(OP_POP, &[]),               // 0011
+                    make(OP_JUMP, &[4]),             // 0012
+                ],
+            ),
+        ];
+        run_compiler_tests_with_level(test_cases, 1);
+
+        // dead code is still compiled, so it reports the same errors
+        let test_cases = [
+            "if (false) { y }",
+            "if (true) { 1 } else { y }",
+            "while (false) { y }",
+            "false && y",
+        ];
+        for input in test_cases {
+            let program = parse(input);
+            let mut compiler = Compiler::new();
+            compiler.optimization_level = 1;
+            let err = compiler.compile(program.make_node()).unwrap_err();
+            assert_eq!(err.to_string(), "undefined variable y", "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_peephole_optimization() {
+        use Literal::{Ins, Int};
+
+        // the output of the same programs on level 1 is in `test_dead_branch_elimination`
+        let test_cases: Vec<CompilerTestCase> = vec![
+            (
+                "let x = true; while (x) { if (false) { break; } }",
+                vec![],
+                vec![
+                    make(OP_TRUE, &[]),              // 0000
+                    make(OP_SET_GLOBAL, &[0]),       // 0001
+                    make(OP_GET_GLOBAL, &[0]),       // 0004
+                    make(OP_JUMP_NOT_TRUTHY, &[13]), // 0007
+                    make(OP_JUMP, &[4]),             // 0010
+                ],
+            ),
+            (
+                "fn(x) { x; if (x > 1) { 10 } else { 20 } }",
+                vec![
+                    Int(1),
+                    Int(10),
+                    Int(20),
+                    Ins(vec![
+                        make(OP_GET_LOCAL, &[0]),        // 0000
+                        make(OP_CONSTANT, &[0]),         // 0002
+                        make(OP_GREATER_THAN, &[]),      // 0005
+                        make(OP_JUMP_NOT_TRUTHY, &[13]), // 0006
+                        make(OP_CONSTANT, &[1]),         // 0009
+                        make(OP_RETURN_VALUE, &[]),      // 0012
+                        make(OP_CONSTANT, &[2]),         // 0013
+                        make(OP_RETURN_VALUE, &[]),      // 0016
+                    ]),
+                ],
+                vec![make(OP_CLOSURE, &[3, 0]), make(OP_POP, &[])],
+            ),
+        ];
+        run_compiler_tests_with_level(test_cases, 2);
+    }
+
+    #[test]
+    fn test_constant_deduplication() {
+        use Literal::{Float, Ins, Int, Str};
+
+        let test_cases: Vec<CompilerTestCase> = vec![
+            (
+                r#""ok"; 1; "ok"; 1; 1.5; 1.5; 0.0; -0.0"#,
+                vec![Str("ok"), Int(1), Float(1.5), Float(0.0)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_POP, &[]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_POP, &[]),
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_POP, &[]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_POP, &[]),
+                    make(OP_CONSTANT, &[2]),
+                    make(OP_POP, &[]),
+                    make(OP_CONSTANT, &[2]),
+                    make(OP_POP, &[]),
+                    make(OP_CONSTANT, &[3]),
+                    make(OP_POP, &[]),
+                    make(OP_CONSTANT, &[3]),
+                    make(OP_MINUS, &[]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+            // functions share the constants of the enclosing scopes, and the same function at
+            // another location is the same constant
+            (
+                "let one = 1; fn() { one + 1 }; fn() { one + 1 }",
+                vec![
+                    Int(1),
+                    Ins(vec![
+                        make(OP_GET_GLOBAL, &[0]),
+                        make(OP_CONSTANT, &[0]),
+                        make(OP_ADD, &[]),
+                        make(OP_RETURN_VALUE, &[]),
+                    ]),
+                ],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_SET_GLOBAL, &[0]),
+                    make(OP_CLOSURE, &[1, 0]),
+                    make(OP_POP, &[]),
+                    make(OP_CLOSURE, &[1, 0]),
+                    make(OP_POP, &[]),
+                ],
+            ),
+        ];
+        run_compiler_tests(test_cases);
+
+        // the name and the locations of a function that shares its constant are kept at its
+        // `OP_CLOSURE` instruction
+        let program = parse("let add = fn(a, b) { a + b };\nlet plus = fn(a, b) { a + b };");
+        let mut compiler = Compiler::new();
+        compiler.compile(program.make_node()).unwrap();
+        let bytecode = compiler.byte_code();
+        assert_eq!(bytecode.constants.len(), 1);
+        let AllObjects::CompiledFunction(shared) = &bytecode.constants[0] else {
+            panic!("expected a compiled function");
+        };
+        assert_eq!(shared.name, "add");
+
+        let sites = bytecode.source_map.functions();
+        assert_eq!(sites.len(), 1);
+        assert_eq!(sites[0].0, 7);
+        assert_eq!(sites[0].1.name, "plus");
+        let span = sites[0].1.source_map.lookup(0).unwrap();
+        assert_eq!((span.line, span.column), (2, 23));
+
+        // -0.0 is folded into a constant of its own
+        run_compiler_tests_with_level(
+            vec![(
+                "0.0; -0.0",
+                vec![Float(0.0), Float(-0.0)],
+                vec![
+                    make(OP_CONSTANT, &[0]),
+                    make(OP_POP, &[]),
+                    make(OP_CONSTANT, &[1]),
+                    make(OP_POP, &[]),
+                ],
+            )],
+            1,
+        );
+    }
+
+    #[test]
+    fn test_constants_across_repl_lines() {
+        let mut compiler = Compiler::new();
+        let program = parse(r#""ok"; fn() { 1 }"#);
+        compiler.compile(program.make_node()).unwrap();
+        let (symbol_table, constants) = (compiler.symbol_table, compiler.constants);
+        assert_eq!(constants.len(), 3);
+
+        // every line starts at 1:1, so the same function at the same column is the same constant
+        let mut compiler = Compiler::new_with_state(symbol_table, constants);
+        let program = parse(r#""ok"; fn() { 1 }; "new""#);
+        compiler.compile(program.make_node()).unwrap();
+        let bytecode = compiler.byte_code();
+
+        let expected = concat_instructions(vec![
+            make(OP_CONSTANT, &[0]),
+            make(OP_POP, &[]),
+            make(OP_CLOSURE, &[2, 0]),
+            make(OP_POP, &[]),
+            make(OP_CONSTANT, &[3]),
+            make(OP_POP, &[]),
+        ]);
+        assert_eq!(bytecode.instructions, expected);
+        assert_eq!(bytecode.constants.len(), 4);
+        test_string_object("new", &bytecode.constants[3]);
+    }
 }
 
 #[cfg(test)]
@@ -956,9 +2063,10 @@ pub mod test_helpers {
         parser::Parser,
     };
 
-    #[derive(Clone, Debug, PartialEq, Eq)]
+    #[derive(Clone, Debug, PartialEq)]
     pub enum Literal {
         Int(i64),
+        Float(f64),
         Bool(bool),
         Str(&'static str),
         Arr(Vec<Literal>),
@@ -971,6 +2079,7 @@ pub mod test_helpers {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             let out = match self {
                 Self::Int(v) => v.to_string(),
+                Self::Float(v) => format!("{:?}", v),
                 Self::Bool(v) => v.to_string(),
                 Self::Str(v) => v.to_string(),
                 Self::Arr(v) => format!("{:?}", v),
@@ -982,10 +2091,14 @@ pub mod test_helpers {
         }
     }
 
+    // float literals in the tests are never NaN
+    impl Eq for Literal {}
+
     impl Hash for Literal {
         fn hash<H: std::hash::Hasher>(&self, _: &mut H) {
             match self {
                 Literal::Int(v) => v.to_string(),
+                Literal::Float(v) => v.to_string(),
                 Literal::Bool(v) => v.to_string(),
                 Literal::Str(v) => v.to_string(),
                 Literal::Arr(_) => unimplemented!(),
@@ -1000,9 +2113,14 @@ pub mod test_helpers {
     pub type CompilerTestCase<'a> = (&'a str, Vec<Literal>, Vec<Instructions>);
 
     pub fn run_compiler_tests(test_cases: Vec<CompilerTestCase>) {
+        run_compiler_tests_with_level(test_cases, 0);
+    }
+
+    pub fn run_compiler_tests_with_level(test_cases: Vec<CompilerTestCase>, level: u8) {
         for tc in test_cases {
             let program = parse(tc.0);
             let mut compiler = Compiler::new();
+            compiler.optimization_level = level;
             if let Err(e) = compiler.compile(program.make_node()) {
                 panic!("compiler error: {e}");
             };
@@ -1028,6 +2146,7 @@ pub mod test_helpers {
         for (i, constant) in expected.into_iter().enumerate() {
             match constant {
                 Literal::Int(v) => test_integer_object(v, &actual[i]),
+                Literal::Float(v) => test_float_object(v, &actual[i]),
                 Literal::Str(v) => test_string_object(v, &actual[i]),
                 Literal::Bool(v) => test_boolean_object(v, &actual[i]),
                 Literal::Arr(v) => test_array_literal(v, &actual[i]),
@@ -1052,6 +2171,13 @@ pub mod test_helpers {
         };
     }
 
+    pub fn test_float_object(expected: f64, actual: &AllObjects) {
+        match actual {
+            AllObjects::Float(v) => assert_eq!(v.value, expected),
+            _ => panic!("expected a float object"),
+        };
+    }
+
     pub fn test_string_object(expected: &str, actual: &AllObjects) {
         match actual {
             AllObjects::StringObj(v) => assert_eq!(*v.value, expected),
@@ -1132,6 +2258,7 @@ pub mod test_helpers {
     pub fn test_expected_object(expected: Literal, actual: &AllObjects) {
         match expected {
             Literal::Int(v) => test_integer_object(v, actual),
+            Literal::Float(v) => test_float_object(v, actual),
             Literal::Bool(v) => test_boolean_object(v, actual),
             Literal::Str(v) => test_string_object(v, actual),
             Literal::Arr(v) => test_array_literal(v, actual),
@@ -0,0 +1,149 @@
+use super::{Compiler, EmittedInstruction, Result};
+use crate::{
+    ast::AllNodes,
+    code::{self, *},
+    object::{
+        objects::{Boolean, Null},
+        AllObjects,
+    },
+    vm::VM,
+};
+
+/// The state of the compiler before a node is compiled, which can be restored to drop the
+/// instructions of the node once it's folded into a constant or found to be dead code
+pub(super) struct Checkpoint {
+    position: usize,
+    num_constants: usize,
+    stack_depth: usize,
+}
+
+impl Compiler {
+    /// Returns whether constant expressions are folded and dead branches are removed
+    pub(super) fn optimizing(&self) -> bool {
+        self.optimization_level > 0
+    }
+
+    pub(super) fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint {
+            position: self.current_instructions().len(),
+            num_constants: self.constants.len(),
+            stack_depth: self.scopes[self.scope_index].stack_depth,
+        }
+    }
+
+    /// Removes the instructions and the constants added since the checkpoint, along with the
+    /// `break` jumps among them. The symbols defined since then stay defined, so the indexes of
+    /// the variables that follow don't depend on the optimization level.
+    pub(super) fn rollback(&mut self, checkpoint: &Checkpoint) {
+        let scope = &mut self.scopes[self.scope_index];
+        scope.instructions.truncate(checkpoint.position);
+        scope.source_map.truncate(checkpoint.position);
+        scope.stack_depth = checkpoint.stack_depth;
+        for context in scope.loops.iter_mut() {
+            context
+                .breaks
+                .retain(|position| *position < checkpoint.position);
+        }
+
+        // what was emitted before the checkpoint doesn't belong to the node that follows it, so
+        // it shouldn't be mistaken for the last instruction of the node (e.g. its value's pop)
+        scope.last_instruction = EmittedInstruction::default();
+        scope.previous_instruction = EmittedInstruction::default();
+
+        self.truncate_constants(checkpoint.num_constants);
+    }
+
+    /// Compiles the node only to report its errors, as it's never executed
+    pub(super) fn compile_dead_code(&mut self, node: AllNodes) -> Result<()> {
+        let checkpoint = self.checkpoint();
+        self.compile(node)?;
+        self.rollback(&checkpoint);
+        Ok(())
+    }
+
+    /// Returns the values pushed by the instructions since the checkpoint if all of them push a
+    /// constant, `true`, `false` or `null`, which is only checked when optimizing
+    pub(super) fn constant_values(&mut self, checkpoint: &Checkpoint) -> Option<Vec<AllObjects>> {
+        if !self.optimizing() {
+            return None;
+        }
+
+        let ins = &self.scopes[self.scope_index].instructions;
+        let mut values = Vec::new();
+        let mut offset = checkpoint.position;
+        while offset < ins.len() {
+            let value = match ins[offset] {
+                OP_CONSTANT => self.constants[helpers::read_u16(&ins[offset + 1..])].clone(),
+                OP_TRUE => AllObjects::Boolean(Boolean { value: true }),
+                OP_FALSE => AllObjects::Boolean(Boolean { value: false }),
+                OP_NULL => AllObjects::Null(Null),
+                _ => return None,
+            };
+            values.push(value);
+
+            let def = code::lookup(ins[offset]).ok()?;
+            offset += 1 + def.operand_widths.iter().sum::<usize>();
+        }
+        Some(values)
+    }
+
+    /// Returns whether the condition compiled since the checkpoint is always or never truthy, if
+    /// it's a constant
+    pub(super) fn constant_condition(&mut self, checkpoint: &Checkpoint) -> Option<bool> {
+        match self.constant_values(checkpoint)?.as_slice() {
+            // anything but false and null is truthy
+            [AllObjects::Boolean(Boolean { value: false }) | AllObjects::Null(_)] => Some(false),
+            [_] => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Replaces the operands compiled since the checkpoint with the result of the operator, if the
+    /// operands are constants and the operator doesn't fail on them. Returns whether the
+    /// expression was folded, or an error if its result can't be emitted.
+    pub(super) fn fold_operator(&mut self, checkpoint: &Checkpoint, op: Opcode) -> Result<bool> {
+        let Some(operands) = self.constant_values(checkpoint) else {
+            return Ok(false);
+        };
+        let result = match (op, operands.as_slice()) {
+            (OP_ADD | OP_SUB | OP_MUL | OP_DIV | OP_MOD, [left, right]) => {
+                VM::arithmetic(op, left.clone(), right.clone())
+            }
+            (
+                OP_EQUAL
+                | OP_NOT_EQUAL
+                | OP_GREATER_THAN
+                | OP_GREATER_THAN_OR_EQUAL
+                | OP_LESS_THAN
+                | OP_LESS_THAN_OR_EQUAL,
+                [left, right],
+            ) => VM::comparison(op, left.clone(), right.clone()),
+            (OP_MINUS, [operand]) => VM::negate(operand.clone()),
+            (OP_BANG, [operand]) => Ok(VM::not(operand.clone())),
+            _ => return Ok(false),
+        };
+
+        // errors are left to be reported at runtime
+        let Ok(result) = result else {
+            return Ok(false);
+        };
+
+        self.rollback(checkpoint);
+        self.emit_value(result)?;
+        Ok(true)
+    }
+
+    /// Emits the instruction that pushes the constant value
+    fn emit_value(&mut self, value: AllObjects) -> Result<()> {
+        match value {
+            AllObjects::Boolean(v) if v.value => self.emit(OP_TRUE, &[])?,
+            AllObjects::Boolean(_) => self.emit(OP_FALSE, &[])?,
+            AllObjects::Null(_) => self.emit(OP_NULL, &[])?,
+            value => {
+                let constant_index = self.add_constant(value);
+                self.emit(OP_CONSTANT, &[constant_index])?
+            }
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,503 @@
+use crate::code::{self, *};
+use std::collections::{HashMap, HashSet};
+
+/// An instruction of the peephole pass, whose jump target is still the offset of an original
+/// instruction until the instructions are encoded again
+struct Instruction {
+    offset: usize,
+    opcode: Opcode,
+    operands: Vec<usize>,
+    removed: bool,
+}
+
+/// Rewrites short sequences of instructions into cheaper ones that behave the same:
+/// - jumps that land on an `OP_JUMP` go straight to its target, and so do the `*_OR_POP` jumps
+///   that land on a jump of the same kind, as it would jump again with the same value
+/// - an `OP_JUMP` to a return is replaced with the return, and one to the next instruction is
+///   removed
+/// - `OP_TRUE; OP_JUMP_NOT_TRUTHY` never jumps so it's removed, while `OP_FALSE` or `OP_NULL`
+///   followed by `OP_JUMP_NOT_TRUTHY` always jumps and becomes an `OP_JUMP`
+/// - a local, a free variable, a builtin or a literal that is popped right after being pushed
+///   is never pushed, including when it's pushed before jumping to an `OP_POP`, in which case
+///   the jump goes past the pop
+///
+/// The jump targets and the source map are relocated to the new offsets. The last instruction is
+/// always kept, as the VM takes the value popped by it as the result of the program.
+pub(super) fn optimize(ins: &Instructions, source_map: &SourceMap) -> (Instructions, SourceMap) {
+    let mut peephole = Peephole::new(ins);
+    while peephole.pass() {}
+    peephole.encode(source_map)
+}
+
+/// Returns whether the instruction does nothing but push a value, so it can be removed along
+/// with the pop of the value
+fn pushes_value(op: Opcode) -> bool {
+    matches!(
+        op,
+        OP_GET_LOCAL
+            | OP_GET_FREE
+            | OP_GET_BUILTIN
+            | OP_CURRENT_CLOSURE
+            | OP_CONSTANT
+            | OP_TRUE
+            | OP_FALSE
+            | OP_NULL
+    )
+}
+
+struct Peephole {
+    instructions: Vec<Instruction>,
+
+    /// index of every instruction by its original offset
+    indexes: HashMap<usize, usize>,
+
+    /// length of the original instructions, which is jumped to by a trailing if or while
+    len: usize,
+}
+
+impl Peephole {
+    fn new(ins: &Instructions) -> Self {
+        let instructions: Vec<Instruction> = code::decode(ins)
+            .into_iter()
+            .map(|(offset, opcode, operands)| Instruction {
+                offset,
+                opcode,
+                operands,
+                removed: false,
+            })
+            .collect();
+        let indexes = instructions
+            .iter()
+            .enumerate()
+            .map(|(i, instruction)| (instruction.offset, i))
+            .collect();
+
+        Self {
+            instructions,
+            indexes,
+            len: ins.len(),
+        }
+    }
+
+    /// Applies every pattern once, returning whether any of the instructions changed
+    fn pass(&mut self) -> bool {
+        // the original offsets that are jumped to, which only a jump past a pop adds to during the
+        // pass, as threading reuses the targets of other jumps
+        let mut targets: HashSet<usize> = self
+            .instructions
+            .iter()
+            .filter(|ins| !ins.removed && code::is_jump(ins.opcode))
+            .map(|ins| ins.operands[0])
+            .collect();
+        let last = self.instructions.len().saturating_sub(1);
+
+        let mut changed = false;
+        for i in 0..self.instructions.len() {
+            if self.instructions[i].removed {
+                continue;
+            }
+            let opcode = self.instructions[i].opcode;
+
+            if code::is_jump(opcode) {
+                if let Some(target) = self.threaded_target(i) {
+                    self.instructions[i].operands[0] = target;
+                    changed = true;
+                    continue;
+                }
+            }
+
+            let next = self.first_kept(i + 1);
+            if opcode == OP_JUMP {
+                let target = self.resolve(self.instructions[i].operands[0]);
+                if target == next && i != last {
+                    self.instructions[i].removed = true;
+                    changed = true;
+                } else if matches!(self.opcode_at(target), Some(OP_RETURN_VALUE | OP_RETURN)) {
+                    self.instructions[i].opcode = self.instructions[target].opcode;
+                    self.instructions[i].operands.clear();
+                    changed = true;
+                }
+                continue;
+            }
+
+            // the second instruction must be reached only through the first one
+            if next >= last || self.is_target(next, &targets) {
+                continue;
+            }
+            match (opcode, self.instructions[next].opcode) {
+                (OP_TRUE, OP_JUMP_NOT_TRUTHY) => {
+                    self.instructions[i].removed = true;
+                    self.instructions[next].removed = true;
+                }
+                (OP_FALSE | OP_NULL, OP_JUMP_NOT_TRUTHY) => {
+                    self.instructions[i].removed = true;
+                    self.instructions[next].opcode = OP_JUMP;
+                }
+                (op, OP_POP) if pushes_value(op) => {
+                    self.instructions[i].removed = true;
+                    self.instructions[next].removed = true;
+                }
+                (op, OP_JUMP) if pushes_value(op) => {
+                    let target = self.resolve(self.instructions[next].operands[0]);
+                    if target >= last || self.instructions[target].opcode != OP_POP {
+                        continue;
+                    }
+                    let after_pop = self.instructions[target + 1].offset;
+                    targets.insert(after_pop);
+                    self.instructions[i].removed = true;
+                    self.instructions[next].operands[0] = after_pop;
+                }
+                _ => continue,
+            }
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// Returns the target the jump can go to directly, if it lands on a jump that would take it
+    /// somewhere else
+    fn threaded_target(&self, i: usize) -> Option<usize> {
+        let jump = &self.instructions[i];
+        let landing = self.instructions.get(self.resolve(jump.operands[0]))?;
+
+        let threads = landing.opcode == OP_JUMP
+            || (matches!(
+                jump.opcode,
+                OP_JUMP_NOT_TRUTHY_OR_POP | OP_JUMP_TRUTHY_OR_POP
+            ) && landing.opcode == jump.opcode);
+        if !threads || landing.operands[0] == jump.operands[0] {
+            return None;
+        }
+        Some(landing.operands[0])
+    }
+
+    /// Returns the index of the instruction that runs when jumping to the original offset, which
+    /// is the first one that is kept from there on
+    fn resolve(&self, offset: usize) -> usize {
+        let start = match self.indexes.get(&offset) {
+            Some(i) => *i,
+            None => self.instructions.len(),
+        };
+        self.first_kept(start)
+    }
+
+    fn first_kept(&self, mut i: usize) -> usize {
+        while i < self.instructions.len() && self.instructions[i].removed {
+            i += 1;
+        }
+        i
+    }
+
+    fn opcode_at(&self, i: usize) -> Option<Opcode> {
+        self.instructions.get(i).map(|ins| ins.opcode)
+    }
+
+    /// Returns whether a jump lands on the instruction, either directly or through the removed
+    /// instructions right before it
+    fn is_target(&self, i: usize, targets: &HashSet<usize>) -> bool {
+        let mut j = i;
+        loop {
+            if targets.contains(&self.instructions[j].offset) {
+                return true;
+            }
+            if j == 0 || !self.instructions[j - 1].removed {
+                return false;
+            }
+            j -= 1;
+        }
+    }
+
+    /// Encodes the instructions that are kept, relocating the jumps and the source map entries
+    fn encode(self, source_map: &SourceMap) -> (Instructions, SourceMap) {
+        // a removed instruction moves to where the next kept one starts
+        let mut new_offsets = HashMap::new();
+        let mut offset = 0;
+        for ins in &self.instructions {
+            new_offsets.insert(ins.offset, offset);
+            // relocating a jump doesn't change its size, as jump targets are never wide
+            if !ins.removed {
+                offset += make(ins.opcode, &ins.operands).len();
+            }
+        }
+        new_offsets.insert(self.len, offset);
+
+        let mut instructions = Instructions::new();
+        let mut new_source_map = SourceMap::default();
+        for ins in self.instructions.iter().filter(|ins| !ins.removed) {
+            if let Some(span) = source_map.lookup(ins.offset) {
+                new_source_map.add(instructions.len(), span);
+            }
+            if let Some(site) = source_map.function_at(ins.offset) {
+                new_source_map.add_function(instructions.len(), site.clone());
+            }
+
+            let mut operands = ins.operands.clone();
+            if code::is_jump(ins.opcode) {
+                operands[0] = new_offsets[&operands[0]];
+            }
+            instructions.extend(make(ins.opcode, &operands));
+        }
+
+        (instructions, new_source_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::optimize;
+    use crate::{
+        code::*,
+        compiler::{
+            test_helpers::{concat_instructions, parse},
+            ByteCode, Compiler,
+        },
+        lexer::token::Span,
+        object::{AllObjects, Object},
+        vm::VM,
+    };
+
+    #[test]
+    fn test_peephole_patterns() {
+        // (instructions, optimized instructions)
+        let test_cases = [
+            // jumps to jumps, where the jump that is skipped then goes to the next instruction
+            (
+                vec![
+                    make(OP_GET_GLOBAL, &[0]),      // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[9]), // 0003
+                    make(OP_JUMP, &[0]),            // 0006
+                    make(OP_JUMP, &[12]),           // 0009
+                    make(OP_GET_GLOBAL, &[1]),      // 0012
+                    make(OP_POP, &[]),              // 0015
+                ],
+                vec![
+                    make(OP_GET_GLOBAL, &[0]),      // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[9]), // 0003
+                    make(OP_JUMP, &[0]),            // 0006
+                    make(OP_GET_GLOBAL, &[1]),      // 0009
+                    make(OP_POP, &[]),              // 0012
+                ],
+            ),
+            // conditional jumps that keep the value only go through jumps of the same kind
+            (
+                vec![
+                    make(OP_GET_GLOBAL, &[0]),              // 0000
+                    make(OP_JUMP_NOT_TRUTHY_OR_POP, &[11]), // 0003
+                    make(OP_GET_GLOBAL, &[1]),              // 0006
+                    make(OP_BANG, &[]),                     // 0009
+                    make(OP_NULL, &[]),                     // 0010
+                    make(OP_JUMP_NOT_TRUTHY_OR_POP, &[20]), // 0011
+                    make(OP_JUMP_TRUTHY_OR_POP, &[20]),     // 0014
+                    make(OP_GET_GLOBAL, &[2]),              // 0017
+                    make(OP_POP, &[]),                      // 0020
+                ],
+                vec![
+                    make(OP_GET_GLOBAL, &[0]),              // 0000
+                    make(OP_JUMP_NOT_TRUTHY_OR_POP, &[20]), // 0003
+                    make(OP_GET_GLOBAL, &[1]),              // 0006
+                    make(OP_BANG, &[]),                     // 0009
+                    make(OP_NULL, &[]),                     // 0010
+                    make(OP_JUMP_NOT_TRUTHY_OR_POP, &[20]), // 0011
+                    make(OP_JUMP_TRUTHY_OR_POP, &[20]),     // 0014
+                    make(OP_GET_GLOBAL, &[2]),              // 0017
+                    make(OP_POP, &[]),                      // 0020
+                ],
+            ),
+            // conditions that are always or never truthy
+            (
+                vec![
+                    make(OP_TRUE, &[]),              // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[15]), // 0001
+                    make(OP_GET_GLOBAL, &[0]),       // 0004
+                    make(OP_FALSE, &[]),             // 0007
+                    make(OP_JUMP_NOT_TRUTHY, &[15]), // 0008
+                    make(OP_NULL, &[]),              // 0011
+                    make(OP_JUMP_NOT_TRUTHY, &[4]),  // 0012
+                    make(OP_POP, &[]),               // 0015
+                ],
+                vec![
+                    make(OP_GET_GLOBAL, &[0]), // 0000
+                    make(OP_JUMP, &[9]),       // 0003
+                    make(OP_JUMP, &[0]),       // 0006
+                    make(OP_POP, &[]),         // 0009
+                ],
+            ),
+            // values that are popped right away, even when they're popped after a jump
+            (
+                vec![
+                    make(OP_GET_LOCAL, &[0]),        // 0000
+                    make(OP_POP, &[]),               // 0002
+                    make(OP_CONSTANT, &[1]),         // 0003
+                    make(OP_POP, &[]),               // 0006
+                    make(OP_GET_GLOBAL, &[0]),       // 0007
+                    make(OP_JUMP_NOT_TRUTHY, &[18]), // 0010
+                    make(OP_GET_FREE, &[0]),         // 0013
+                    make(OP_JUMP, &[19]),            // 0015
+                    make(OP_NULL, &[]),              // 0018
+                    make(OP_POP, &[]),               // 0019
+                    make(OP_GET_BUILTIN, &[1]),      // 0020
+                    make(OP_POP, &[]),               // 0022
+                    make(OP_RETURN, &[]),            // 0023
+                ],
+                vec![
+                    make(OP_GET_GLOBAL, &[0]),      // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[7]), // 0003
+                    make(OP_RETURN, &[]),           // 0006
+                    make(OP_RETURN, &[]),           // 0007
+                ],
+            ),
+            // unless the pop is jumped to with another value
+            (
+                vec![
+                    make(OP_GET_GLOBAL, &[0]),             // 0000
+                    make(OP_JUMP_NOT_TRUTHY_OR_POP, &[8]), // 0003
+                    make(OP_GET_LOCAL, &[0]),              // 0006
+                    make(OP_POP, &[]),                     // 0008
+                    make(OP_RETURN, &[]),                  // 0009
+                ],
+                vec![
+                    make(OP_GET_GLOBAL, &[0]),             // 0000
+                    make(OP_JUMP_NOT_TRUTHY_OR_POP, &[8]), // 0003
+                    make(OP_GET_LOCAL, &[0]),              // 0006
+                    make(OP_POP, &[]),                     // 0008
+                    make(OP_RETURN, &[]),                  // 0009
+                ],
+            ),
+            // jumps to returns and to the next instruction
+            (
+                vec![
+                    make(OP_GET_LOCAL, &[0]),        // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[11]), // 0002
+                    make(OP_CONSTANT, &[0]),         // 0005
+                    make(OP_JUMP, &[17]),            // 0008
+                    make(OP_JUMP, &[14]),            // 0011
+                    make(OP_CONSTANT, &[1]),         // 0014
+                    make(OP_RETURN_VALUE, &[]),      // 0017
+                ],
+                vec![
+                    make(OP_GET_LOCAL, &[0]),       // 0000
+                    make(OP_JUMP_NOT_TRUTHY, &[9]), // 0002
+                    make(OP_CONSTANT, &[0]),        // 0005
+                    make(OP_RETURN_VALUE, &[]),     // 0008
+                    make(OP_CONSTANT, &[1]),        // 0009
+                    make(OP_RETURN_VALUE, &[]),     // 0012
+                ],
+            ),
+            // the last instruction stays, as its pop sets the result of the program
+            (
+                vec![
+                    make(OP_CONSTANT, &[0]), // 0000
+                    make(OP_POP, &[]),       // 0003
+                    make(OP_CONSTANT, &[1]), // 0004
+                    make(OP_POP, &[]),       // 0007
+                ],
+                vec![
+                    make(OP_CONSTANT, &[1]), // 0000
+                    make(OP_POP, &[]),       // 0003
+                ],
+            ),
+        ];
+
+        for (input, expected) in test_cases {
+            let input = concat_instructions(input);
+            let (optimized, _) = optimize(&input, &SourceMap::default());
+            assert_eq!(
+                instructions_to_string(&optimized),
+                instructions_to_string(&concat_instructions(expected)),
+                "input:\n{}",
+                instructions_to_string(&input)
+            );
+        }
+    }
+
+    #[test]
+    fn test_peephole_source_map() {
+        let span = |column| Span::new(1, column, column - 1, 1);
+        let input = concat_instructions(vec![
+            make(OP_GET_LOCAL, &[0]),   // 0000
+            make(OP_POP, &[]),          // 0002
+            make(OP_GET_GLOBAL, &[0]),  // 0003
+            make(OP_CONSTANT, &[0]),    // 0006
+            make(OP_ADD, &[]),          // 0009
+            make(OP_RETURN_VALUE, &[]), // 0010
+        ]);
+        let mut source_map = SourceMap::default();
+        source_map.add(0, span(1));
+        source_map.add(3, span(5));
+        source_map.add(9, span(7));
+        source_map.add(10, span(5));
+
+        let (_, optimized) = optimize(&input, &source_map);
+        assert_eq!(
+            optimized.entries(),
+            &[(0, span(5)), (6, span(7)), (7, span(5))]
+        );
+    }
+
+    /// Runs the program with and without the peephole pass, which is the only optimization
+    #[test]
+    fn test_peephole_equivalence() {
+        let test_cases = [
+            "let f = fn(x) { x; if (x > 1) { 10 } else { 20 } }; [f(1), f(2)]",
+            "let g = fn(a, b) { a && b && a || b || !a }; [g(1, 2), g(false, 2), g(0, null)]",
+            "let h = fn(n) { let i = 0; while (true) { i += 1; if (i > n) { break; } } i }; h(3)",
+            "let s = 0; for (v in [1, 2, 3]) { if (v == 2) { continue; } s += v; } s",
+            "let n = 0; while (n < 5 && true) { n += 1; if (false) { break; } } n",
+            "let c = fn() { let x = 1; fn() { x; len; x } }; c()()",
+            "if (true) { 1 } else { 2 }; if (false) { 3 }",
+            "let r = fn(n) { if (n == 0) { return 0; } n + r(n - 1) }; r(10)",
+            "let x = 5; x; 1; true; null",
+            "let f = fn() { let a = 1; a; 1 / 0 }; f()",
+            "let f = fn(x) { if (x) { x + 1 } else { -x } }; f(true)",
+        ];
+
+        for input in test_cases {
+            let mut comp = Compiler::new();
+            comp.compile(parse(input).make_node()).unwrap();
+            let bytecode = comp.byte_code();
+            let optimized = optimize_bytecode(&bytecode);
+            assert_ne!(
+                bytecode.disassemble(),
+                optimized.disassemble(),
+                "input: {}",
+                input
+            );
+
+            let results: Vec<String> = [bytecode, optimized]
+                .into_iter()
+                .map(|bytecode| {
+                    let mut vm = VM::new(bytecode);
+                    match vm.run() {
+                        Ok(_) => vm.result().unwrap().inspect(),
+                        Err(e) => format!("{}: {}", e.span, e),
+                    }
+                })
+                .collect();
+            assert_eq!(results[0], results[1], "input: {}", input);
+        }
+    }
+
+    fn optimize_bytecode(bytecode: &ByteCode) -> ByteCode {
+        let (instructions, source_map) = optimize(&bytecode.instructions, &bytecode.source_map);
+        let constants = bytecode
+            .constants
+            .iter()
+            .map(|constant| match constant {
+                AllObjects::CompiledFunction(func) => {
+                    let mut func = func.clone();
+                    (func.instructions, func.source_map) =
+                        optimize(&func.instructions, &func.source_map);
+                    AllObjects::CompiledFunction(func)
+                }
+                constant => constant.clone(),
+            })
+            .collect();
+
+        ByteCode {
+            instructions,
+            constants,
+            source_map,
+        }
+    }
+}
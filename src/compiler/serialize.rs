@@ -0,0 +1,560 @@
+use super::ByteCode;
+use crate::{
+    code::{self, FunctionSite, Instructions, SourceMap},
+    errors::LoadError,
+    lexer::token::Span,
+    object::{
+        bigint::BigInt,
+        objects::{CompiledFunctionObj, Float, FreeVariable, Integer, StringObj},
+        AllObjects,
+    },
+};
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashSet;
+
+/// Every bytecode file starts with these bytes
+pub const MAGIC: &[u8; 4] = b"MKC\0";
+
+/// Version of the bytecode file format, which should be bumped on every incompatible change:
+///
+/// - 3: `OP_GET_ITER` and `OP_ITER_NEXT` for `for` loops
+/// - 4: `OP_JUMP_TRUTHY_OR_POP` and `OP_JUMP_NOT_TRUTHY_OR_POP` for `&&` and `||`
+/// - 5: `OP_MOD` and `OP_GREATER_THAN_OR_EQUAL`
+/// - 6: float constants
+/// - 7: the `OP_WIDE` prefix for operands that don't fit in a byte
+/// - 8: `OP_LESS_THAN` and `OP_LESS_THAN_OR_EQUAL`, instead of swapping the operands
+/// - 9: big integer constants
+/// - 10: names and source maps of the functions that share a constant with an identical one
+pub const FORMAT_VERSION: u16 = 10;
+
+// Tags that precede each constant in the constant pool
+const TAG_INTEGER: u8 = 1;
+const TAG_STRING: u8 = 2;
+const TAG_COMPILED_FUNCTION: u8 = 3;
+const TAG_FLOAT: u8 = 4;
+const TAG_BIG_INTEGER: u8 = 5;
+
+// Tags that precede each free variable of a compiled function
+const TAG_FREE_LOCAL: u8 = 1;
+const TAG_FREE_FREE: u8 = 2;
+const TAG_FREE_CURRENT_CLOSURE: u8 = 3;
+
+impl ByteCode {
+    /// Serializes the bytecode into the `.mkc` file format.
+    ///
+    /// The file starts with the magic header and the format version, followed by the main
+    /// instructions with their source map and the constant pool. All numbers are big-endian and
+    /// lengths are written as u32 values before the data they describe.
+    ///
+    /// Only integers, floats, strings and compiled functions can be serialized, as these are the only
+    /// objects the compiler puts in the constant pool. Big integers are written as decimal strings.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(MAGIC);
+        w.u16(FORMAT_VERSION);
+        w.instructions(&self.instructions);
+        w.source_map(&self.source_map);
+
+        w.len(self.constants.len());
+        for constant in &self.constants {
+            w.constant(constant);
+        }
+
+        w.buf
+    }
+
+    /// Loads bytecode that was serialized with [`ByteCode::serialize`].
+    ///
+    /// Apart from the format itself, the instructions are validated so that the VM doesn't run
+    /// into undefined opcodes, missing constants or jumps to nowhere.
+    pub fn deserialize(bytes: &[u8]) -> Result<ByteCode, LoadError> {
+        let mut r = Reader { bytes, position: 0 };
+        if r.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(LoadError::InvalidMagic);
+        }
+        let version = r.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion { version });
+        }
+
+        let instructions = r.instructions()?;
+        let source_map = r.source_map()?;
+
+        let num_constants = r.len()?;
+        let mut constants = Vec::new();
+        for _ in 0..num_constants {
+            constants.push(r.constant()?);
+        }
+        if r.position != bytes.len() {
+            return Err(LoadError::TrailingBytes);
+        }
+
+        validate_instructions(&instructions, constants.len())?;
+        for constant in &constants {
+            if let AllObjects::CompiledFunction(func) = constant {
+                validate_instructions(&func.instructions, constants.len())?;
+            }
+        }
+
+        Ok(ByteCode {
+            instructions,
+            constants,
+            source_map,
+        })
+    }
+}
+
+/// Checks that every instruction is complete and that its operands point to existing
+/// constants and to the start of instructions.
+fn validate_instructions(ins: &Instructions, num_constants: usize) -> Result<(), LoadError> {
+    // jumping past the last instruction ends the instruction stream
+    let mut starts = HashSet::from([ins.len()]);
+    let mut jumps = Vec::new();
+
+    let mut offset = 0;
+    while offset < ins.len() {
+        starts.insert(offset);
+
+        // the opcode after an `OP_WIDE` prefix, which can't be another prefix
+        let opcode_offset = offset + usize::from(ins[offset] == code::OP_WIDE);
+        let Some(&opcode) = ins.get(opcode_offset) else {
+            return Err(LoadError::TruncatedInstruction { offset });
+        };
+        let Ok((_, def, start)) = code::lookup_instruction(ins, offset) else {
+            return Err(LoadError::UnknownOpcode { opcode, offset });
+        };
+
+        let end = start + def.operand_widths.iter().sum::<usize>();
+        if end > ins.len() {
+            return Err(LoadError::TruncatedInstruction { offset });
+        }
+
+        match opcode {
+            code::OP_CONSTANT | code::OP_CLOSURE => {
+                let index = code::helpers::read_u16(&ins[start..]);
+                if index >= num_constants {
+                    return Err(LoadError::ConstantOutOfRange { index, offset });
+                }
+            }
+            op if code::is_jump(op) => {
+                let target = code::helpers::read_u16(&ins[start..]);
+                if target > ins.len() {
+                    return Err(LoadError::JumpOutOfRange { target, offset });
+                }
+                jumps.push((target, offset));
+            }
+            _ => {}
+        }
+
+        offset = end;
+    }
+
+    // targets can only be checked once every instruction start is known
+    for (target, offset) in jumps {
+        if !starts.contains(&target) {
+            return Err(LoadError::JumpIntoInstruction { target, offset });
+        }
+    }
+
+    Ok(())
+}
+
+/// Appends the big-endian encoding of values to a buffer
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.bytes(&v.to_be_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_be_bytes());
+    }
+
+    fn i64(&mut self, v: i64) {
+        self.bytes(&v.to_be_bytes());
+    }
+
+    fn f64(&mut self, v: f64) {
+        self.bytes(&v.to_be_bytes());
+    }
+
+    /// lengths, indexes and offsets are far below u32::MAX as they are bound by the u16 operands
+    fn len(&mut self, v: usize) {
+        self.u32(u32::try_from(v).unwrap());
+    }
+
+    fn string(&mut self, v: &str) {
+        self.len(v.len());
+        self.bytes(v.as_bytes());
+    }
+
+    fn instructions(&mut self, ins: &Instructions) {
+        self.len(ins.len());
+        self.bytes(ins);
+    }
+
+    fn source_map(&mut self, source_map: &SourceMap) {
+        self.len(source_map.entries().len());
+        for (offset, span) in source_map.entries() {
+            self.len(*offset);
+            self.len(span.line);
+            self.len(span.column);
+            self.len(span.offset);
+            self.len(span.len);
+        }
+
+        self.len(source_map.functions().len());
+        for (offset, site) in source_map.functions() {
+            self.len(*offset);
+            self.string(&site.name);
+            self.source_map(&site.source_map);
+        }
+    }
+
+    fn constant(&mut self, obj: &AllObjects) {
+        match obj {
+            AllObjects::Integer(v) => {
+                self.u8(TAG_INTEGER);
+                self.i64(v.value);
+            }
+            AllObjects::BigInteger(v) => {
+                self.u8(TAG_BIG_INTEGER);
+                self.string(&v.value.to_string());
+            }
+            AllObjects::Float(v) => {
+                self.u8(TAG_FLOAT);
+                self.f64(v.value);
+            }
+            AllObjects::StringObj(v) => {
+                self.u8(TAG_STRING);
+                self.string(&v.value);
+            }
+            AllObjects::CompiledFunction(func) => {
+                self.u8(TAG_COMPILED_FUNCTION);
+                self.len(func.num_args);
+                self.len(func.num_locals);
+                self.string(&func.name);
+
+                self.len(func.free_variables.len());
+                for free_variable in &func.free_variables {
+                    let (tag, index) = match *free_variable {
+                        FreeVariable::Local(index) => (TAG_FREE_LOCAL, index),
+                        FreeVariable::Free(index) => (TAG_FREE_FREE, index),
+                        FreeVariable::CurrentClosure => (TAG_FREE_CURRENT_CLOSURE, 0),
+                    };
+                    self.u8(tag);
+                    self.len(index);
+                }
+
+                self.instructions(&func.instructions);
+                self.source_map(&func.source_map);
+            }
+            _ => unreachable!("the compiler doesn't add other objects to the constant pool"),
+        }
+    }
+}
+
+/// Reads big-endian values from a buffer, failing if the buffer ends before the value
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], LoadError> {
+        let end = self
+            .position
+            .checked_add(n)
+            .ok_or(LoadError::UnexpectedEof)?;
+        let Some(v) = self.bytes.get(self.position..end) else {
+            return Err(LoadError::UnexpectedEof);
+        };
+        self.position = end;
+        Ok(v)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        Ok(BigEndian::read_u16(self.bytes(2)?))
+    }
+
+    fn i64(&mut self) -> Result<i64, LoadError> {
+        Ok(BigEndian::read_i64(self.bytes(8)?))
+    }
+
+    fn f64(&mut self) -> Result<f64, LoadError> {
+        Ok(BigEndian::read_f64(self.bytes(8)?))
+    }
+
+    fn len(&mut self) -> Result<usize, LoadError> {
+        Ok(BigEndian::read_u32(self.bytes(4)?) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let len = self.len()?;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| LoadError::InvalidUtf8)
+    }
+
+    fn instructions(&mut self) -> Result<Instructions, LoadError> {
+        let len = self.len()?;
+        Ok(self.bytes(len)?.to_vec())
+    }
+
+    fn source_map(&mut self) -> Result<SourceMap, LoadError> {
+        let num_entries = self.len()?;
+        let mut source_map = SourceMap::default();
+        for _ in 0..num_entries {
+            let offset = self.len()?;
+            let span = Span::new(self.len()?, self.len()?, self.len()?, self.len()?);
+            source_map.add(offset, span);
+        }
+
+        let num_functions = self.len()?;
+        for _ in 0..num_functions {
+            let offset = self.len()?;
+            let name = self.string()?;
+            let site_source_map = self.source_map()?;
+            source_map.add_function(
+                offset,
+                FunctionSite {
+                    name,
+                    source_map: site_source_map,
+                },
+            );
+        }
+        Ok(source_map)
+    }
+
+    fn constant(&mut self) -> Result<AllObjects, LoadError> {
+        let obj = match self.u8()? {
+            TAG_INTEGER => AllObjects::Integer(Integer { value: self.i64()? }),
+            TAG_BIG_INTEGER => {
+                let digits = self.string()?;
+                let value =
+                    BigInt::parse(&digits).ok_or(LoadError::InvalidBigInteger { digits })?;
+                AllObjects::from_big_int(value)
+            }
+            TAG_FLOAT => AllObjects::Float(Float { value: self.f64()? }),
+            TAG_STRING => AllObjects::StringObj(StringObj::new(&self.string()?)),
+            TAG_COMPILED_FUNCTION => {
+                let num_args = self.len()?;
+                let num_locals = self.len()?;
+                let name = self.string()?;
+
+                let num_free = self.len()?;
+                let mut free_variables = Vec::new();
+                for _ in 0..num_free {
+                    let free_variable = match self.u8()? {
+                        TAG_FREE_LOCAL => FreeVariable::Local(self.len()?),
+                        TAG_FREE_FREE => FreeVariable::Free(self.len()?),
+                        TAG_FREE_CURRENT_CLOSURE => {
+                            self.len()?;
+                            FreeVariable::CurrentClosure
+                        }
+                        tag => return Err(LoadError::UnknownFreeVariableTag { tag }),
+                    };
+                    free_variables.push(free_variable);
+                }
+
+                let mut func = CompiledFunctionObj::new(self.instructions()?, num_args);
+                func.num_locals = num_locals;
+                func.name = name;
+                func.free_variables = free_variables;
+                func.source_map = self.source_map()?;
+                AllObjects::CompiledFunction(func)
+            }
+            tag => return Err(LoadError::UnknownConstantTag { tag }),
+        };
+        Ok(obj)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ByteCode, FORMAT_VERSION, MAGIC, TAG_BIG_INTEGER};
+    use crate::{
+        code::{make, OP_CONSTANT, OP_GET_GLOBAL, OP_GET_LOCAL, OP_JUMP, OP_WIDE},
+        compiler::{test_helpers::*, Compiler},
+        errors::LoadError,
+        object::AllObjects,
+        vm::VM,
+    };
+
+    fn compile(input: &str) -> ByteCode {
+        let program = parse(input);
+        let mut comp = Compiler::new();
+        comp.compile(program.make_node()).unwrap();
+        comp.byte_code()
+    }
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let input = r#"
+            let greeting = "héllo";
+            let counter = fn(start) {
+                let count = start;
+                let rec = fn(n) { if (n > 0) { count = count + 1; rec(n - 1) } else { count } };
+                rec
+            };
+            [greeting, counter(-40)(2), 2.5, 99999999999999999999 - 99999999999999999998]
+        "#;
+        let bytecode = compile(input);
+        let loaded = ByteCode::deserialize(&bytecode.serialize()).unwrap();
+
+        assert_eq!(loaded.instructions, bytecode.instructions);
+        assert_eq!(loaded.source_map, bytecode.source_map);
+        assert_eq!(loaded.constants.len(), bytecode.constants.len());
+        for (got, want) in loaded.constants.iter().zip(&bytecode.constants) {
+            match (got, want) {
+                (AllObjects::CompiledFunction(got), AllObjects::CompiledFunction(want)) => {
+                    assert_eq!(got.instructions, want.instructions);
+                    assert_eq!(got.num_args, want.num_args);
+                    assert_eq!(got.num_locals, want.num_locals);
+                    assert_eq!(got.name, want.name);
+                    assert_eq!(got.free_variables, want.free_variables);
+                    assert_eq!(got.source_map, want.source_map);
+                }
+                _ => assert!(got == want),
+            }
+        }
+
+        let mut vm = VM::new(loaded);
+        vm.run().unwrap();
+        test_expected_object(
+            Literal::Arr(vec![
+                Literal::Str("héllo"),
+                Literal::Int(-38),
+                Literal::Float(2.5),
+                Literal::Int(1),
+            ]),
+            vm.result().unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_invalid_bytecode_files() {
+        let valid = compile("let x = 1; x + 2").serialize();
+
+        let mut newer_version = valid.clone();
+        newer_version[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_be_bytes());
+
+        // files of older versions may use opcodes and constants that mean something else now
+        let mut older_version = valid.clone();
+        older_version[4..6].copy_from_slice(&(FORMAT_VERSION - 1).to_be_bytes());
+
+        let mut trailing = valid.clone();
+        trailing.push(0);
+
+        // a main instruction stream of a single constant instruction that points past the pool
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        let with_instructions = |ins: Vec<u8>| {
+            let mut file = header.clone();
+            file.extend_from_slice(&(ins.len() as u32).to_be_bytes());
+            file.extend_from_slice(&ins);
+            file.extend_from_slice(&[0; 12]); // no source map entries or functions, no constants
+            file
+        };
+
+        // a single big integer constant that isn't a number
+        let mut invalid_big_integer = with_instructions(vec![]);
+        invalid_big_integer.truncate(invalid_big_integer.len() - 4);
+        invalid_big_integer.extend_from_slice(&[0, 0, 0, 1, TAG_BIG_INTEGER, 0, 0, 0, 3]);
+        invalid_big_integer.extend_from_slice(b"12a");
+
+        let test_cases = [
+            (b"MK".to_vec(), LoadError::InvalidMagic),
+            (b"#!/usr/bin/env monkey".to_vec(), LoadError::InvalidMagic),
+            (
+                newer_version,
+                LoadError::UnsupportedVersion {
+                    version: FORMAT_VERSION + 1,
+                },
+            ),
+            (
+                older_version,
+                LoadError::UnsupportedVersion {
+                    version: FORMAT_VERSION - 1,
+                },
+            ),
+            (valid[..valid.len() - 3].to_vec(), LoadError::UnexpectedEof),
+            (trailing, LoadError::TrailingBytes),
+            (
+                invalid_big_integer,
+                LoadError::InvalidBigInteger {
+                    digits: "12a".to_string(),
+                },
+            ),
+            (
+                with_instructions(make(OP_CONSTANT, &[0])),
+                LoadError::ConstantOutOfRange {
+                    index: 0,
+                    offset: 0,
+                },
+            ),
+            (
+                with_instructions(make(OP_JUMP, &[10])),
+                LoadError::JumpOutOfRange {
+                    target: 10,
+                    offset: 0,
+                },
+            ),
+            (
+                with_instructions([make(OP_JUMP, &[5]), make(OP_GET_GLOBAL, &[1])].concat()),
+                LoadError::JumpIntoInstruction {
+                    target: 5,
+                    offset: 0,
+                },
+            ),
+            (
+                with_instructions(vec![200]),
+                LoadError::UnknownOpcode {
+                    opcode: 200,
+                    offset: 0,
+                },
+            ),
+            (
+                with_instructions(vec![OP_CONSTANT, 0]),
+                LoadError::TruncatedInstruction { offset: 0 },
+            ),
+            (
+                with_instructions(vec![OP_WIDE]),
+                LoadError::TruncatedInstruction { offset: 0 },
+            ),
+            (
+                with_instructions(vec![OP_WIDE, OP_GET_LOCAL, 1]),
+                LoadError::TruncatedInstruction { offset: 0 },
+            ),
+            (
+                with_instructions(vec![OP_WIDE, 200]),
+                LoadError::UnknownOpcode {
+                    opcode: 200,
+                    offset: 0,
+                },
+            ),
+        ];
+
+        for (bytes, want) in test_cases {
+            match ByteCode::deserialize(&bytes) {
+                Ok(_) => panic!("expected {:?}", want),
+                Err(e) => assert_eq!(e, want),
+            }
+        }
+    }
+}
@@ -62,6 +62,11 @@ impl SymbolTable {
         self.table.borrow_mut().define(name, self.outer.is_some())
     }
 
+    /// Returns the number of symbols defined with `define`, which are the locals of a function
+    pub fn num_definitions(&self) -> usize {
+        self.table.borrow().num_definitions
+    }
+
     /// Defines builtin functions in the BUILTIN_SCOPE
     pub fn define_builtin(&self, index: usize, name: &str) -> Symbol {
         let symbol = Symbol::new(name, BUILTIN_SCOPE, index);
@@ -86,24 +91,18 @@ impl SymbolTable {
     ///
     /// It will also set the free variables, if found.
     pub fn resolve(&self, name: &str) -> Option<Symbol> {
-        let mut obj = self.table.borrow().store.get(name).cloned();
-
-        if obj.is_none() && self.outer.is_some() {
-            obj = self.outer.as_ref().unwrap().resolve(name);
-            if obj.is_none() {
-                return obj;
-            }
-
-            let scope = obj.as_ref().unwrap().scope;
-            if scope == GLOBAL_SCOPE || scope == BUILTIN_SCOPE {
-                return obj;
-            }
+        let obj = self.table.borrow().store.get(name).cloned();
+        if obj.is_some() {
+            return obj;
+        }
 
-            let free = self.define_free(obj.unwrap());
-            return Some(free);
+        let obj = self.outer.as_ref()?.resolve(name)?;
+        if obj.scope == GLOBAL_SCOPE || obj.scope == BUILTIN_SCOPE {
+            return Some(obj);
         }
 
-        obj
+        let free = self.define_free(obj);
+        Some(free)
     }
 
     /// Defines a free variable in the symbol-table's free variable holder
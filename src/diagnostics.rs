@@ -0,0 +1,230 @@
+use crate::lexer::token::Span;
+use std::fmt::{self, Display};
+
+/// An error attached to the location in the source code that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic<E> {
+    pub error: E,
+    pub span: Span,
+}
+
+impl<E: Display> Diagnostic<E> {
+    pub fn new(error: E, span: Span) -> Self {
+        Self { error, span }
+    }
+
+    /// Renders the diagnostic as `name:line:column: message`, followed by the offending source
+    /// line with the span underlined by carets.
+    ///
+    /// Only the location and the message are rendered if the span is unknown (line 0) or does not
+    /// belong to the given source.
+    pub fn render(&self, source_name: &str, source: &str) -> String {
+        if self.span.line == 0 {
+            return format!("{}: {}\n", source_name, self.error);
+        }
+
+        let mut out = format!("{}:{}: {}\n", source_name, self.span, self.error);
+        let Some(line) = source.lines().nth(self.span.line - 1) else {
+            return out;
+        };
+
+        // keep tabs in the padding, so that the carets line up with the source line
+        let padding: String = line
+            .chars()
+            .take(self.span.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let underlined = source
+            .get(self.span.offset..self.span.offset + self.span.len)
+            .unwrap_or_default();
+        let num_carets = underlined.chars().take_while(|c| *c != '\n').count().max(1);
+
+        let line_number = self.span.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{} | {}\n", line_number, line));
+        out.push_str(&format!(
+            "{} | {}{}\n",
+            gutter,
+            padding,
+            "^".repeat(num_carets)
+        ));
+
+        out
+    }
+}
+
+impl<E: Display> Display for Diagnostic<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for Diagnostic<E> {}
+
+/// A function call that was in progress when a runtime error was raised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    /// name of the called function
+    pub function: String,
+
+    /// offset of the instruction being executed in the instructions of the function
+    pub offset: usize,
+
+    /// location of the instruction being executed, if it's known
+    pub span: Option<Span>,
+}
+
+/// The function calls that were in progress when a runtime error was raised, with the most
+/// recent call last.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Traceback {
+    pub frames: Vec<TraceFrame>,
+}
+
+impl Traceback {
+    /// Renders the traceback with a line for each call, followed by the source line of the call
+    /// when its location is known. Consecutive calls from the same place, like the ones of a
+    /// runaway recursion, are collapsed into a single line.
+    pub fn render(&self, source_name: &str, source: &str) -> String {
+        let mut out = String::from("Traceback (most recent call last):\n");
+        let mut repeated = 0;
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i > 0 && self.frames[i - 1] == *frame {
+                repeated += 1;
+                continue;
+            }
+            if repeated > 0 {
+                out.push_str(&format!(
+                    "  [previous call repeated {} more times]\n",
+                    repeated
+                ));
+                repeated = 0;
+            }
+
+            let Some(span) = frame.span else {
+                out.push_str(&format!(
+                    "  {}, in {} at offset {:04}\n",
+                    source_name, frame.function, frame.offset
+                ));
+                continue;
+            };
+
+            out.push_str(&format!(
+                "  {}:{}, in {} at offset {:04}\n",
+                source_name, span, frame.function, frame.offset
+            ));
+            if let Some(line) = source.lines().nth(span.line.saturating_sub(1)) {
+                out.push_str(&format!("    {}\n", line.trim()));
+            }
+        }
+        if repeated > 0 {
+            out.push_str(&format!(
+                "  [previous call repeated {} more times]\n",
+                repeated
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, TraceFrame, Traceback};
+    use crate::lexer::token::Span;
+
+    #[test]
+    fn test_render() {
+        let source = "let x = 1;\nlet y = foo(1, 2;\n";
+        let diagnostic = Diagnostic::new("expected ')', found ';'", Span::new(2, 17, 27, 1));
+
+        let expected = "script.mk:2:17: expected ')', found ';'
+  |
+2 | let y = foo(1, 2;
+  |                 ^
+";
+        assert_eq!(diagnostic.render("script.mk", source), expected);
+    }
+
+    #[test]
+    fn test_render_multi_character_span() {
+        let source = "\tlet héllo = undefined_var;";
+        let diagnostic = Diagnostic::new("undefined variable", Span::new(1, 14, 14, 13));
+
+        let expected = "main.mk:1:14: undefined variable
+  |
+1 | \tlet héllo = undefined_var;
+  | \t            ^^^^^^^^^^^^^
+";
+        assert_eq!(diagnostic.render("main.mk", source), expected);
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let diagnostic = Diagnostic::new("stack overflow", Span::default());
+        assert_eq!(
+            diagnostic.render("main.mk", "1 + 1"),
+            "main.mk: stack overflow\n"
+        );
+    }
+
+    #[test]
+    fn test_render_traceback() {
+        let source = "let f = fn(a) {\n  a - \"x\"\n};\nlet g = fn() { f(1) };\ng();";
+        let traceback = Traceback {
+            frames: vec![
+                TraceFrame {
+                    function: "<main>".to_string(),
+                    offset: 9,
+                    span: Some(Span::new(5, 2, 57, 1)),
+                },
+                TraceFrame {
+                    function: "g".to_string(),
+                    offset: 4,
+                    span: Some(Span::new(4, 17, 45, 1)),
+                },
+                TraceFrame {
+                    function: "f".to_string(),
+                    offset: 4,
+                    span: Some(Span::new(2, 5, 20, 1)),
+                },
+                TraceFrame {
+                    function: "<anonymous>".to_string(),
+                    offset: 12,
+                    span: None,
+                },
+            ],
+        };
+
+        let expected = "Traceback (most recent call last):
+  main.mk:5:2, in <main> at offset 0009
+    g();
+  main.mk:4:17, in g at offset 0004
+    let g = fn() { f(1) };
+  main.mk:2:5, in f at offset 0004
+    a - \"x\"
+  main.mk, in <anonymous> at offset 0012
+";
+        assert_eq!(traceback.render("main.mk", source), expected);
+    }
+
+    #[test]
+    fn test_render_repeated_traceback_frames() {
+        let call = TraceFrame {
+            function: "f".to_string(),
+            offset: 4,
+            span: None,
+        };
+        let mut frames = vec![call.clone(); 4];
+        frames.push(TraceFrame { offset: 8, ..call });
+        let traceback = Traceback { frames };
+
+        let expected = "Traceback (most recent call last):
+  main.mk, in f at offset 0004
+  [previous call repeated 3 more times]
+  main.mk, in f at offset 0008
+";
+        assert_eq!(traceback.render("main.mk", ""), expected);
+    }
+}
@@ -0,0 +1,592 @@
+use crate::{
+    code::MAX_OPERAND,
+    diagnostics::{Diagnostic, Traceback},
+    lexer::token::{Token, TokenType},
+    object::ObjectType,
+};
+use std::{
+    fmt::{self, Display},
+    num::ParseIntError,
+};
+
+/// Errors reported by the parser when the source code is not well-formed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// a token of the given type was expected in place of the found token
+    UnexpectedToken { expected: TokenType, found: Token },
+
+    /// the elements of a list should be followed by either a comma or the closing token
+    UnterminatedList { end: TokenType, found: Token },
+
+    /// the found token cannot start an expression
+    ExpectedExpression { found: Token },
+
+    /// the integer literal is not a valid integer, as literals too large for 64 bits become big
+    /// integers
+    InvalidInteger {
+        literal: String,
+        source: ParseIntError,
+    },
+
+    /// the float literal is too large to be represented as a finite 64-bit float
+    InvalidFloat { literal: String },
+
+    /// the string literal is missing its closing quote
+    UnterminatedString,
+
+    /// the backslash in a string literal is followed by an unknown escape, or a `\u{...}` escape
+    /// that isn't a valid unicode scalar value
+    InvalidEscape { escape: String },
+
+    /// the block comment is missing its closing `*/`, counting the block comments nested in it
+    UnterminatedComment,
+
+    /// the exponent of the float literal has no digits
+    MalformedFloat { literal: String },
+}
+
+impl ParseError {
+    /// Returns the stable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedToken { .. } => "P0001",
+            ParseError::UnterminatedList { .. } => "P0002",
+            ParseError::ExpectedExpression { .. } => "P0003",
+            ParseError::InvalidInteger { .. } => "P0004",
+            ParseError::InvalidFloat { .. } => "P0005",
+            ParseError::UnterminatedString => "P0006",
+            ParseError::InvalidEscape { .. } => "P0007",
+            ParseError::UnterminatedComment => "P0008",
+            ParseError::MalformedFloat { .. } => "P0009",
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedToken { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            ParseError::UnterminatedList { end, found } => {
+                write!(f, "expected ',' or {}, found {}", end, found)
+            }
+            ParseError::ExpectedExpression { found } => {
+                write!(f, "expected an expression, found {}", found)
+            }
+            ParseError::InvalidInteger { literal, source } => {
+                write!(f, "could not parse {} as integer: {}", literal, source)
+            }
+            ParseError::InvalidFloat { literal } => {
+                write!(f, "could not parse {} as float: number too large", literal)
+            }
+            ParseError::UnterminatedString => write!(f, "unterminated string"),
+            ParseError::InvalidEscape { escape } => {
+                write!(f, "invalid escape sequence `{}` in string", escape)
+            }
+            ParseError::UnterminatedComment => write!(f, "unterminated block comment"),
+            ParseError::MalformedFloat { literal } => {
+                write!(
+                    f,
+                    "malformed float literal {}: the exponent has no digits",
+                    literal
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Errors reported by the compiler for programs that are well-formed, but cannot be compiled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    /// a node of the AST is missing one of its parts, e.g. an infix expression without a right side
+    MissingExpression {
+        node: &'static str,
+        part: &'static str,
+    },
+
+    /// the identifier doesn't refer to a variable in any of the enclosing scopes
+    UndefinedVariable { name: String },
+
+    /// the infix operator is not known to the compiler
+    UnknownInfixOperator { operator: String },
+
+    /// the prefix operator is not known to the compiler
+    UnknownPrefixOperator { operator: String },
+
+    /// assignment to a variable that was never declared with `let`
+    AssignmentToUndeclared { name: String },
+
+    /// assignment to one of the builtin functions
+    AssignmentToBuiltin { name: String },
+
+    /// assignment to the name of the function that is being defined
+    AssignmentToFunction { name: String },
+
+    /// `break` or `continue` that is not inside a loop
+    OutsideLoop { keyword: &'static str },
+
+    /// `break` or `continue` inside a function that is defined in a loop, but not inside a loop of
+    /// its own
+    LoopAcrossFunction { keyword: &'static str },
+
+    /// an operand of an instruction is too large to be encoded in the bytecode, like the index of
+    /// a local in a function with too many locals or a jump in a program that is too long
+    OperandOverflow { operand: &'static str, value: usize },
+}
+
+impl CompileError {
+    /// Returns the stable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompileError::MissingExpression { .. } => "C0001",
+            CompileError::UndefinedVariable { .. } => "C0002",
+            CompileError::UnknownInfixOperator { .. } => "C0003",
+            CompileError::UnknownPrefixOperator { .. } => "C0004",
+            CompileError::AssignmentToUndeclared { .. } => "C0005",
+            CompileError::AssignmentToBuiltin { .. } => "C0006",
+            CompileError::AssignmentToFunction { .. } => "C0007",
+            CompileError::OutsideLoop { .. } => "C0008",
+            CompileError::LoopAcrossFunction { .. } => "C0009",
+            CompileError::OperandOverflow { .. } => "C0010",
+        }
+    }
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::MissingExpression { node, part } => {
+                write!(f, "{} should contain {}", node, part)
+            }
+            CompileError::UndefinedVariable { name } => write!(f, "undefined variable {}", name),
+            CompileError::UnknownInfixOperator { operator } => {
+                write!(f, "unknown arithmetic operator: {}", operator)
+            }
+            CompileError::UnknownPrefixOperator { operator } => {
+                write!(f, "unknown prefix expression: {}", operator)
+            }
+            CompileError::AssignmentToUndeclared { name } => {
+                write!(f, "variable with name {}, not found", name)
+            }
+            CompileError::AssignmentToBuiltin { name } => {
+                write!(f, "cannot assign to builtin function {}", name)
+            }
+            CompileError::AssignmentToFunction { name } => {
+                write!(f, "cannot assign to function {}", name)
+            }
+            CompileError::OutsideLoop { keyword } => write!(f, "{} outside of a loop", keyword),
+            CompileError::LoopAcrossFunction { keyword } => {
+                write!(f, "{} cannot cross a function boundary", keyword)
+            }
+            CompileError::OperandOverflow { operand, value } => write!(
+                f,
+                "{} {} is larger than the maximum of {}",
+                operand, value, MAX_OPERAND
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Errors raised by the VM while executing bytecode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// the stack has no room for another object
+    StackOverflow,
+
+    /// an object was expected on the stack, but the stack is empty
+    StackUnderflow,
+
+    /// strings only support concatenation
+    UnsupportedStringOperation,
+
+    /// arithmetic operations are only supported between two strings or two numbers, which are
+    /// integers or floats
+    UnsupportedArithmeticOperands { left: ObjectType, right: ObjectType },
+
+    /// comparisons are only supported between objects of the same type
+    MismatchedOperands { left: ObjectType, right: ObjectType },
+
+    /// the operand of the prefix minus is not a number
+    InvalidNegationOperand { found: String },
+
+    /// a 64-bit integer is required, e.g. for a length, but the value doesn't fit in one
+    IntegerOverflow,
+
+    /// the bytecode refers to a constant that isn't in the constant pool
+    ConstantNotFound { index: usize },
+
+    /// the bytecode refers to a global, local or captured variable that doesn't exist
+    VariableNotFound { index: usize },
+
+    /// the bytecode refers to a free variable the current closure doesn't have
+    FreeVariableNotFound { index: usize },
+
+    /// the bytecode refers to a builtin function that doesn't exist
+    BuiltinNotFound { index: usize },
+
+    /// only arrays, strings and hash-maps can be indexed
+    NotIndexable { object_type: ObjectType },
+
+    /// arrays and strings should be indexed with an integer
+    InvalidIndex { object_type: ObjectType },
+
+    /// arrays and strings cannot be indexed with a negative integer
+    NegativeIndex { index: i64 },
+
+    /// the index is past the end of the array or the string
+    IndexOutOfBounds { index: usize, length: usize },
+
+    /// only arrays and strings can be sliced
+    NotSliceable { object_type: ObjectType },
+
+    /// slice bounds should be integers
+    InvalidSliceIndex { object_type: ObjectType },
+
+    /// slice bounds cannot be negative
+    NegativeSliceIndex { index: i64 },
+
+    /// the start of the slice is past the end of the sliceable
+    SliceStartOutOfRange { start: usize, length: usize },
+
+    /// the end of the slice is past the end of the sliceable
+    SliceEndOutOfRange { end: usize, length: usize },
+
+    /// the start of the slice is past the end of the slice
+    SliceStartAfterEnd { start: usize, end: usize },
+
+    /// the called object is not a function
+    NotCallable { found: String },
+
+    /// the closure instruction refers to a constant that is not a compiled function
+    NotAFunction { found: String },
+
+    /// a function was called with the wrong number of arguments
+    WrongNumberOfArguments { want: usize, got: usize },
+
+    /// the closure instruction doesn't match the free variables of the function
+    WrongNumberOfFreeVariables { want: usize, got: usize },
+
+    /// a builtin function was called with an argument of an unsupported type
+    UnsupportedArgument {
+        function: &'static str,
+        object_type: ObjectType,
+    },
+
+    /// sleep was called with a negative duration
+    InvalidSleepDuration { seconds: i64 },
+
+    /// a function was called while the maximum number of frames are in progress
+    CallStackOverflow { max_frames: usize },
+
+    /// a `for` loop was given an object that can't be iterated over
+    NotIterable { object_type: ObjectType },
+
+    /// the right side of an integer division or a modulo is zero
+    DivisionByZero,
+
+    /// a value could not be converted to a number, e.g. a string that isn't a number or a float
+    /// that is out of the integer range
+    InvalidConversion { value: String, to: ObjectType },
+}
+
+impl RuntimeError {
+    /// Returns the stable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeError::StackOverflow => "R0001",
+            RuntimeError::StackUnderflow => "R0002",
+            RuntimeError::UnsupportedStringOperation => "R0003",
+            RuntimeError::UnsupportedArithmeticOperands { .. } => "R0004",
+            RuntimeError::MismatchedOperands { .. } => "R0005",
+            RuntimeError::InvalidNegationOperand { .. } => "R0006",
+            RuntimeError::IntegerOverflow => "R0007",
+            RuntimeError::ConstantNotFound { .. } => "R0008",
+            RuntimeError::VariableNotFound { .. } => "R0009",
+            RuntimeError::FreeVariableNotFound { .. } => "R0010",
+            RuntimeError::BuiltinNotFound { .. } => "R0011",
+            RuntimeError::NotIndexable { .. } => "R0012",
+            RuntimeError::InvalidIndex { .. } => "R0013",
+            RuntimeError::NegativeIndex { .. } => "R0014",
+            RuntimeError::IndexOutOfBounds { .. } => "R0015",
+            RuntimeError::NotSliceable { .. } => "R0016",
+            RuntimeError::InvalidSliceIndex { .. } => "R0017",
+            RuntimeError::NegativeSliceIndex { .. } => "R0018",
+            RuntimeError::SliceEndOutOfRange { .. } => "R0019",
+            RuntimeError::SliceStartAfterEnd { .. } => "R0020",
+            RuntimeError::NotCallable { .. } => "R0021",
+            RuntimeError::NotAFunction { .. } => "R0022",
+            RuntimeError::WrongNumberOfArguments { .. } => "R0023",
+            RuntimeError::WrongNumberOfFreeVariables { .. } => "R0024",
+            RuntimeError::UnsupportedArgument { .. } => "R0025",
+            RuntimeError::InvalidSleepDuration { .. } => "R0026",
+            RuntimeError::CallStackOverflow { .. } => "R0027",
+            RuntimeError::NotIterable { .. } => "R0028",
+            RuntimeError::DivisionByZero => "R0029",
+            RuntimeError::InvalidConversion { .. } => "R0030",
+            RuntimeError::SliceStartOutOfRange { .. } => "R0031",
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use RuntimeError::*;
+        match self {
+            StackOverflow => write!(f, "stack overflow"),
+            StackUnderflow => write!(f, "stack is empty"),
+            UnsupportedStringOperation => write!(f, "incorrect operation on strings"),
+            UnsupportedArithmeticOperands { left, right } => write!(
+                f,
+                "arithmetic operations are only supported between strings or numbers, got {} and {}",
+                left, right
+            ),
+            MismatchedOperands { left, right } => write!(
+                f,
+                "left {} and right {} operand types doesn't match",
+                left, right
+            ),
+            InvalidNegationOperand { found } => write!(f, "expected a number, found {}", found),
+            IntegerOverflow => write!(f, "integer overflow"),
+            ConstantNotFound { index } => write!(f, "constant at index {} not found", index),
+            VariableNotFound { index } => write!(f, "variable at index {} not found", index),
+            FreeVariableNotFound { index } => {
+                write!(f, "free variable at index {} not found", index)
+            }
+            BuiltinNotFound { index } => {
+                write!(f, "builtin function with index {} not found", index)
+            }
+            NotIndexable { object_type } => write!(
+                f,
+                "indexing is only supported for arrays, strings and hash-maps, got {}",
+                object_type
+            ),
+            InvalidIndex { object_type } => {
+                write!(f, "index should be an integer, got {}", object_type)
+            }
+            NegativeIndex { .. } => write!(f, "index should be a non-negative integer"),
+            IndexOutOfBounds { index, length } => {
+                write!(f, "index {} out of bounds for length {}", index, length)
+            }
+            NotSliceable { object_type } => write!(
+                f,
+                "range indexing is only supported for arrays and strings, got {}",
+                object_type
+            ),
+            InvalidSliceIndex { object_type } => {
+                write!(f, "slice index should be an integer, got {}", object_type)
+            }
+            NegativeSliceIndex { .. } => {
+                write!(f, "slice index should be a non-negative integer")
+            },
+            SliceStartOutOfRange { start, length } => write!(
+                f,
+                "slice start index {} out of range for length {}",
+                start, length
+            ),
+            SliceEndOutOfRange { end, length } => write!(
+                f,
+                "slice end index {} out of range for length {}",
+                end, length
+            ),
+            SliceStartAfterEnd { start, end } => write!(
+                f,
+                "slice start index {} is greater than end index {}",
+                start, end
+            ),
+            NotCallable { found } => write!(f, "expected a function, found {}", found),
+            NotAFunction { found } => write!(f, "not a function: {}", found),
+            WrongNumberOfArguments { want, got } => {
+                write!(f, "wrong number of arguments: want={}, got={}", want, got)
+            }
+            WrongNumberOfFreeVariables { want, got } => write!(
+                f,
+                "wrong number of free variables: want={}, got={}",
+                want, got
+            ),
+            UnsupportedArgument {
+                function,
+                object_type,
+            } => write!(
+                f,
+                "argument to `{}` not supported, got {}",
+                function, object_type
+            ),
+            InvalidSleepDuration { .. } => write!(f, "sleep only takes a positive integer value"),
+            CallStackOverflow { max_frames } => {
+                write!(f, "call stack overflow, exceeded {} frames", max_frames)
+            }
+            NotIterable { object_type } => write!(
+                f,
+                "iteration is only supported for arrays, strings, hash-maps and ranges, got {}",
+                object_type
+            ),
+            DivisionByZero => write!(f, "division by zero"),
+            InvalidConversion { value, to } => write!(f, "cannot convert {} to {}", value, to),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Errors reported when loading a bytecode file that is malformed or was written by an
+/// incompatible version of the compiler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// the file doesn't start with the magic header of bytecode files
+    InvalidMagic,
+
+    /// the file was written in a format version this loader doesn't understand
+    UnsupportedVersion { version: u16 },
+
+    /// the file ended in the middle of a value
+    UnexpectedEof,
+
+    /// a string in the file is not valid UTF-8
+    InvalidUtf8,
+
+    /// a constant in the constant pool has an unknown type tag
+    UnknownConstantTag { tag: u8 },
+
+    /// a free variable of a function has an unknown type tag
+    UnknownFreeVariableTag { tag: u8 },
+
+    /// the instructions contain an opcode that is not defined
+    UnknownOpcode { opcode: u8, offset: usize },
+
+    /// the instructions end in the middle of an instruction
+    TruncatedInstruction { offset: usize },
+
+    /// an instruction refers to a constant that isn't in the constant pool
+    ConstantOutOfRange { index: usize, offset: usize },
+
+    /// a jump instruction targets an offset outside of the instructions
+    JumpOutOfRange { target: usize, offset: usize },
+
+    /// a jump instruction targets an offset in the middle of an instruction
+    JumpIntoInstruction { target: usize, offset: usize },
+
+    /// a big integer constant is not a valid decimal integer
+    InvalidBigInteger { digits: String },
+
+    /// there are bytes left after the last constant
+    TrailingBytes,
+}
+
+impl LoadError {
+    /// Returns the stable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            LoadError::InvalidMagic => "L0001",
+            LoadError::UnsupportedVersion { .. } => "L0002",
+            LoadError::UnexpectedEof => "L0003",
+            LoadError::InvalidUtf8 => "L0004",
+            LoadError::UnknownConstantTag { .. } => "L0005",
+            LoadError::UnknownFreeVariableTag { .. } => "L0006",
+            LoadError::UnknownOpcode { .. } => "L0007",
+            LoadError::TruncatedInstruction { .. } => "L0008",
+            LoadError::ConstantOutOfRange { .. } => "L0009",
+            LoadError::JumpOutOfRange { .. } => "L0010",
+            LoadError::TrailingBytes => "L0011",
+            LoadError::JumpIntoInstruction { .. } => "L0012",
+            LoadError::InvalidBigInteger { .. } => "L0013",
+        }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use LoadError::*;
+        match self {
+            InvalidMagic => write!(f, "not a bytecode file: invalid magic header"),
+            UnsupportedVersion { version } => {
+                write!(f, "unsupported bytecode format version {}", version)
+            }
+            UnexpectedEof => write!(f, "unexpected end of bytecode file"),
+            InvalidUtf8 => write!(f, "invalid UTF-8 in a string constant"),
+            UnknownConstantTag { tag } => write!(f, "unknown constant tag {}", tag),
+            UnknownFreeVariableTag { tag } => write!(f, "unknown free variable tag {}", tag),
+            UnknownOpcode { opcode, offset } => {
+                write!(f, "unknown opcode {} at offset {}", opcode, offset)
+            }
+            TruncatedInstruction { offset } => {
+                write!(f, "truncated instruction at offset {}", offset)
+            }
+            ConstantOutOfRange { index, offset } => write!(
+                f,
+                "constant index {} out of range at offset {}",
+                index, offset
+            ),
+            JumpOutOfRange { target, offset } => {
+                write!(
+                    f,
+                    "jump target {} out of range at offset {}",
+                    target, offset
+                )
+            }
+            JumpIntoInstruction { target, offset } => write!(
+                f,
+                "jump target {} is in the middle of an instruction at offset {}",
+                target, offset
+            ),
+            InvalidBigInteger { digits } => write!(f, "invalid big integer constant {:?}", digits),
+            TrailingBytes => write!(f, "unexpected data after the constant pool"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Any error that can stop a program from running, located in the source code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// all the syntax errors found in the source code
+    Parse(Vec<Diagnostic<ParseError>>),
+    Compile(Diagnostic<CompileError>),
+
+    /// the runtime error, along with the function calls that led to it
+    Runtime(Diagnostic<RuntimeError>, Traceback),
+}
+
+impl Error {
+    /// Renders the error(s) with the offending source code, see [`Diagnostic::render`]
+    pub fn render(&self, source_name: &str, source: &str) -> String {
+        match self {
+            Error::Parse(errors) => errors
+                .iter()
+                .map(|e| e.render(source_name, source))
+                .collect(),
+            Error::Compile(e) => e.render(source_name, source),
+            Error::Runtime(e, traceback) => {
+                let mut out = traceback.render(source_name, source);
+                out.push_str(&e.render(source_name, source));
+                out
+            }
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(errors) => {
+                let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", messages.join("\n"))
+            }
+            Error::Compile(e) => write!(f, "{}", e),
+            Error::Runtime(e, _) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Diagnostic<CompileError>> for Error {
+    fn from(e: Diagnostic<CompileError>) -> Self {
+        Error::Compile(e)
+    }
+}
@@ -3,6 +3,10 @@ pub const LET: &str = "let";
 pub const IF: &str = "if";
 pub const ELSE: &str = "else";
 pub const WHILE: &str = "while";
+pub const FOR: &str = "for";
+pub const IN: &str = "in";
+pub const BREAK: &str = "break";
+pub const CONTINUE: &str = "continue";
 pub const RETURN: &str = "return";
 pub const TRUE: &str = "true";
 pub const FALSE: &str = "false";
@@ -1,3 +1,6 @@
+use crate::{diagnostics::Diagnostic, errors::ParseError};
+use token::Token;
+
 pub mod keywords;
 pub mod token;
 mod tokenizer;
@@ -15,4 +18,24 @@ pub struct Lexer {
 
     /// current character under examination
     ch: char,
+
+    /// line of the current character (starts from 1)
+    line: usize,
+
+    /// column of the current character in characters (starts from 1)
+    column: usize,
+
+    /// byte offset of the current character
+    offset: usize,
+
+    /// errors in the tokens read so far, such as unterminated strings, which the parser collects
+    /// into its own errors
+    pub errors: Vec<Diagnostic<ParseError>>,
+
+    /// whether the skipped comments are kept in `comments`
+    keep_comments: bool,
+
+    /// the comments skipped so far as `Comment` tokens, which are only kept by a lexer created
+    /// with `Lexer::with_comments`
+    pub comments: Vec<Token>,
 }
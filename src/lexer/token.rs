@@ -1,9 +1,69 @@
 use super::keywords;
+use std::{fmt::Display, hash::Hash};
 
-#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub literal: String,
+    pub span: Span,
+}
+
+// The span only tells where a token was found, so two tokens of the same type and literal are
+// considered equal regardless of their position (e.g. duplicate hash literal keys).
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.token_type == other.token_type && self.literal == other.literal
+    }
+}
+
+impl Eq for Token {}
+
+impl Hash for Token {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.token_type.hash(state);
+        self.literal.hash(state);
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use TokenType::*;
+        match self.token_type {
+            Ident | Int | Float | String | Illegal => {
+                write!(f, "{} `{}`", self.token_type, self.literal)
+            }
+            _ => write!(f, "{}", self.token_type),
+        }
+    }
+}
+
+/// The location of a token in the source code.
+///
+/// Lines and columns are 1-based and counted in characters, while the offset and the length are
+/// in bytes, so that the span can be used to slice the source text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize, offset: usize, len: usize) -> Self {
+        Self {
+            line,
+            column,
+            offset,
+            len,
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
 
 /// Represents the UNICODE null character
@@ -14,9 +74,13 @@ pub enum TokenType {
     Illegal,
     Eof,
 
+    // Comments, which are only kept as trivia and never given to the parser
+    Comment,
+
     // Identifiers + literals
     Ident,
     Int,
+    Float,
     String,
 
     // Operators
@@ -26,10 +90,20 @@ pub enum TokenType {
     Bang,
     Asterisk,
     Slash,
+    Percent,
     Lt,
     Gt,
+    LtEq,
+    GtEq,
     Eq,
     NotEq,
+    And,
+    Or,
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+    PercentAssign,
 
     // Delimiters
     Comma,
@@ -49,12 +123,89 @@ pub enum TokenType {
     If,
     Else,
     While,
+    For,
+    In,
+    Break,
+    Continue,
     Return,
     True,
     False,
     Null,
 }
 
+impl Display for TokenType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use TokenType::*;
+        let out = match self {
+            Illegal => "illegal token",
+            Eof => "end of file",
+            Comment => "comment",
+            Ident => "identifier",
+            Int => "integer",
+            Float => "float",
+            String => "string",
+            Assign => "'='",
+            Plus => "'+'",
+            Minus => "'-'",
+            Bang => "'!'",
+            Asterisk => "'*'",
+            Slash => "'/'",
+            Percent => "'%'",
+            Lt => "'<'",
+            Gt => "'>'",
+            LtEq => "'<='",
+            GtEq => "'>='",
+            Eq => "'=='",
+            NotEq => "'!='",
+            And => "'&&'",
+            Or => "'||'",
+            PlusAssign => "'+='",
+            MinusAssign => "'-='",
+            AsteriskAssign => "'*='",
+            SlashAssign => "'/='",
+            PercentAssign => "'%='",
+            Comma => "','",
+            Semicolon => "';'",
+            Lparen => "'('",
+            Rparen => "')'",
+            Lbrace => "'{'",
+            Rbrace => "'}'",
+            Lbracket => "'['",
+            Rbracket => "']'",
+            Colon => "':'",
+            Function => "'fn'",
+            Let => "'let'",
+            If => "'if'",
+            Else => "'else'",
+            While => "'while'",
+            For => "'for'",
+            In => "'in'",
+            Break => "'break'",
+            Continue => "'continue'",
+            Return => "'return'",
+            True => "'true'",
+            False => "'false'",
+            Null => "'null'",
+        };
+        write!(f, "{}", out)
+    }
+}
+
+impl TokenType {
+    /// Returns the arithmetic operator that a compound assignment (e.g. `+=`) applies
+    pub fn compound_operator(&self) -> Option<&'static str> {
+        use TokenType::*;
+        match self {
+            PlusAssign => Some("+"),
+            MinusAssign => Some("-"),
+            AsteriskAssign => Some("*"),
+            SlashAssign => Some("/"),
+            PercentAssign => Some("%"),
+            _ => None,
+        }
+    }
+}
+
 /// A helper function to return an EOF token for initializing the parser
 pub fn eof_token() -> Token {
     new_token(TokenType::Eof, NULL_CHAR)
@@ -65,6 +216,7 @@ pub fn new_token<T: ToString>(token_type: TokenType, literal: T) -> Token {
     Token {
         token_type,
         literal: literal.to_string(),
+        span: Span::default(),
     }
 }
 
@@ -79,6 +231,10 @@ pub fn look_up_identifier(ident: &str) -> TokenType {
         ELSE => TokenType::Else,
         RETURN => TokenType::Return,
         WHILE => TokenType::While,
+        FOR => TokenType::For,
+        IN => TokenType::In,
+        BREAK => TokenType::Break,
+        CONTINUE => TokenType::Continue,
         TRUE => TokenType::True,
         FALSE => TokenType::False,
         NULL => TokenType::Null,
@@ -1,5 +1,6 @@
 use super::token::*;
 use super::Lexer;
+use crate::{diagnostics::Diagnostic, errors::ParseError};
 
 impl Lexer {
     /// Returns a new Lexer for the given input
@@ -9,6 +10,21 @@ impl Lexer {
             position: 0,
             read_position: 0,
             ch: NULL_CHAR,
+            line: 1,
+            column: 1,
+            offset: 0,
+            errors: Vec::new(),
+            keep_comments: false,
+            comments: Vec::new(),
+        }
+    }
+
+    /// Returns a new Lexer that keeps the comments in the input, for tools that need to reproduce
+    /// the source code such as a formatter
+    pub fn with_comments(input: &str) -> Self {
+        Lexer {
+            keep_comments: true,
+            ..Self::new(input)
         }
     }
 
@@ -20,13 +36,12 @@ impl Lexer {
             self.read_char();
         }
 
-        // skipping whitespace characters
-        while self.ch.is_ascii_whitespace() {
-            self.read_char();
-        }
+        self.skip_whitespace_and_comments();
+
+        let (line, column, start_offset) = (self.line, self.column, self.offset);
 
         // get the matching token
-        let tok = match self.ch {
+        let mut tok = match self.ch {
             '=' => {
                 if self.peek_char() == '=' {
                     self.read_char();
@@ -39,8 +54,8 @@ impl Lexer {
             '(' => new_token(TokenType::Lparen, self.ch),
             ')' => new_token(TokenType::Rparen, self.ch),
             ',' => new_token(TokenType::Comma, self.ch),
-            '+' => new_token(TokenType::Plus, self.ch),
-            '-' => new_token(TokenType::Minus, self.ch),
+            '+' => self.read_operator(TokenType::Plus, TokenType::PlusAssign),
+            '-' => self.read_operator(TokenType::Minus, TokenType::MinusAssign),
             '!' => {
                 if self.peek_char() == '=' {
                     self.read_char();
@@ -49,10 +64,19 @@ impl Lexer {
                     new_token(TokenType::Bang, self.ch)
                 }
             }
-            '*' => new_token(TokenType::Asterisk, self.ch),
-            '/' => new_token(TokenType::Slash, self.ch),
-            '<' => new_token(TokenType::Lt, self.ch),
-            '>' => new_token(TokenType::Gt, self.ch),
+            '&' if self.peek_char() == '&' => {
+                self.read_char();
+                new_token(TokenType::And, "&&")
+            }
+            '|' if self.peek_char() == '|' => {
+                self.read_char();
+                new_token(TokenType::Or, "||")
+            }
+            '*' => self.read_operator(TokenType::Asterisk, TokenType::AsteriskAssign),
+            '/' => self.read_operator(TokenType::Slash, TokenType::SlashAssign),
+            '%' => self.read_operator(TokenType::Percent, TokenType::PercentAssign),
+            '<' => self.read_operator(TokenType::Lt, TokenType::LtEq),
+            '>' => self.read_operator(TokenType::Gt, TokenType::GtEq),
             '{' => new_token(TokenType::Lbrace, self.ch),
             '}' => new_token(TokenType::Rbrace, self.ch),
             '"' => new_token(TokenType::String, self.read_string()),
@@ -66,14 +90,21 @@ impl Lexer {
                     let token_type = look_up_identifier(&identifier);
                     new_token(token_type, &identifier)
                 } else if self.ch.is_ascii_digit() {
-                    let num_literal = self.read_number();
-                    new_token(TokenType::Int, num_literal)
+                    let (token_type, num_literal) = self.read_number();
+                    new_token(token_type, num_literal)
                 } else {
                     new_token(TokenType::Illegal, self.ch)
                 }
             }
         };
 
+        // the current character is the last character of the token
+        let len = match tok.token_type {
+            TokenType::Eof => 0,
+            _ => self.offset + self.ch.len_utf8() - start_offset,
+        };
+        tok.span = Span::new(line, column, start_offset, len);
+
         // read the next character
         self.read_char();
 
@@ -82,6 +113,17 @@ impl Lexer {
 
     /// Sets the next character and advances the position in the input
     fn read_char(&mut self) {
+        // moving past the current character, unless the very first character is being read
+        if self.read_position > 0 && self.position < self.input.len() {
+            if self.ch == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+            self.offset += self.ch.len_utf8();
+        }
+
         if self.read_position >= self.input.len() {
             self.ch = NULL_CHAR;
         } else {
@@ -91,6 +133,67 @@ impl Lexer {
         self.read_position += 1;
     }
 
+    /// Skips whitespace characters, `//` line comments and `/* */` block comments, which can be
+    /// nested
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            while self.ch.is_ascii_whitespace() {
+                self.read_char();
+            }
+            if self.ch != '/' || !matches!(self.peek_char(), '/' | '*') {
+                return;
+            }
+
+            let (line, column, start_offset) = (self.line, self.column, self.offset);
+            let start = self.position;
+            if self.peek_char() == '/' {
+                while !matches!(self.ch, '\n' | NULL_CHAR) {
+                    self.read_char();
+                }
+            } else {
+                self.skip_block_comment(Span::new(line, column, start_offset, 2));
+            }
+
+            if self.keep_comments {
+                let end = self.position.min(self.input.len());
+                let mut tok = new_token(
+                    TokenType::Comment,
+                    self.input[start..end].iter().collect::<String>(),
+                );
+                tok.span = Span::new(line, column, start_offset, self.offset - start_offset);
+                self.comments.push(tok);
+            }
+        }
+    }
+
+    /// Skips a block comment up to the character after its closing `*/`, reporting an error at the
+    /// opening `/*` if the input ends before the comment and the ones nested in it are closed
+    fn skip_block_comment(&mut self, opening: Span) {
+        let mut depth = 0;
+        loop {
+            match (self.ch, self.peek_char()) {
+                ('/', '*') => {
+                    depth += 1;
+                    self.read_char();
+                }
+                ('*', '/') => {
+                    depth -= 1;
+                    self.read_char();
+                }
+                (NULL_CHAR, _) => {
+                    let err = ParseError::UnterminatedComment;
+                    self.errors.push(Diagnostic::new(err, opening));
+                    return;
+                }
+                _ => {}
+            }
+            self.read_char();
+            if depth == 0 {
+                return;
+            }
+        }
+    }
+
     /// Reads an identifier by iteratively calling the read_char method and returns the string literal
     fn read_identifier(&mut self) -> String {
         let current_position = self.position;
@@ -102,46 +205,180 @@ impl Lexer {
             .collect()
     }
 
-    /// Reads a number by iteratively calling the read_char method and returns the number literal
-    fn read_number(&mut self) -> String {
+    /// Reads a number by iteratively calling the read_char method and returns the number literal,
+    /// which is a float if it has a fractional part or an exponent
+    ///
+    /// An exponent without digits is reported as an error, while the literal is still returned as a
+    /// float so that it isn't split into a number and an identifier.
+    fn read_number(&mut self) -> (TokenType, String) {
         let current_position = self.position;
-        while self.peek_char().is_ascii_digit() {
+        let (line, column, offset) = (self.line, self.column, self.offset);
+        let mut token_type = TokenType::Int;
+        self.read_digits();
+
+        if self.peek_char() == '.' && self.peek_nth_char(1).is_ascii_digit() {
+            token_type = TokenType::Float;
             self.read_char();
+            self.read_digits();
         }
-        self.input[current_position..self.read_position]
+
+        let mut malformed = false;
+        if matches!(self.peek_char(), 'e' | 'E') {
+            token_type = TokenType::Float;
+            self.read_char();
+            if matches!(self.peek_char(), '+' | '-') {
+                self.read_char();
+            }
+            malformed = !self.peek_char().is_ascii_digit();
+            self.read_digits();
+        }
+
+        let literal: String = self.input[current_position..self.read_position]
             .iter()
-            .collect()
+            .collect();
+        if malformed {
+            // numbers are ascii, so the length of the literal is its length in bytes
+            let span = Span::new(line, column, offset, literal.len());
+            let err = ParseError::MalformedFloat {
+                literal: literal.clone(),
+            };
+            self.errors.push(Diagnostic::new(err, span));
+        }
+        (token_type, literal)
+    }
+
+    /// Reads the digits that follow the current character
+    fn read_digits(&mut self) {
+        while self.peek_char().is_ascii_digit() {
+            self.read_char();
+        }
     }
 
-    /// Reads a string by iteratively calling the read_char method and returns the string literal
+    /// Reads a string by iteratively calling the read_char method and returns its contents, with
+    /// the escape sequences replaced by the characters they stand for.
+    ///
+    /// A missing closing quote and invalid escapes are reported as errors, while the rest of the
+    /// string is still returned.
     fn read_string(&mut self) -> String {
-        let position = self.position + 1;
+        let opening_quote = Span::new(self.line, self.column, self.offset, 1);
+        let mut value = String::new();
         loop {
             self.read_char();
-            if self.ch == '"' || self.ch == NULL_CHAR {
-                break;
+            match self.ch {
+                '"' => break,
+                NULL_CHAR => {
+                    let err = ParseError::UnterminatedString;
+                    self.errors.push(Diagnostic::new(err, opening_quote));
+                    break;
+                }
+                '\\' => {
+                    if let Some(ch) = self.read_escape() {
+                        value.push(ch);
+                    }
+                }
+                ch => value.push(ch),
             }
         }
-        self.input[position..self.position].iter().collect()
+        value
+    }
+
+    /// Reads the escape sequence that starts with the current backslash and returns the character
+    /// it stands for. The end of the input is left for the string to report as unterminated.
+    fn read_escape(&mut self) -> Option<char> {
+        let start = self.position;
+        let span = Span::new(self.line, self.column, self.offset, 0);
+        let escaped = match self.peek_char() {
+            NULL_CHAR => return None,
+            '"' => '"',
+            '\\' => '\\',
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            'u' => {
+                self.read_char();
+                return self.read_unicode_escape(start, span);
+            }
+            _ => {
+                self.read_char();
+                return self.invalid_escape(start, span);
+            }
+        };
+        self.read_char();
+        Some(escaped)
+    }
+
+    /// Reads the braces of a unicode escape, which follow the current `u`
+    fn read_unicode_escape(&mut self, start: usize, span: Span) -> Option<char> {
+        // a unicode escape has between one and six hex digits in braces, e.g. `\u{1F600}`
+        if self.peek_char() != '{' {
+            return self.invalid_escape(start, span);
+        }
+        self.read_char();
+        let mut code_point = String::new();
+        while self.peek_char().is_ascii_hexdigit() {
+            self.read_char();
+            code_point.push(self.ch);
+        }
+        let closed = self.peek_char() == '}';
+        if closed {
+            self.read_char();
+        }
+
+        let ch = match closed && (1..=6).contains(&code_point.len()) {
+            true => u32::from_str_radix(&code_point, 16)
+                .ok()
+                .and_then(char::from_u32),
+            false => None,
+        };
+        if ch.is_none() {
+            self.invalid_escape(start, span);
+        }
+        ch
+    }
+
+    /// Reports the escape sequence from the given backslash up to the current character
+    fn invalid_escape(&mut self, start: usize, mut span: Span) -> Option<char> {
+        span.len = self.offset + self.ch.len_utf8() - span.offset;
+        let escape = self.input[start..=self.position].iter().collect();
+        let err = ParseError::InvalidEscape { escape };
+        self.errors.push(Diagnostic::new(err, span));
+        None
+    }
+
+    /// Reads an operator that turns into a different operator when it's followed by `=`
+    fn read_operator(&mut self, token_type: TokenType, with_equals: TokenType) -> Token {
+        if self.peek_char() != '=' {
+            return new_token(token_type, self.ch);
+        }
+        let literal = format!("{}=", self.ch);
+        self.read_char();
+        new_token(with_equals, literal)
     }
 
     /// Returns the character corresponding to the read_position
     fn peek_char(&self) -> char {
+        self.peek_nth_char(0)
+    }
+
+    /// Returns the character that is n characters after the read_position
+    fn peek_nth_char(&self, n: usize) -> char {
         self.input
-            .get(self.read_position)
+            .get(self.read_position + n)
             .unwrap_or(&NULL_CHAR)
             .to_owned()
     }
 }
 
-/// Checks if the given character is an ASCII alphabetic character or an _ character
+/// Checks if the given character can start an identifier, which follows the default identifiers
+/// of UAX #31 with the addition of _
 fn is_letter(ch: char) -> bool {
-    ch.is_ascii_alphabetic() || ch == '_'
+    unicode_ident::is_xid_start(ch) || ch == '_'
 }
 
-/// Checks if the given character is an ASCII alphabetic character, _ or a digit
+/// Checks if the given character can continue an identifier, which includes _ and digits
 fn is_letter_or_digit(ch: char) -> bool {
-    ch.is_ascii_alphabetic() || ch == '_' || ch.is_ascii_digit()
+    unicode_ident::is_xid_continue(ch)
 }
 
 #[cfg(test)]
@@ -149,6 +386,51 @@ mod tests {
     use super::Lexer;
     use crate::lexer::token::{TokenType::*, *};
 
+    #[test]
+    fn test_token_spans() {
+        let input = "let x = 5;\n  foo(\"hé\") == 10";
+        let mut l = Lexer::new(input);
+
+        // token type, line, column, byte offset, byte length
+        let test_cases = [
+            (Let, 1, 1, 0, 3),
+            (Ident, 1, 5, 4, 1),
+            (Assign, 1, 7, 6, 1),
+            (Int, 1, 9, 8, 1),
+            (Semicolon, 1, 10, 9, 1),
+            (Ident, 2, 3, 13, 3),
+            (Lparen, 2, 6, 16, 1),
+            (String, 2, 7, 17, 5),
+            (Rparen, 2, 11, 22, 1),
+            (Eq, 2, 13, 24, 2),
+            (Int, 2, 16, 27, 2),
+            (Eof, 2, 18, 29, 0),
+        ];
+
+        for (i, tc) in test_cases.into_iter().enumerate() {
+            let tok = l.next_token();
+            assert_eq!(tc.0, tok.token_type, "tests[{}] - token type wrong", i);
+            assert_eq!(
+                Span::new(tc.1, tc.2, tc.3, tc.4),
+                tok.span,
+                "tests[{}] - span wrong",
+                i
+            );
+        }
+
+        // the span can be used to slice the source text
+        let mut l = Lexer::new(input);
+        let literals: Vec<&str> = std::iter::from_fn(|| {
+            let tok = l.next_token();
+            (tok.token_type != Eof).then(|| &input[tok.span.offset..tok.span.offset + tok.span.len])
+        })
+        .collect();
+        assert_eq!(
+            literals,
+            ["let", "x", "=", "5", ";", "foo", "(", "\"hé\"", ")", "==", "10"]
+        );
+    }
+
     #[test]
     fn test_next_token_for_characters() {
         let input = "=+(){},;";
@@ -192,7 +474,7 @@ mod tests {
                     };
                     let result = add(five, ten);
                     
-                    !-/*5;
+                    !-/ *5;
                 5 < 10 > 5;
                 if (5 < 10) {
                     return true;
@@ -201,6 +483,10 @@ mod tests {
                 }
                 10 == 10; 
                 10 != 9;
+                a && b || c;
+                a <= b >= c % d;
+                x += 1; x -= 1; x *= 1; x /= 1; x %= 1;
+                3.14 * 1e-3;
                 "foobar"
                 "foo bar"
                 [1, 2];
@@ -283,6 +569,44 @@ mod tests {
             new_token(NotEq, "!="),
             new_token(Int, "9"),
             new_token(Semicolon, ';'),
+            new_token(Ident, "a"),
+            new_token(And, "&&"),
+            new_token(Ident, "b"),
+            new_token(Or, "||"),
+            new_token(Ident, "c"),
+            new_token(Semicolon, ';'),
+            new_token(Ident, "a"),
+            new_token(LtEq, "<="),
+            new_token(Ident, "b"),
+            new_token(GtEq, ">="),
+            new_token(Ident, "c"),
+            new_token(Percent, '%'),
+            new_token(Ident, "d"),
+            new_token(Semicolon, ';'),
+            new_token(Ident, "x"),
+            new_token(PlusAssign, "+="),
+            new_token(Int, "1"),
+            new_token(Semicolon, ';'),
+            new_token(Ident, "x"),
+            new_token(MinusAssign, "-="),
+            new_token(Int, "1"),
+            new_token(Semicolon, ';'),
+            new_token(Ident, "x"),
+            new_token(AsteriskAssign, "*="),
+            new_token(Int, "1"),
+            new_token(Semicolon, ';'),
+            new_token(Ident, "x"),
+            new_token(SlashAssign, "/="),
+            new_token(Int, "1"),
+            new_token(Semicolon, ';'),
+            new_token(Ident, "x"),
+            new_token(PercentAssign, "%="),
+            new_token(Int, "1"),
+            new_token(Semicolon, ';'),
+            new_token(Float, "3.14"),
+            new_token(Asterisk, '*'),
+            new_token(Float, "1e-3"),
+            new_token(Semicolon, ';'),
             new_token(String, "foobar"),
             new_token(String, "foo bar"),
             new_token(Lbracket, "["),
@@ -320,4 +644,244 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_number_literals() {
+        let test_cases = [
+            ("42", vec![(Int, "42")]),
+            ("3.14", vec![(Float, "3.14")]),
+            ("0.5e10", vec![(Float, "0.5e10")]),
+            ("1E+3", vec![(Float, "1E+3")]),
+            ("2e-08", vec![(Float, "2e-08")]),
+            // a dot without digits is not part of the number
+            ("1.", vec![(Int, "1"), (Illegal, ".")]),
+            ("1.x", vec![(Int, "1"), (Illegal, "."), (Ident, "x")]),
+            // an exponent without digits is a malformed float
+            ("1e", vec![(Float, "1e")]),
+            ("1e+", vec![(Float, "1e+")]),
+            ("2.5E-x", vec![(Float, "2.5E-"), (Ident, "x")]),
+            ("1.5.2", vec![(Float, "1.5"), (Illegal, "."), (Int, "2")]),
+        ];
+
+        for (input, expected) in test_cases {
+            let mut l = Lexer::new(input);
+            for (token_type, literal) in expected {
+                let tok = l.next_token();
+                assert_eq!(tok.token_type, token_type, "input: {}", input);
+                assert_eq!(tok.literal, literal, "input: {}", input);
+            }
+            assert_eq!(l.next_token().token_type, Eof, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let input = "let café = 変数 + _x1 * ünï2; x·y €  \u{301}a";
+        let mut l = Lexer::new(input);
+
+        let test_cases = [
+            (Let, "let"),
+            (Ident, "café"),
+            (Assign, "="),
+            (Ident, "変数"),
+            (Plus, "+"),
+            (Ident, "_x1"),
+            (Asterisk, "*"),
+            (Ident, "ünï2"),
+            (Semicolon, ";"),
+            // the middle dot can continue an identifier, but neither the euro sign nor a
+            // combining mark can start one
+            (Ident, "x·y"),
+            (Illegal, "€"),
+            (Illegal, "\u{301}"),
+            (Ident, "a"),
+            (Eof, "\0"),
+        ];
+
+        for (i, (token_type, literal)) in test_cases.into_iter().enumerate() {
+            let tok = l.next_token();
+            assert_eq!(tok.token_type, token_type, "tests[{}] - type wrong", i);
+            assert_eq!(tok.literal, literal, "tests[{}] - literal wrong", i);
+        }
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        // input, expected contents
+        let test_cases = [
+            (r#""a\nb""#, "a\nb"),
+            (r#""tab\there""#, "tab\there"),
+            (r#""\r\0""#, "\r\0"),
+            (r#""say \"hi\"""#, "say \"hi\""),
+            (r#""back\\slash\\n""#, "back\\slash\\n"),
+            (r#""\u{48}\u{e9}\u{1F600}""#, "Hé😀"),
+            ("\"multi\nline\"", "multi\nline"),
+        ];
+
+        for (input, expected) in test_cases {
+            let mut l = Lexer::new(input);
+            let tok = l.next_token();
+            assert_eq!(tok.token_type, String, "input: {}", input);
+            assert_eq!(tok.literal, expected, "input: {}", input);
+            assert_eq!(tok.span.len, input.len(), "input: {}", input);
+            assert!(l.errors.is_empty(), "input: {}", input);
+            assert_eq!(l.next_token().token_type, Eof, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_string_errors() {
+        // input, expected contents, expected errors with their locations
+        let test_cases = [
+            (r#""abc"#, "abc", vec!["1:1: unterminated string"]),
+            (r#"x = "abc\"#, "abc", vec!["1:5: unterminated string"]),
+            (
+                r#""a\qb""#,
+                "ab",
+                vec!["1:3: invalid escape sequence `\\q` in string"],
+            ),
+            (
+                r#""\u{110000}\u{d800}""#,
+                "",
+                vec![
+                    "1:2: invalid escape sequence `\\u{110000}` in string",
+                    "1:12: invalid escape sequence `\\u{d800}` in string",
+                ],
+            ),
+            (
+                r#""\u41 \u{} \u{1234567}""#,
+                "41  ",
+                vec![
+                    "1:2: invalid escape sequence `\\u` in string",
+                    "1:7: invalid escape sequence `\\u{}` in string",
+                    "1:12: invalid escape sequence `\\u{1234567}` in string",
+                ],
+            ),
+            (
+                r#""\u{41""#,
+                "",
+                vec!["1:2: invalid escape sequence `\\u{41` in string"],
+            ),
+        ];
+
+        for (input, contents, expected) in test_cases {
+            let mut l = Lexer::new(input);
+            let tok = std::iter::from_fn(|| Some(l.next_token()))
+                .find(|tok| tok.token_type == String)
+                .unwrap();
+            assert_eq!(tok.literal, contents, "input: {}", input);
+
+            let errors: Vec<std::string::String> = l
+                .errors
+                .iter()
+                .map(|e| format!("{}: {}", e.span, e))
+                .collect();
+            assert_eq!(errors, expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let test_cases = [
+            ("1 // one\n// two\n2", vec![(Int, "1"), (Int, "2")]),
+            ("a / b // c", vec![(Ident, "a"), (Slash, "/"), (Ident, "b")]),
+            (
+                "a /= b //",
+                vec![(Ident, "a"), (SlashAssign, "/="), (Ident, "b")],
+            ),
+            (
+                "1 /* one */ + /**/ 2",
+                vec![(Int, "1"), (Plus, "+"), (Int, "2")],
+            ),
+            ("1 /* a /* b */ c */ 2", vec![(Int, "1"), (Int, "2")]),
+            (
+                "1 /* a\n// */ 2 */ 3",
+                vec![
+                    (Int, "1"),
+                    (Int, "2"),
+                    (Asterisk, "*"),
+                    (Slash, "/"),
+                    (Int, "3"),
+                ],
+            ),
+            ("1 /* a **/ 2", vec![(Int, "1"), (Int, "2")]),
+            ("1 // a /* b\n2", vec![(Int, "1"), (Int, "2")]),
+            ("\"// a /* b\"", vec![(String, "// a /* b")]),
+        ];
+
+        for (input, expected) in test_cases {
+            let mut l = Lexer::new(input);
+            for (token_type, literal) in expected {
+                let tok = l.next_token();
+                assert_eq!(tok.token_type, token_type, "input: {}", input);
+                assert_eq!(tok.literal, literal, "input: {}", input);
+            }
+            assert_eq!(l.next_token().token_type, Eof, "input: {}", input);
+            assert!(l.errors.is_empty(), "input: {}", input);
+            assert!(l.comments.is_empty(), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_kept_comments() {
+        let input = "// é\nx /* a /* b */\n*/ + 1 //";
+        let mut l = Lexer::with_comments(input);
+        while l.next_token().token_type != Eof {}
+
+        let comments: Vec<(&str, std::string::String)> = l
+            .comments
+            .iter()
+            .map(|tok| {
+                assert_eq!(tok.token_type, Comment);
+                let text = &input[tok.span.offset..tok.span.offset + tok.span.len];
+                assert_eq!(tok.literal, text);
+                (text, tok.span.to_string())
+            })
+            .collect();
+        let expected = [("// é", "1:1"), ("/* a /* b */\n*/", "2:3"), ("//", "3:8")];
+        let expected: Vec<(&str, std::string::String)> = expected
+            .iter()
+            .map(|(text, location)| (*text, location.to_string()))
+            .collect();
+        assert_eq!(comments, expected);
+    }
+
+    #[test]
+    fn test_unterminated_comments() {
+        // input, expected tokens, expected errors with their locations
+        let test_cases = [
+            (
+                "1 /* a",
+                vec![(Int, "1")],
+                vec!["1:3: unterminated block comment"],
+            ),
+            (
+                "1 /*/",
+                vec![(Int, "1")],
+                vec!["1:3: unterminated block comment"],
+            ),
+            (
+                "x\n  /* a /* b */ c",
+                vec![(Ident, "x")],
+                vec!["2:3: unterminated block comment"],
+            ),
+        ];
+
+        for (input, expected, expected_errors) in test_cases {
+            let mut l = Lexer::new(input);
+            for (token_type, literal) in expected {
+                let tok = l.next_token();
+                assert_eq!(tok.token_type, token_type, "input: {}", input);
+                assert_eq!(tok.literal, literal, "input: {}", input);
+            }
+            assert_eq!(l.next_token().token_type, Eof, "input: {}", input);
+
+            let errors: Vec<std::string::String> = l
+                .errors
+                .iter()
+                .map(|e| format!("{}: {}", e.span, e))
+                .collect();
+            assert_eq!(errors, expected_errors, "input: {}", input);
+        }
+    }
 }
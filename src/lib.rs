@@ -1,21 +1,258 @@
 mod ast;
 mod code;
 mod compiler;
+mod diagnostics;
+mod errors;
 mod lexer;
 mod object;
 mod parser;
 mod repl;
 mod vm;
 
-pub use repl::{execute_program, start_repl};
-use std::{error::Error, fs, io::Write};
+pub use compiler::ByteCode;
+pub use diagnostics::{Diagnostic, TraceFrame, Traceback};
+pub use errors::{CompileError, Error, LoadError, ParseError, RuntimeError};
+pub use lexer::token::{Span, Token, TokenType};
+pub use object::ObjectType;
+pub use repl::{execute_bytecode, execute_program, start_repl};
 
-/// Read and execute the given input file
-pub fn read_file<U: Write>(given_path: String, output: &mut U) -> Result<(), Box<dyn Error>> {
+use object::Object;
+use std::{fs, io::Write, path::PathBuf};
+
+/// Read and execute the given input file, which can either be a script or a compiled `.mkc` file.
+/// A script is compiled with the given optimization level.
+pub fn read_file<U: Write>(
+    given_path: String,
+    optimization_level: u8,
+    output: &mut U,
+) -> Result<(), Box<dyn std::error::Error>> {
     let file_path = std::path::Path::new(&given_path);
     let content = fs::read(file_path)?;
+
+    if content.starts_with(compiler::MAGIC) {
+        // the source isn't available, so runtime errors are reported with locations only
+        let bytecode = ByteCode::deserialize(&content)?;
+        execute_bytecode(&given_path, "", bytecode, output)?;
+        return Ok(());
+    }
+
     let input = String::from_utf8(content)?;
-    execute_program(&input, output)?;
+    execute_program(&given_path, &input, optimization_level, output)?;
 
     Ok(())
 }
+
+/// Writes the disassembled bytecode of the given script or compiled `.mkc` file to the output. A
+/// script is compiled with the given optimization level.
+pub fn disassemble_file<U: Write>(
+    given_path: String,
+    optimization_level: u8,
+    output: &mut U,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let content = fs::read(&given_path)?;
+
+    let bytecode = if content.starts_with(compiler::MAGIC) {
+        ByteCode::deserialize(&content)?
+    } else {
+        let input = String::from_utf8(content)?;
+        match compile(&input, optimization_level) {
+            Ok(v) => v,
+            Err(e) => return Err(e.render(&given_path, &input).trim_end().into()),
+        }
+    };
+    write!(output, "{}", bytecode.disassemble())?;
+
+    Ok(())
+}
+
+/// Compiles the given script with the optimization level and writes the bytecode to the output
+/// path, which defaults to the script path with a `.mkc` extension. Returns the path of the
+/// written file.
+pub fn compile_file(
+    given_path: String,
+    output_path: Option<String>,
+    optimization_level: u8,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let input = fs::read_to_string(&given_path)?;
+    let bytecode = match compile(&input, optimization_level) {
+        Ok(v) => v,
+        Err(e) => return Err(e.render(&given_path, &input).trim_end().into()),
+    };
+
+    let output_path = match output_path {
+        Some(v) => PathBuf::from(v),
+        None => PathBuf::from(&given_path).with_extension("mkc"),
+    };
+    fs::write(&output_path, bytecode.serialize())?;
+
+    Ok(output_path)
+}
+
+/// Enables or disables printing the parsing functions that are entered and left while parsing
+/// expressions.
+pub fn set_parser_tracing(enabled: bool) {
+    unsafe {
+        parser::TRACING_ENABLED = enabled;
+    }
+}
+
+/// Parses and compiles the given source code into bytecode.
+///
+/// From optimization level 1 onwards, constant expressions are folded and branches that can never
+/// run are removed.
+pub fn compile(source: &str, optimization_level: u8) -> Result<ByteCode, Error> {
+    let mut p = parser::Parser::new(lexer::Lexer::new(source));
+    let program = p.parse_program();
+    if !p.errors.is_empty() {
+        return Err(Error::Parse(p.errors));
+    }
+
+    let mut comp = compiler::Compiler::new();
+    comp.optimization_level = optimization_level;
+    comp.compile(program.make_node())?;
+    Ok(comp.byte_code())
+}
+
+/// Splits the given source code into tokens, for tools such as formatters.
+///
+/// Unlike the parser, the comments are kept as `Comment` tokens in the order they appear in the
+/// source code. The end of file token isn't included.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let mut l = lexer::Lexer::with_comments(source);
+    let mut tokens = Vec::new();
+    loop {
+        let tok = l.next_token();
+        // the comments before a token are skipped while reading it
+        tokens.append(&mut l.comments);
+        if tok.token_type == TokenType::Eof {
+            break;
+        }
+        tokens.push(tok);
+    }
+
+    if !l.errors.is_empty() {
+        return Err(Error::Parse(l.errors));
+    }
+    Ok(tokens)
+}
+
+/// Compiles and runs the given source code.
+///
+/// Returns the inspected value of the last expression statement, unless it's null.
+pub fn run(source: &str) -> Result<Option<String>, Error> {
+    let mut machine = vm::VM::new(compile(source, 0)?);
+    if let Err(e) = machine.run() {
+        return Err(Error::Runtime(e, machine.traceback()));
+    }
+
+    let result = machine.result().filter(|obj| !obj.is_null());
+    Ok(result.map(|obj| obj.inspect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        assert_eq!(run("let x = 5; x * 2").unwrap(), Some("10".to_string()));
+        assert_eq!(run("null").unwrap(), None);
+    }
+
+    #[test]
+    fn test_execute_program() {
+        // input, output
+        let test_cases = [
+            ("let x = 5; x * 2", "10\n"),
+            ("let total = 0; for (x in [1, 2, 3]) { total += x; }", ""),
+            ("let i = 0; while (i < 3) { i += 1; }", ""),
+            ("null", ""),
+        ];
+
+        for (input, want) in test_cases {
+            let mut output = Vec::new();
+            execute_program("test.monkey", input, 0, &mut output).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), want, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let input = "// double it\nlet x = 2 /* twice */ * 3;";
+        let tokens: Vec<(TokenType, &str, usize)> = tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|tok| {
+                let text = &input[tok.span.offset..tok.span.offset + tok.span.len];
+                (tok.token_type, text, tok.span.line)
+            })
+            .collect();
+
+        let expected = vec![
+            (TokenType::Comment, "// double it", 1),
+            (TokenType::Let, "let", 2),
+            (TokenType::Ident, "x", 2),
+            (TokenType::Assign, "=", 2),
+            (TokenType::Int, "2", 2),
+            (TokenType::Comment, "/* twice */", 2),
+            (TokenType::Asterisk, "*", 2),
+            (TokenType::Int, "3", 2),
+            (TokenType::Semicolon, ";", 2),
+        ];
+        assert_eq!(tokens, expected);
+
+        let Err(Error::Parse(errors)) = tokenize("1 /* /* */") else {
+            panic!("expected parse errors");
+        };
+        assert_eq!(errors[0].error, ParseError::UnterminatedComment);
+        assert_eq!(errors[0].span, Span::new(1, 3, 2, 2));
+    }
+
+    #[test]
+    fn test_typed_errors() {
+        let Err(Error::Parse(errors)) = compile("let x = ;", 0) else {
+            panic!("expected parse errors");
+        };
+        assert_eq!(errors[0].error.code(), "P0003");
+        assert!(matches!(
+            &errors[0].error,
+            ParseError::ExpectedExpression { found } if found.token_type == TokenType::Semicolon
+        ));
+
+        let Err(Error::Compile(e)) = compile("let x = 1;\nx + y", 0) else {
+            panic!("expected a compile error");
+        };
+        assert_eq!(e.error.code(), "C0002");
+        assert_eq!(
+            e.error,
+            CompileError::UndefinedVariable {
+                name: "y".to_string()
+            }
+        );
+        assert_eq!(e.span, Span::new(2, 5, 15, 1));
+
+        let Err(Error::Runtime(e, _)) = run("[1, 2][5]") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(e.error.code(), "R0015");
+        assert_eq!(
+            e.error,
+            RuntimeError::IndexOutOfBounds {
+                index: 5,
+                length: 2
+            }
+        );
+        assert_eq!(e.to_string(), "index 5 out of bounds for length 2");
+
+        let Err(Error::Runtime(e, _)) = run("1 + true") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(
+            e.error,
+            RuntimeError::UnsupportedArithmeticOperands {
+                left: ObjectType::Integer,
+                right: ObjectType::Boolean
+            }
+        );
+    }
+}
@@ -1,21 +1,74 @@
-use compiler_lib::{read_file, start_repl};
+use clap::{ArgAction, Parser, Subcommand};
+use compiler_lib::{compile_file, disassemble_file, read_file, set_parser_tracing, start_repl};
 use std::io::{self, BufReader};
 
+/// The monkey programming language
+#[derive(Parser)]
+#[clap(author, version, about, long_about = None)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Script or compiled `.mkc` file to execute. Starts the REPL if omitted
+    #[clap(value_parser)]
+    file: Option<String>,
+
+    /// Enables tracing for parsing expressions
+    #[clap(short, long, value_parser, default_value_t = false, global = true)]
+    tracing: bool,
+
+    /// Prints the disassembled bytecode of the file instead of executing it
+    #[clap(short, long, value_parser, default_value_t = false, global = true)]
+    disassemble: bool,
+
+    /// Folds constant expressions and removes dead branches, repeat it for peephole optimizations
+    #[clap(short = 'O', action = ArgAction::Count, global = true)]
+    optimize: u8,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compiles a script to a `.mkc` bytecode file
+    Compile {
+        #[clap(value_parser)]
+        file: String,
+
+        /// Path of the bytecode file, defaults to the script path with a `.mkc` extension
+        #[clap(short, long, value_parser)]
+        output: Option<String>,
+    },
+    /// Executes a script or a compiled `.mkc` file
+    Run {
+        #[clap(value_parser)]
+        file: String,
+    },
+}
+
 fn main() {
     let mut writer = io::stdout();
+    let cli = Cli::parse();
+    set_parser_tracing(cli.tracing);
 
-    let mut args = std::env::args();
-    if args.len() > 1 {
-        let file_path = args.nth(1).unwrap();
-        if let Err(e) = read_file(file_path, &mut writer) {
-            eprintln!("{}", e);
-            std::process::exit(1);
+    let result = match (cli.command, cli.file) {
+        (Some(Command::Compile { file, output }), _) => compile_file(file, output, cli.optimize)
+            .and_then(|path| match cli.disassemble {
+                true => disassemble_file(path.to_string_lossy().to_string(), 0, &mut writer),
+                false => Ok(()),
+            }),
+        (Some(Command::Run { file }), _) | (None, Some(file)) if cli.disassemble => {
+            disassemble_file(file, cli.optimize, &mut writer)
         }
-        std::process::exit(0);
-    }
+        (Some(Command::Run { file }), _) | (None, Some(file)) => {
+            read_file(file, cli.optimize, &mut writer)
+        }
+        (None, None) if cli.disassemble => Err("--disassemble requires a file".into()),
+        (None, None) => {
+            let mut reader = BufReader::new(io::stdin());
+            start_repl(&mut reader, &mut writer).map_err(|e| e.into())
+        }
+    };
 
-    let mut reader = BufReader::new(io::stdin());
-    if let Err(e) = start_repl(&mut reader, &mut writer) {
+    if let Err(e) = result {
         eprintln!("{}", e);
         std::process::exit(1);
     }
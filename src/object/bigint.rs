@@ -0,0 +1,379 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+    ops::{Add, Mul, Neg, Sub},
+};
+
+/// Base of the chunks that are used to convert between digits and decimal strings
+const DECIMAL_BASE: u32 = 1_000_000_000;
+
+/// A signed integer of arbitrary size, which integers are promoted to when they overflow `i64`.
+///
+/// The magnitude is kept in base 2^32 with the least significant digit first and without leading
+/// zeros, and zero is never negative. Every value has a single representation as a result, so the
+/// derived equality and hashing compare values.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    fn new(negative: bool, mut digits: Vec<u32>) -> Self {
+        trim(&mut digits);
+        Self {
+            negative: negative && !digits.is_empty(),
+            digits,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    /// Parses a decimal integer with an optional sign
+    pub fn parse(s: &str) -> Option<Self> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let mut magnitude = Vec::new();
+        // the first chunk is shorter, so that the others have exactly 9 digits
+        let first_len = match digits.len() % 9 {
+            0 => 9,
+            n => n,
+        };
+        let mut start = 0;
+        let mut end = first_len;
+        while start < digits.len() {
+            let chunk: u32 = digits[start..end].parse().ok()?;
+            mul_small_add(&mut magnitude, 10u32.pow((end - start) as u32), chunk);
+            start = end;
+            end += 9;
+        }
+
+        Some(Self::new(negative, magnitude))
+    }
+
+    /// Truncates the float towards zero, returning `None` if it's not finite
+    pub fn from_f64(v: f64) -> Option<Self> {
+        if !v.is_finite() {
+            return None;
+        }
+        let v = v.trunc();
+        if v.abs() < 2f64.powi(63) {
+            return Some(Self::from(v as i64));
+        }
+
+        // a float this large is its 53-bit mantissa shifted to the left by the exponent
+        let bits = v.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as usize - 1075;
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+        let digits = shift_left(&[mantissa as u32, (mantissa >> 32) as u32], exponent);
+        Some(Self::new(v < 0.0, digits))
+    }
+
+    /// Returns the value as an `i64`, if it fits in one
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0i128, |acc, d| (acc << 32) | i128::from(*d));
+        let value = if self.negative { -magnitude } else { magnitude };
+        i64::try_from(value).ok()
+    }
+
+    /// Returns the nearest float, which is infinite if the value is too large
+    pub fn to_f64(&self) -> f64 {
+        // parsing the decimal string rounds correctly, unlike summing up the digits
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Returns the quotient truncated towards zero and the remainder, which has the sign of the
+    /// dividend like the integer division of `i64`. Returns `None` if the divisor is zero.
+    pub fn div_rem(&self, other: &Self) -> Option<(Self, Self)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = div_rem_magnitudes(&self.digits, &other.digits);
+        Some((
+            Self::new(self.negative != other.negative, quotient),
+            Self::new(self.negative, remainder),
+        ))
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(v: i64) -> Self {
+        let magnitude = v.unsigned_abs();
+        Self::new(v < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_magnitudes(&self.digits, &other.digits));
+        }
+        // the sign of the result is the sign of the operand with the larger magnitude
+        match cmp_magnitudes(&self.digits, &other.digits) {
+            Ordering::Less => {
+                BigInt::new(other.negative, sub_magnitudes(&other.digits, &self.digits))
+            }
+            _ => BigInt::new(self.negative, sub_magnitudes(&self.digits, &other.digits)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        let mut digits = vec![0u32; self.digits.len() + other.digits.len()];
+        for (i, a) in self.digits.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, b) in other.digits.iter().enumerate() {
+                let v = u64::from(*a) * u64::from(*b) + u64::from(digits[i + j]) + carry;
+                digits[i + j] = v as u32;
+                carry = v >> 32;
+            }
+            digits[i + other.digits.len()] = carry as u32;
+        }
+        BigInt::new(self.negative != other.negative, digits)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitudes(&self.digits, &other.digits),
+            (true, true) => cmp_magnitudes(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the chunks of 9 decimal digits, starting from the least significant one
+        let mut chunks = Vec::new();
+        let mut magnitude = self.digits.clone();
+        while !magnitude.is_empty() {
+            chunks.push(div_rem_small(&mut magnitude, DECIMAL_BASE));
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap_or(&0))?;
+        for chunk in chunks {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// Removes the leading zeros
+fn trim(digits: &mut Vec<u32>) {
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+}
+
+fn cmp_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut digits = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, d) in long.iter().enumerate() {
+        let v = u64::from(*d) + u64::from(*short.get(i).unwrap_or(&0)) + carry;
+        digits.push(v as u32);
+        carry = v >> 32;
+    }
+    digits.push(carry as u32);
+    digits
+}
+
+/// Subtracts the smaller magnitude `b` from `a`
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut digits = Vec::with_capacity(a.len());
+    let mut borrow = false;
+    for (i, d) in a.iter().enumerate() {
+        let (v, borrowed_1) = d.overflowing_sub(*b.get(i).unwrap_or(&0));
+        let (v, borrowed_2) = v.overflowing_sub(u32::from(borrow));
+        digits.push(v);
+        borrow = borrowed_1 || borrowed_2;
+    }
+    digits
+}
+
+/// Multiplies the magnitude by a small factor and adds a small value to it, in place
+fn mul_small_add(digits: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = u64::from(addend);
+    for d in digits.iter_mut() {
+        let v = u64::from(*d) * u64::from(factor) + carry;
+        *d = v as u32;
+        carry = v >> 32;
+    }
+    if carry > 0 {
+        digits.push(carry as u32);
+    }
+}
+
+/// Divides the magnitude by a small divisor in place and returns the remainder
+fn div_rem_small(digits: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut remainder = 0u64;
+    for d in digits.iter_mut().rev() {
+        let v = (remainder << 32) | u64::from(*d);
+        *d = (v / u64::from(divisor)) as u32;
+        remainder = v % u64::from(divisor);
+    }
+    trim(digits);
+    remainder as u32
+}
+
+/// Divides the magnitudes with a bitwise long division, as the divisor is rarely large
+fn div_rem_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_magnitudes(a, b) == Ordering::Less {
+        return (vec![], a.to_vec());
+    }
+    if let [divisor] = b {
+        let mut quotient = a.to_vec();
+        let remainder = div_rem_small(&mut quotient, *divisor);
+        return (quotient, vec![remainder]);
+    }
+
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder = Vec::with_capacity(b.len() + 1);
+    for bit in (0..a.len() * 32).rev() {
+        remainder = shift_left(&remainder, 1);
+        if (a[bit / 32] >> (bit % 32)) & 1 == 1 {
+            if remainder.is_empty() {
+                remainder.push(1);
+            } else {
+                remainder[0] |= 1;
+            }
+        }
+        if cmp_magnitudes(&remainder, b) != Ordering::Less {
+            remainder = sub_magnitudes(&remainder, b);
+            trim(&mut remainder);
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    (quotient, remainder)
+}
+
+fn shift_left(digits: &[u32], shift: usize) -> Vec<u32> {
+    let (words, bits) = (shift / 32, shift % 32);
+    let mut shifted = vec![0u32; words];
+    let mut carry = 0u32;
+    for d in digits {
+        shifted.push((d << bits) | carry);
+        carry = if bits == 0 { 0 } else { d >> (32 - bits) };
+    }
+    shifted.push(carry);
+    trim(&mut shifted);
+    shifted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BigInt;
+
+    fn big(s: &str) -> BigInt {
+        BigInt::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic_matches_i128() {
+        let values: [i128; 9] = [
+            0,
+            1,
+            -7,
+            i64::MAX as i128,
+            i64::MIN as i128,
+            u32::MAX as i128 + 1,
+            -(u64::MAX as i128),
+            123_456_789_012_345_678_901,
+            -98_765_432_109_876_543_210_987,
+        ];
+
+        for a in values {
+            for b in values {
+                let (x, y) = (big(&a.to_string()), big(&b.to_string()));
+                assert_eq!((&x + &y).to_string(), (a + b).to_string(), "{} + {}", a, b);
+                assert_eq!((&x - &y).to_string(), (a - b).to_string(), "{} - {}", a, b);
+                assert_eq!(x.cmp(&y), a.cmp(&b), "{} cmp {}", a, b);
+                if let Some(product) = a.checked_mul(b) {
+                    assert_eq!((&x * &y).to_string(), product.to_string(), "{} * {}", a, b);
+                }
+                if b != 0 {
+                    let (q, r) = x.div_rem(&y).unwrap();
+                    assert_eq!(q.to_string(), (a / b).to_string(), "{} / {}", a, b);
+                    assert_eq!(r.to_string(), (a % b).to_string(), "{} % {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(BigInt::from(i64::MIN).to_string(), "-9223372036854775808");
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(big("-0"), BigInt::from(0));
+        assert_eq!(big("+000120").to_string(), "120");
+        assert_eq!(BigInt::parse("1_000"), None);
+        assert_eq!(BigInt::parse("-"), None);
+
+        assert_eq!(big("1000000000000000000000").to_f64(), 1e21);
+        assert_eq!(
+            BigInt::from_f64(-1e21),
+            Some(big("-1000000000000000000000"))
+        );
+        assert_eq!(BigInt::from_f64(2.9), Some(BigInt::from(2)));
+        assert_eq!(BigInt::from_f64(f64::NAN), None);
+
+        let factorial = (1..=30).fold(BigInt::from(1), |acc, n| &acc * &BigInt::from(n));
+        assert_eq!(factorial.to_string(), "265252859812191058636308480000000");
+    }
+}
@@ -1,9 +1,9 @@
 use super::{
-    objects::{Boolean, BuiltinFunctionObj, Integer, Null},
+    bigint::BigInt,
+    objects::{Boolean, BuiltinFunctionObj, Float, Integer, Null, RangeObj},
     AllObjects, ObjectType,
 };
-use crate::object::Object;
-use anyhow::{anyhow, Result};
+use crate::{errors::RuntimeError, object::Object};
 use std::{thread, time::Duration};
 
 /// Defines an index for the builtin functions for the VM to access using an operand
@@ -17,6 +17,11 @@ pub static BUILTIN_FUNCTIONS: &[(usize, &str)] = &[
     (7, "delete"),
     (8, "sleep"),
     (9, "println"),
+    (10, "range"),
+    (11, "float"),
+    (12, "int"),
+    (13, "round"),
+    (14, "floor"),
 ];
 
 /// Return the builtin function associated with the passed index number
@@ -31,33 +36,41 @@ pub fn get_builtin_function(index: usize) -> Option<AllObjects> {
         7 => BuiltinFunctionObj::new("delete", 2, delete),
         8 => BuiltinFunctionObj::new("sleep", 1, sleep),
         9 => BuiltinFunctionObj::new("sleep", usize::MAX, println),
+        10 => BuiltinFunctionObj::new("range", usize::MAX, range),
+        11 => BuiltinFunctionObj::new("float", 1, float),
+        12 => BuiltinFunctionObj::new("int", 1, int),
+        13 => BuiltinFunctionObj::new("round", 1, round),
+        14 => BuiltinFunctionObj::new("floor", 1, floor),
         _ => return None,
     };
 
     Some(AllObjects::BuiltinFunction(func))
 }
 
-/// Returns the length of a string, an array or a hashmap.
+/// Returns the length of a string in characters, an array or a hashmap.
 ///
 /// The function expects an argument called value, which must be one of the said types.
-pub fn len(mut values: Vec<AllObjects>) -> Result<AllObjects> {
+pub fn len(mut values: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
     let length = match values.remove(0) {
-        AllObjects::StringObj(v) => v.value.len(),
+        AllObjects::StringObj(v) => v.char_len(),
         AllObjects::ArrayObj(v) => v.elements.borrow().len(),
         AllObjects::HashMap(v) => v.map.borrow().len(),
+        AllObjects::Range(v) => v.len() as usize,
         v => return Err(err_argument_not_supported("len", v.object_type())),
     };
 
-    // panic of conversion from usize to i64 is highly unlikely
+    // overflow of conversion from usize to i64 is highly unlikely
     let length = AllObjects::Integer(Integer {
-        value: length.try_into()?,
+        value: length
+            .try_into()
+            .map_err(|_| RuntimeError::IntegerOverflow)?,
     });
 
     Ok(length)
 }
 
 /// Takes a variable number of arguments and prints each one consecutively to the stdout with a single space separator.
-pub fn print(args: Vec<AllObjects>) -> Result<AllObjects> {
+pub fn print(args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
     for (i, arg) in args.iter().enumerate() {
         print!("{}", arg.inspect());
         if i != args.len() - 1 {
@@ -69,7 +82,7 @@ pub fn print(args: Vec<AllObjects>) -> Result<AllObjects> {
 
 /// Takes a variable number of arguments and prints each one consecutively to the stdout with a single space separator and
 /// a newline will be printed for each call.
-pub fn println(args: Vec<AllObjects>) -> Result<AllObjects> {
+pub fn println(args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
     for (i, arg) in args.iter().enumerate() {
         print!("{}", arg.inspect());
         if i != args.len() - 1 {
@@ -81,7 +94,7 @@ pub fn println(args: Vec<AllObjects>) -> Result<AllObjects> {
 }
 
 /// Appends an element to the back of the array
-pub fn push(mut args: Vec<AllObjects>) -> Result<AllObjects> {
+pub fn push(mut args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
     let array = match args.remove(0) {
         AllObjects::ArrayObj(v) => v,
         v => return Err(err_argument_not_supported("push", v.object_type())),
@@ -96,7 +109,7 @@ pub fn push(mut args: Vec<AllObjects>) -> Result<AllObjects> {
 /// Removes the last element from an array and returns it.
 ///
 /// Returns null, if the array is empty
-pub fn pop(mut args: Vec<AllObjects>) -> Result<AllObjects> {
+pub fn pop(mut args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
     let array = match args.remove(0) {
         AllObjects::ArrayObj(v) => v,
         v => return Err(err_argument_not_supported("pop", v.object_type())),
@@ -112,7 +125,7 @@ pub fn pop(mut args: Vec<AllObjects>) -> Result<AllObjects> {
 }
 
 /// Checks if the passed value is a null
-pub fn is_null(mut args: Vec<AllObjects>) -> Result<AllObjects> {
+pub fn is_null(mut args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
     let is_null = matches!(args.remove(0), AllObjects::Null(_));
     Ok(AllObjects::Boolean(Boolean { value: is_null }))
 }
@@ -122,7 +135,7 @@ pub fn is_null(mut args: Vec<AllObjects>) -> Result<AllObjects> {
 /// If the map did not have this key present, Null is returned.
 ///
 /// If the map have this key present, the value is updated, and the old value is returned
-pub fn insert(mut args: Vec<AllObjects>) -> Result<AllObjects> {
+pub fn insert(mut args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
     let map_arg = args.remove(0);
     let key = args.remove(0);
     let value = args.remove(0);
@@ -141,7 +154,7 @@ pub fn insert(mut args: Vec<AllObjects>) -> Result<AllObjects> {
 
 /// Removes a key from the map, returning the value at the key if the key was previously in the map and
 /// returns Null otherwise
-pub fn delete(mut args: Vec<AllObjects>) -> Result<AllObjects> {
+pub fn delete(mut args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
     let m = match args.remove(0) {
         AllObjects::HashMap(v) => v,
         v => return Err(err_argument_not_supported("delete", v.object_type())),
@@ -156,14 +169,16 @@ pub fn delete(mut args: Vec<AllObjects>) -> Result<AllObjects> {
 }
 
 /// Puts the main thread to sleep for the specified amount of time given in seconds
-pub fn sleep(mut args: Vec<AllObjects>) -> Result<AllObjects> {
+pub fn sleep(mut args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
     let seconds = match args.remove(0) {
         AllObjects::Integer(n) => n,
         v => return Err(err_argument_not_supported("sleep", v.object_type())),
     };
 
     let Ok(seconds) = TryInto::<u64>::try_into(seconds.value) else {
-        return Err(anyhow!("sleep only takes a positive integer value"));
+        return Err(RuntimeError::InvalidSleepDuration {
+            seconds: seconds.value,
+        });
     };
 
     thread::sleep(Duration::from_secs(seconds));
@@ -171,6 +186,106 @@ pub fn sleep(mut args: Vec<AllObjects>) -> Result<AllObjects> {
     Ok(AllObjects::Null(Null))
 }
 
-fn err_argument_not_supported(fn_name: &str, obj_type: ObjectType) -> anyhow::Error {
-    anyhow!("argument to `{fn_name}` not supported, got {obj_type}")
+/// Returns the integers from the start up to, but not including the end.
+///
+/// Takes either the end, which starts the range from zero, or the start and the end.
+pub fn range(args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
+    let mut bounds = Vec::with_capacity(args.len());
+    for arg in args {
+        match arg {
+            AllObjects::Integer(v) => bounds.push(v.value),
+            v => return Err(err_argument_not_supported("range", v.object_type())),
+        }
+    }
+
+    let (start, end) = match bounds[..] {
+        [end] => (0, end),
+        [start, end] => (start, end),
+        _ => {
+            return Err(RuntimeError::WrongNumberOfArguments {
+                want: 2,
+                got: bounds.len(),
+            })
+        }
+    };
+
+    Ok(AllObjects::Range(RangeObj { start, end }))
+}
+
+/// Converts an integer, a float or a string to a float
+pub fn float(mut args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
+    let value = match args.remove(0) {
+        AllObjects::Integer(v) => v.value as f64,
+        AllObjects::BigInteger(v) => v.value.to_f64(),
+        AllObjects::Float(v) => v.value,
+        AllObjects::StringObj(v) => match v.value.trim().parse::<f64>() {
+            Ok(n) => n,
+            Err(_) => return Err(err_invalid_conversion(&v.value, ObjectType::Float)),
+        },
+        v => return Err(err_argument_not_supported("float", v.object_type())),
+    };
+
+    Ok(AllObjects::Float(Float { value }))
+}
+
+/// Converts an integer, a float or a string to an integer, which is big if it doesn't fit in 64
+/// bits.
+///
+/// Floats are truncated towards zero, and fail to convert if they are infinite or NaN.
+pub fn int(mut args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
+    let value = match args.remove(0) {
+        v @ (AllObjects::Integer(_) | AllObjects::BigInteger(_)) => return Ok(v),
+        AllObjects::Float(v) => match BigInt::from_f64(v.value) {
+            Some(n) => n,
+            None => return Err(err_invalid_conversion(&v.value, ObjectType::Integer)),
+        },
+        AllObjects::StringObj(v) => match BigInt::parse(v.value.trim()) {
+            Some(n) => n,
+            None => return Err(err_invalid_conversion(&v.value, ObjectType::Integer)),
+        },
+        v => return Err(err_argument_not_supported("int", v.object_type())),
+    };
+
+    Ok(AllObjects::from_big_int(value))
+}
+
+/// Rounds a float to the nearest integer value, rounding half-way cases away from zero.
+///
+/// Integers are returned as they are.
+pub fn round(mut args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
+    match args.remove(0) {
+        AllObjects::Float(v) => Ok(AllObjects::Float(Float {
+            value: v.value.round(),
+        })),
+        v @ (AllObjects::Integer(_) | AllObjects::BigInteger(_)) => Ok(v),
+        v => Err(err_argument_not_supported("round", v.object_type())),
+    }
+}
+
+/// Returns the largest integer value less than or equal to a float.
+///
+/// Integers are returned as they are.
+pub fn floor(mut args: Vec<AllObjects>) -> Result<AllObjects, RuntimeError> {
+    match args.remove(0) {
+        AllObjects::Float(v) => Ok(AllObjects::Float(Float {
+            value: v.value.floor(),
+        })),
+        v @ (AllObjects::Integer(_) | AllObjects::BigInteger(_)) => Ok(v),
+        v => Err(err_argument_not_supported("floor", v.object_type())),
+    }
+}
+
+fn err_argument_not_supported(function: &'static str, object_type: ObjectType) -> RuntimeError {
+    RuntimeError::UnsupportedArgument {
+        function,
+        object_type,
+    }
+}
+
+/// Describes the value that failed to convert the way it appears in the source code
+fn err_invalid_conversion(value: &impl std::fmt::Debug, to: ObjectType) -> RuntimeError {
+    RuntimeError::InvalidConversion {
+        value: format!("{:?}", value),
+        to,
+    }
 }
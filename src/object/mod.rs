@@ -1,16 +1,28 @@
-use std::fmt::{self, Display};
+use bigint::BigInt;
+use std::{
+    fmt::{self, Display},
+    rc::Rc,
+};
 
+pub mod bigint;
 pub mod builtins;
 pub mod objects;
 
 pub trait Object {
     fn inspect(&self) -> String;
+
+    /// Returns the object the way it's written in the source code, which is how the elements of
+    /// arrays and hash maps are shown. It's the same as `inspect` for everything but strings.
+    fn repr(&self) -> String {
+        self.inspect()
+    }
 }
 
 /// This is useful when doing just type comparisons disregarding underlying value
-#[derive(PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ObjectType {
     Integer,
+    Float,
     String,
     Boolean,
     Null,
@@ -19,12 +31,15 @@ pub enum ObjectType {
     Array,
     HashMap,
     Closure,
+    Range,
+    Iterator,
 }
 
 impl Display for ObjectType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
         let out = match self {
             ObjectType::Integer => "INTEGER",
+            ObjectType::Float => "FLOAT",
             ObjectType::String => "STRING",
             ObjectType::Boolean => "BOOLEAN",
             ObjectType::Null => "NULL",
@@ -33,6 +48,8 @@ impl Display for ObjectType {
             ObjectType::Array => "ARRAY",
             ObjectType::HashMap => "HASH_MAP",
             ObjectType::Closure => "CLOSURE",
+            ObjectType::Range => "RANGE",
+            ObjectType::Iterator => "ITERATOR",
         };
         write!(f, "{}", out)
     }
@@ -44,6 +61,8 @@ impl Display for ObjectType {
 #[derive(PartialEq, Eq, Hash, Clone)]
 pub enum AllObjects {
     Integer(objects::Integer),
+    BigInteger(objects::BigInteger),
+    Float(objects::Float),
     StringObj(objects::StringObj),
     Boolean(objects::Boolean),
     Null(objects::Null),
@@ -52,12 +71,16 @@ pub enum AllObjects {
     ArrayObj(objects::ArrayObj),
     HashMap(objects::HashMapObj),
     Closure(objects::Closure),
+    Range(objects::RangeObj),
+    Iterator(objects::IteratorObj),
 }
 
 impl Object for AllObjects {
     fn inspect(&self) -> String {
         match self {
             Self::Integer(v) => v.inspect(),
+            Self::BigInteger(v) => v.inspect(),
+            Self::Float(v) => v.inspect(),
             Self::StringObj(v) => v.inspect(),
             Self::Boolean(v) => v.inspect(),
             Self::Null(v) => v.inspect(),
@@ -66,6 +89,15 @@ impl Object for AllObjects {
             Self::ArrayObj(v) => v.inspect(),
             Self::HashMap(v) => v.inspect(),
             Self::Closure(v) => v.inspect(),
+            Self::Range(v) => v.inspect(),
+            Self::Iterator(v) => v.inspect(),
+        }
+    }
+
+    fn repr(&self) -> String {
+        match self {
+            Self::StringObj(v) => v.repr(),
+            v => v.inspect(),
         }
     }
 }
@@ -73,7 +105,8 @@ impl Object for AllObjects {
 impl AllObjects {
     pub fn object_type(&self) -> ObjectType {
         match self {
-            Self::Integer(_) => ObjectType::Integer,
+            Self::Integer(_) | Self::BigInteger(_) => ObjectType::Integer,
+            Self::Float(_) => ObjectType::Float,
             Self::StringObj(_) => ObjectType::String,
             Self::Boolean(_) => ObjectType::Boolean,
             Self::Null(_) => ObjectType::Null,
@@ -82,6 +115,8 @@ impl AllObjects {
             Self::ArrayObj(_) => ObjectType::Array,
             Self::HashMap(_) => ObjectType::HashMap,
             Self::Closure(_) => ObjectType::Closure,
+            Self::Range(_) => ObjectType::Range,
+            Self::Iterator(_) => ObjectType::Iterator,
         }
     }
 
@@ -89,6 +124,37 @@ impl AllObjects {
         self.object_type() == ObjectType::Integer
     }
 
+    /// Returns an integer object for the value, which is a big integer only if the value doesn't
+    /// fit in an `i64`
+    pub fn from_big_int(value: BigInt) -> Self {
+        match value.to_i64() {
+            Some(value) => Self::Integer(objects::Integer { value }),
+            None => Self::BigInteger(objects::BigInteger {
+                value: Rc::new(value),
+            }),
+        }
+    }
+
+    /// Returns the value of an integer of either size as a big integer
+    pub fn as_big_int(&self) -> Option<BigInt> {
+        match self {
+            Self::Integer(v) => Some(BigInt::from(v.value)),
+            Self::BigInteger(v) => Some(v.value.as_ref().clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an integer or a float as a float, which is how an integer is combined
+    /// with a float in arithmetic and comparisons
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Integer(v) => Some(v.value as f64),
+            Self::BigInteger(v) => Some(v.value.to_f64()),
+            Self::Float(v) => Some(v.value),
+            _ => None,
+        }
+    }
+
     pub fn is_boolean(&self) -> bool {
         self.object_type() == ObjectType::Boolean
     }
@@ -1,7 +1,14 @@
-use super::{AllObjects, Object};
-use crate::code::Instructions;
-use anyhow::Result;
-use std::{cell::RefCell, collections::HashMap, hash::Hash, rc::Rc};
+use super::{bigint::BigInt, AllObjects, Object};
+use crate::{
+    code::{Instructions, SourceMap},
+    errors::RuntimeError,
+};
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
+    hash::Hash,
+    rc::Rc,
+};
 
 #[derive(PartialEq, Eq, Hash, Clone)]
 pub struct Integer {
@@ -14,22 +21,207 @@ impl Object for Integer {
     }
 }
 
+/// An integer that doesn't fit in an `i64`, which integer arithmetic is promoted to on overflow.
+///
+/// Results that fit in an `i64` again are always turned back into an `Integer` by
+/// [`AllObjects::from_big_int`], so that a value has a single representation for equality and
+/// hashing. Both are integers to the language.
 #[derive(PartialEq, Eq, Hash, Clone)]
+pub struct BigInteger {
+    pub value: Rc<BigInt>,
+}
+
+impl Object for BigInteger {
+    fn inspect(&self) -> String {
+        self.value.to_string()
+    }
+}
+
+/// A 64-bit floating point number.
+///
+/// Arithmetic and comparisons in the VM follow IEEE 754, so `1.0 / 0` is infinite and NaN is not
+/// equal to itself. As a hash map key however, a float is compared by its value: zero and negative
+/// zero are the same key and so are all NaNs. Floats and integers are never the same key, even
+/// though `1 == 1.0` is true.
+#[derive(Clone)]
+pub struct Float {
+    pub value: f64,
+}
+
+impl Float {
+    /// Returns the bits of the value, with a single representation for zero and for NaN
+    fn key_bits(&self) -> u64 {
+        if self.value == 0.0 {
+            0
+        } else if self.value.is_nan() {
+            f64::NAN.to_bits()
+        } else {
+            self.value.to_bits()
+        }
+    }
+}
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.key_bits() == other.key_bits()
+    }
+}
+
+impl Eq for Float {}
+
+impl Hash for Float {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key_bits().hash(state);
+    }
+}
+
+impl Object for Float {
+    fn inspect(&self) -> String {
+        // the debug format always has a fractional part or an exponent, e.g. `3.0` or `1e-7`
+        format!("{:?}", self.value)
+    }
+}
+
+/// Strings up to this many bytes are indexed by walking their characters instead of building a
+/// char index
+const MAX_UNINDEXED_LEN: usize = 64;
+
+/// Number of characters between the offsets that are kept in a char index
+const CHAR_INDEX_STRIDE: usize = 32;
+
+/// A string of unicode scalar values, which are what the length, indexes and slices of a string
+/// count. Grapheme clusters aren't taken into account, so `"e\u{301}"` has a length of two.
+#[derive(Clone)]
 pub struct StringObj {
     pub value: Rc<String>,
+
+    /// built on the first index into a long string and shared by the clones of the string
+    char_index: Rc<OnceCell<CharIndex>>,
+}
+
+/// Maps the character positions of a string to byte offsets
+struct CharIndex {
+    num_chars: usize,
+
+    /// byte offsets of every `CHAR_INDEX_STRIDE`-th character, which is empty for ASCII strings
+    /// as their characters are their bytes
+    offsets: Vec<usize>,
 }
 
 impl StringObj {
     pub fn new(v: &str) -> Self {
         Self {
             value: Rc::new(v.to_string()),
+            char_index: Rc::default(),
+        }
+    }
+
+    /// Returns the number of characters in the string
+    pub fn char_len(&self) -> usize {
+        match self.char_index() {
+            Some(index) => index.num_chars,
+            None => self.value.chars().count(),
         }
     }
+
+    /// Returns the character at the given position
+    pub fn char_at(&self, position: usize) -> Option<char> {
+        self.value[self.byte_offset(position)?..].chars().next()
+    }
+
+    /// Returns the characters from the start up to, but not including the end, which should be
+    /// valid positions in the string
+    pub fn char_slice(&self, start: usize, end: usize) -> &str {
+        let start = self.byte_offset(start).unwrap_or(self.value.len());
+        let end = self.byte_offset(end).unwrap_or(self.value.len());
+        &self.value[start..end]
+    }
+
+    /// Returns the byte offset of the character at the given position, if there's one
+    fn byte_offset(&self, position: usize) -> Option<usize> {
+        let Some(index) = self.char_index() else {
+            return self.value.char_indices().nth(position).map(|(i, _)| i);
+        };
+        if position >= index.num_chars {
+            return None;
+        }
+        if index.offsets.is_empty() {
+            return Some(position);
+        }
+
+        // walk from the closest offset before the position, which is at most a stride away
+        let start = index.offsets[position / CHAR_INDEX_STRIDE];
+        let skipped = position % CHAR_INDEX_STRIDE;
+        self.value[start..]
+            .char_indices()
+            .nth(skipped)
+            .map(|(i, _)| start + i)
+    }
+
+    /// Returns the char index of the string, which is only built for long strings
+    fn char_index(&self) -> Option<&CharIndex> {
+        if self.value.len() <= MAX_UNINDEXED_LEN {
+            return None;
+        }
+        let index = self.char_index.get_or_init(|| {
+            if self.value.is_ascii() {
+                return CharIndex {
+                    num_chars: self.value.len(),
+                    offsets: vec![],
+                };
+            }
+            let mut num_chars = 0;
+            let mut offsets = Vec::new();
+            for (i, (offset, _)) in self.value.char_indices().enumerate() {
+                if i % CHAR_INDEX_STRIDE == 0 {
+                    offsets.push(offset);
+                }
+                num_chars += 1;
+            }
+            CharIndex { num_chars, offsets }
+        });
+        Some(index)
+    }
+}
+
+impl PartialEq for StringObj {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for StringObj {}
+
+impl Hash for StringObj {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
 }
 
 impl Object for StringObj {
     fn inspect(&self) -> String {
-        self.value.replace("\\n", "\n").replace("\\t", "\t")
+        self.value.to_string()
+    }
+
+    /// Returns the string as a literal, in quotes and with quotes, backslashes and control
+    /// characters escaped
+    fn repr(&self) -> String {
+        let mut out = String::with_capacity(self.value.len() + 2);
+        out.push('"');
+        for ch in self.value.chars() {
+            match ch {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\t' => out.push_str("\\t"),
+                '\r' => out.push_str("\\r"),
+                '\0' => out.push_str("\\0"),
+                ch if ch.is_control() => out.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+                ch => out.push(ch),
+            }
+        }
+        out.push('"');
+        out
     }
 }
 
@@ -57,6 +249,18 @@ impl Object for Null {
 pub struct CompiledFunctionObj {
     pub instructions: Instructions,
     pub num_args: usize,
+
+    /// number of local bindings including the arguments, which are reserved on the stack per call
+    pub num_locals: usize,
+
+    /// where each free variable of the function is found when a closure is created from it
+    pub free_variables: Vec<FreeVariable>,
+
+    /// locations of the instructions in the source code, used for reporting runtime errors
+    pub source_map: SourceMap,
+
+    /// name of the function, which is empty for anonymous functions
+    pub name: String,
 }
 
 impl CompiledFunctionObj {
@@ -64,10 +268,27 @@ impl CompiledFunctionObj {
         Self {
             instructions,
             num_args,
+            num_locals: num_args,
+            free_variables: vec![],
+            source_map: SourceMap::default(),
+            name: String::new(),
         }
     }
 }
 
+/// Describes a variable captured by a closure, relative to the function that creates the closure.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum FreeVariable {
+    /// a local binding of the enclosing function at the given index
+    Local(usize),
+
+    /// a free variable of the enclosing closure at the given index
+    Free(usize),
+
+    /// the enclosing closure itself, which is how nested functions refer to a recursive parent
+    CurrentClosure,
+}
+
 impl PartialEq for CompiledFunctionObj {
     fn eq(&self, other: &Self) -> bool {
         format!("{:?}", self.instructions) == format!("{:?}", other.instructions)
@@ -88,7 +309,7 @@ impl Object for CompiledFunctionObj {
     }
 }
 
-pub type BuiltinFn = fn(Vec<AllObjects>) -> Result<AllObjects>;
+pub type BuiltinFn = fn(Vec<AllObjects>) -> Result<AllObjects, RuntimeError>;
 
 #[derive(Clone)]
 pub struct BuiltinFunctionObj {
@@ -161,7 +382,7 @@ impl Object for ArrayObj {
             self.elements
                 .borrow()
                 .iter()
-                .map(|v| v.inspect())
+                .map(|v| v.repr())
                 .collect::<Vec<String>>()
                 .join(", ")
         )
@@ -174,6 +395,7 @@ pub struct HashMapObj {
 }
 
 impl HashMapObj {
+    #[allow(clippy::mutable_key_type)]
     pub fn new(map: HashMap<AllObjects, AllObjects>) -> Self {
         Self {
             map: Rc::new(RefCell::new(map)),
@@ -200,7 +422,7 @@ impl Object for HashMapObj {
         let binding = self.map.borrow();
         let out = binding
             .iter()
-            .map(|(k, v)| format!("{}:{}", k.inspect(), v.inspect()))
+            .map(|(k, v)| format!("{}:{}", k.repr(), v.repr()))
             .collect::<Vec<String>>()
             .join(", ");
 
@@ -208,20 +430,156 @@ impl Object for HashMapObj {
     }
 }
 
-#[derive(PartialEq, Eq, Hash, Clone)]
+#[derive(PartialEq, Eq, Clone)]
 pub struct Closure {
     pub func: CompiledFunctionObj,
-    pub free: Vec<AllObjects>,
+    pub free: Vec<UpvalueRef>,
 }
 
 impl Closure {
-    pub fn new(func: CompiledFunctionObj, free: Vec<AllObjects>) -> Self {
+    pub fn new(func: CompiledFunctionObj, free: Vec<UpvalueRef>) -> Self {
         Self { func, free }
     }
 }
 
+impl Hash for Closure {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.func.hash(state)
+    }
+}
+
+/// A captured variable shared by every closure that captured it.
+///
+/// While the function that defined the variable is still running, the upvalue is open and points to
+/// the stack slot of that local, so that assignments on either side are visible to the other. The
+/// VM closes it by moving the value into the upvalue, once that frame returns.
+#[derive(PartialEq, Eq, Clone)]
+pub enum Upvalue {
+    Open { slot: usize },
+    Closed(AllObjects),
+}
+
+pub type UpvalueRef = Rc<RefCell<Upvalue>>;
+
 impl Object for Closure {
     fn inspect(&self) -> String {
         format!("Closure[{}]", self.func.inspect())
     }
 }
+
+/// Integers from the start up to, but not including the end
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub struct RangeObj {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl RangeObj {
+    /// Returns the number of integers in the range, which is zero if the end is before the start
+    pub fn len(&self) -> u64 {
+        self.end.saturating_sub(self.start).max(0) as u64
+    }
+}
+
+impl Object for RangeObj {
+    fn inspect(&self) -> String {
+        format!("range({}, {})", self.start, self.end)
+    }
+}
+
+/// The state of a `for` loop, which lives on the stack while the loop is running
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub struct IteratorObj {
+    items: IteratorItems,
+
+    /// index of the next item
+    position: usize,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+enum IteratorItems {
+    /// elements are read as the loop goes, so that pushes in the loop body are visited too
+    Array(ArrayObj),
+    Chars(Vec<char>),
+    /// the entries of a hash map at the start of the loop, ordered by their keys
+    Entries(Vec<(AllObjects, AllObjects)>),
+    Range(RangeObj),
+}
+
+impl IteratorObj {
+    /// Creates an iterator over the given object, if it's iterable
+    pub fn new(iterable: AllObjects) -> Option<Self> {
+        let items = match iterable {
+            AllObjects::ArrayObj(v) => IteratorItems::Array(v),
+            AllObjects::StringObj(v) => IteratorItems::Chars(v.value.chars().collect()),
+            AllObjects::HashMap(v) => {
+                let mut entries: Vec<_> = v
+                    .map
+                    .borrow()
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                entries.sort_by_cached_key(|(k, _)| Self::key_order(k));
+                IteratorItems::Entries(entries)
+            }
+            AllObjects::Range(v) => IteratorItems::Range(v),
+            _ => return None,
+        };
+
+        Some(Self { items, position: 0 })
+    }
+
+    /// Returns whether a loop with a single variable binds the keys instead of the values
+    pub fn yields_keys(&self) -> bool {
+        matches!(self.items, IteratorItems::Entries(_))
+    }
+
+    /// Advances the iterator and returns the key and the value of the next item. Keys are the
+    /// indexes of the items, except for hash maps.
+    pub fn next(&mut self) -> Option<(AllObjects, AllObjects)> {
+        let index = self.position;
+        let item = match &self.items {
+            IteratorItems::Array(v) => {
+                let value = v.elements.borrow().get(index)?.clone();
+                (Self::index(index), value)
+            }
+            IteratorItems::Chars(v) => {
+                let value = StringObj::new(&v.get(index)?.to_string());
+                (Self::index(index), AllObjects::StringObj(value))
+            }
+            IteratorItems::Entries(v) => v.get(index)?.clone(),
+            IteratorItems::Range(v) => {
+                if index as u64 >= v.len() {
+                    return None;
+                }
+                let value = Integer {
+                    value: v.start + index as i64,
+                };
+                (Self::index(index), AllObjects::Integer(value))
+            }
+        };
+
+        self.position += 1;
+        Some(item)
+    }
+
+    fn index(index: usize) -> AllObjects {
+        AllObjects::Integer(Integer {
+            value: index as i64,
+        })
+    }
+
+    /// Orders integer keys by value, followed by the other keys by their string representations
+    fn key_order(key: &AllObjects) -> (bool, i64, String) {
+        match key {
+            AllObjects::Integer(v) => (false, v.value, String::new()),
+            v => (true, 0, v.inspect()),
+        }
+    }
+}
+
+impl Object for IteratorObj {
+    fn inspect(&self) -> String {
+        "<iterator>".to_string()
+    }
+}
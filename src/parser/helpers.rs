@@ -1,5 +1,5 @@
 use super::{program::Parser, Precedence};
-use crate::lexer::token::TokenType;
+use crate::{diagnostics::Diagnostic, errors::ParseError, lexer::token::TokenType};
 
 use std::mem;
 
@@ -8,6 +8,7 @@ impl Parser {
     pub fn next_token(&mut self) {
         mem::swap(&mut self.current_token, &mut self.peek_token);
         self.peek_token = self.l.next_token();
+        self.errors.append(&mut self.l.errors);
     }
 
     /// Checks if the current token is the supplied token type
@@ -32,16 +33,35 @@ impl Parser {
 
     /// Add an error to errors when the type of peekToken doesn't match the expectation
     pub fn peek_error(&mut self, token_type: TokenType) {
-        let msg = format!(
-            "expected next token to be {:?}, got {:?} instead",
-            token_type, self.peek_token
-        );
-        self.errors.push(msg);
+        let err = ParseError::UnexpectedToken {
+            expected: token_type,
+            found: self.peek_token.clone(),
+        };
+        self.errors.push(Diagnostic::new(err, self.peek_token.span));
     }
 
-    pub fn no_prefix_parse_fn_error(&mut self, token_type: TokenType) {
-        let msg = format!("no prefix parse function for {:?} found", token_type);
-        self.errors.push(msg);
+    /// Return true if the peek token is a comma that separates the elements of a list and advance
+    /// the tokenizer, otherwise report that either a comma or the closing token was expected
+    pub fn expect_list_separator(&mut self, end: &TokenType) -> bool {
+        if self.peek_token_is(&TokenType::Comma) {
+            self.next_token();
+            return true;
+        }
+        let err = ParseError::UnterminatedList {
+            end: end.clone(),
+            found: self.peek_token.clone(),
+        };
+        self.errors.push(Diagnostic::new(err, self.peek_token.span));
+        false
+    }
+
+    /// Add an error to errors when the current token cannot start an expression
+    pub fn no_prefix_parse_fn_error(&mut self) {
+        let err = ParseError::ExpectedExpression {
+            found: self.current_token.clone(),
+        };
+        self.errors
+            .push(Diagnostic::new(err, self.current_token.span));
     }
 
     pub fn peek_precedence(&self) -> Precedence {
@@ -13,13 +13,15 @@ pub static mut TRACING_ENABLED: bool = false;
 #[derive(PartialEq, Eq, PartialOrd, Debug)]
 pub enum Precedence {
     Lowest = 1,
-    Equals = 2,
-    LessGreater = 3,
-    Sum = 4,
-    Product = 5,
-    Prefix = 6,
-    Call = 7,
-    Index = 8,
+    LogicalOr = 2,
+    LogicalAnd = 3,
+    Equals = 4,
+    LessGreater = 5,
+    Sum = 6,
+    Product = 7,
+    Prefix = 8,
+    Call = 9,
+    Index = 10,
 }
 
 impl Precedence {
@@ -28,10 +30,12 @@ impl Precedence {
         use TokenType::*;
 
         match token_type {
+            Or => LogicalOr,
+            And => LogicalAnd,
             Eq | NotEq => Equals,
-            Lt | Gt => LessGreater,
+            Lt | Gt | LtEq | GtEq => LessGreater,
             Plus | Minus => Sum,
-            Slash | Asterisk => Product,
+            Slash | Asterisk | Percent => Product,
             Lparen => Call,
             Lbracket => Index,
             _ => Lowest,
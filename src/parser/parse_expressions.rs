@@ -6,12 +6,15 @@ use super::{
 };
 use crate::ast::expressions::{
     self, AllExpressions, ArrayLiteral, AssignmentExpression, Boolean, CallExpression,
-    FunctionLiteral, HashLiteral, Identifier, IfExpression, IndexExpression, RangeExpression,
-    StringLiteral,
+    FunctionLiteral, HashLiteral, Identifier, IfExpression, IndexExpression, InfixExpression,
+    NullLiteral, RangeExpression, StringLiteral,
 };
 use crate::ast::statements::ExpressionStatement;
 use crate::ast::statements::{AllStatements, BlockStatement};
+use crate::diagnostics::Diagnostic;
+use crate::errors::ParseError;
 use crate::lexer::token::TokenType;
+use crate::object::bigint::BigInt;
 
 impl Parser {
     /// Parses expression statements.
@@ -25,7 +28,9 @@ impl Parser {
             expression: None,
         };
 
-        if self.current_token_is(&TokenType::Ident) && self.peek_token_is(&TokenType::Assign) {
+        let is_assignment = self.peek_token_is(&TokenType::Assign)
+            || self.peek_token.token_type.compound_operator().is_some();
+        if self.current_token_is(&TokenType::Ident) && is_assignment {
             stmt.expression = self.parse_assignment_expressions();
         } else {
             stmt.expression = self.parse_expression(Precedence::Lowest);
@@ -45,7 +50,7 @@ impl Parser {
         let prefix = match Parser::prefix_parse_function(&self.current_token.token_type) {
             Some(v) => v,
             None => {
-                self.no_prefix_parse_fn_error(self.current_token.token_type.clone());
+                self.no_prefix_parse_fn_error();
                 return None;
             }
         };
@@ -76,11 +81,23 @@ impl Parser {
             value: self.current_token.literal.clone(),
         };
 
-        // consumes the ident and =
+        // consumes the ident and the assignment operator
         self.next_token();
+        let operator_token = self.current_token.clone();
         self.next_token();
 
-        let value = self.parse_expression(Precedence::Lowest)?;
+        let mut value = self.parse_expression(Precedence::Lowest)?;
+
+        // compound assignments are desugared, e.g. `x += 1` is parsed as `x = x + 1`
+        if let Some(operator) = operator_token.token_type.compound_operator() {
+            let infix = InfixExpression {
+                token: operator_token,
+                left: Some(Box::new(AllExpressions::Identifier(ident.clone()))),
+                operator: operator.to_string(),
+                right: Some(value),
+            };
+            value = Box::new(AllExpressions::InfixExpression(infix));
+        }
 
         let expr = AssignmentExpression {
             token,
@@ -103,22 +120,55 @@ pub fn parse_identifier(p: &mut Parser) -> BoxedExpression {
 
 pub fn parse_integer_literal(p: &mut Parser) -> BoxedExpression {
     let trace_msg = p.tracer.trace("parseIntegerLiteral");
-    let value = match p.current_token.literal.parse::<i64>() {
-        Ok(v) => v,
-        Err(e) => {
-            let msg = format!("could not parse as integer: {:?}", e);
-            p.errors.push(msg);
+    let expr = match p.current_token.literal.parse::<i64>() {
+        Ok(value) => AllExpressions::IntegerLiteral(expressions::IntegerLiteral {
+            token: p.current_token.clone(),
+            value,
+        }),
+        // integers never overflow, so larger literals are big integers
+        Err(e) => match BigInt::parse(&p.current_token.literal) {
+            Some(value) => AllExpressions::BigIntegerLiteral(expressions::BigIntegerLiteral {
+                token: p.current_token.clone(),
+                value,
+            }),
+            None => {
+                let err = ParseError::InvalidInteger {
+                    literal: p.current_token.literal.clone(),
+                    source: e,
+                };
+                p.errors.push(Diagnostic::new(err, p.current_token.span));
+                return None;
+            }
+        },
+    };
+
+    p.tracer.un_trace(trace_msg);
+    Some(Box::new(expr))
+}
+
+pub fn parse_float_literal(p: &mut Parser) -> BoxedExpression {
+    let trace_msg = p.tracer.trace("parseFloatLiteral");
+    // valid literals parse to infinity when they're too large, while the literals with an exponent
+    // but no digits are already reported by the lexer and kept to parse the rest of the expression
+    let value = match p.current_token.literal.parse::<f64>() {
+        Ok(v) if v.is_finite() => v,
+        Err(_) => f64::NAN,
+        Ok(_) => {
+            let err = ParseError::InvalidFloat {
+                literal: p.current_token.literal.clone(),
+            };
+            p.errors.push(Diagnostic::new(err, p.current_token.span));
             return None;
         }
     };
 
-    let expr = expressions::IntegerLiteral {
+    let expr = expressions::FloatLiteral {
         token: p.current_token.clone(),
         value,
     };
 
     p.tracer.un_trace(trace_msg);
-    Some(Box::new(AllExpressions::IntegerLiteral(expr)))
+    Some(Box::new(AllExpressions::FloatLiteral(expr)))
 }
 
 pub fn parse_string_literal(p: &mut Parser) -> BoxedExpression {
@@ -132,8 +182,11 @@ pub fn parse_string_literal(p: &mut Parser) -> BoxedExpression {
 
 pub fn parse_null_literal(p: &mut Parser) -> BoxedExpression {
     let trace_msg = p.tracer.trace("parseNullLiteral");
+    let null_literal = NullLiteral {
+        token: p.current_token.clone(),
+    };
     p.tracer.un_trace(trace_msg);
-    Some(Box::new(AllExpressions::NullLiteral))
+    Some(Box::new(AllExpressions::NullLiteral(null_literal)))
 }
 
 pub fn parse_boolean_expression(p: &mut Parser) -> BoxedExpression {
@@ -220,10 +273,15 @@ pub fn parse_if_expression(p: &mut Parser) -> BoxedExpression {
     let mut alternative = None;
     if p.peek_token_is(&TokenType::Else) {
         p.next_token(); // consumes else
-        if !p.expect_peek(TokenType::Lbrace) {
-            return None;
+        if p.peek_token_is(&TokenType::If) {
+            p.next_token();
+            alternative = Some(parse_else_if_block(p)?);
+        } else {
+            if !p.expect_peek(TokenType::Lbrace) {
+                return None;
+            }
+            alternative = Some(parse_block_statement(p));
         }
-        alternative = Some(parse_block_statement(p));
     }
 
     let if_expr = IfExpression {
@@ -236,6 +294,23 @@ pub fn parse_if_expression(p: &mut Parser) -> BoxedExpression {
     Some(Box::new(AllExpressions::IfExpression(if_expr)))
 }
 
+/// Parses the `if` of an `else if` into an alternative block that only contains the nested if
+/// expression. The block takes the `if` token, which tells it apart from an `else { if ... }`.
+fn parse_else_if_block(p: &mut Parser) -> Option<BlockStatement> {
+    let token = p.current_token.clone();
+    let nested = parse_if_expression(p)?;
+
+    let stmt = ExpressionStatement {
+        token: token.clone(),
+        expression: Some(nested),
+    };
+
+    Some(BlockStatement {
+        token,
+        statements: vec![AllStatements::Expression(stmt)],
+    })
+}
+
 pub fn parse_function_literal(p: &mut Parser) -> BoxedExpression {
     let token = p.current_token.clone();
 
@@ -288,36 +363,48 @@ pub fn parse_array_literal(p: &mut Parser) -> BoxedExpression {
     Some(Box::new(array))
 }
 
+/// Parses index expressions such as `arr[1]` and range expressions such as `arr[1:3]`.
+///
+/// Both bounds of a range are optional, so `arr[2:]`, `arr[:3]` and `arr[:]` are valid as well.
 pub fn parse_index_expressions(p: &mut Parser, left: BoxedExpression) -> BoxedExpression {
     let token = p.current_token.clone();
+    let left = left?;
     p.next_token(); // consume [
 
-    let index = p.parse_expression(Precedence::Lowest);
-    let mut right = None;
+    let mut left_index = None;
+    if !p.current_token_is(&TokenType::Colon) {
+        let index = p.parse_expression(Precedence::Lowest)?;
 
-    if p.peek_token_is(&TokenType::Colon) {
-        p.next_token();
-        p.next_token();
-        right = p.parse_expression(Precedence::Lowest);
+        if !p.peek_token_is(&TokenType::Colon) {
+            if !p.expect_peek(TokenType::Rbracket) {
+                return None;
+            }
+            return Some(Box::new(AllExpressions::IndexExpression(IndexExpression {
+                token,
+                left,
+                index,
+            })));
+        }
+
+        left_index = Some(index);
+        p.next_token(); // the current token is now the :
     }
 
-    if !p.expect_peek(TokenType::Rbracket) {
-        return None;
+    let mut right_index = None;
+    if !p.peek_token_is(&TokenType::Rbracket) {
+        p.next_token(); // consume :
+        right_index = Some(p.parse_expression(Precedence::Lowest)?);
     }
 
-    if right.is_none() {
-        return Some(Box::new(AllExpressions::IndexExpression(IndexExpression {
-            token,
-            left: left?,
-            index: index?,
-        })));
+    if !p.expect_peek(TokenType::Rbracket) {
+        return None;
     }
 
     Some(Box::new(AllExpressions::RangeExpression(RangeExpression {
         token,
-        left: left?,
-        left_index: index?,
-        right_index: right?,
+        left,
+        left_index,
+        right_index,
     })))
 }
 
@@ -337,7 +424,7 @@ fn parse_fn_literal_parameters(p: &mut Parser) -> Option<Vec<Identifier>> {
             break;
         }
 
-        if !p.expect_peek(TokenType::Comma) {
+        if !p.expect_list_separator(&TokenType::Rparen) {
             return None;
         }
     }
@@ -356,7 +443,7 @@ fn parse_comma_sep_arguments(p: &mut Parser, end: &TokenType) -> Option<Vec<AllE
             break;
         }
 
-        if !p.expect_peek(TokenType::Comma) {
+        if !p.expect_list_separator(end) {
             return None;
         }
     }
@@ -400,7 +487,7 @@ pub fn parse_hash_literal(p: &mut Parser) -> BoxedExpression {
 
         pairs.insert(*key, *value);
 
-        if !p.peek_token_is(&TokenType::Rbrace) && !p.expect_peek(TokenType::Comma) {
+        if !p.peek_token_is(&TokenType::Rbrace) && !p.expect_list_separator(&TokenType::Rbrace) {
             return None;
         }
     }
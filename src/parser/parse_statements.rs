@@ -1,5 +1,8 @@
 use crate::ast::expressions::{AllExpressions, Identifier};
-use crate::ast::statements::{AllStatements, LetStatement, ReturnStatement, WhileStatement};
+use crate::ast::statements::{
+    AllStatements, BreakStatement, ContinueStatement, ForStatement, LetStatement, ReturnStatement,
+    WhileStatement,
+};
 use crate::lexer::token::TokenType;
 
 use super::parse_expressions::parse_block_statement;
@@ -14,6 +17,9 @@ impl Parser {
             Let => self.parse_let_statement(),
             Return => self.parse_return_statement(),
             While => self.parse_while_statement(),
+            For => self.parse_for_statement(),
+            Break => self.parse_break_statement(),
+            Continue => self.parse_continue_statement(),
             _ => self.parse_expression_statement(),
         }
     }
@@ -104,4 +110,81 @@ impl Parser {
 
         Some(AllStatements::While(stmt))
     }
+
+    /// Parses `For` statements, which bind one or two loop variables
+    fn parse_for_statement(&mut self) -> Option<AllStatements> {
+        let token = self.current_token.clone();
+
+        if !self.expect_peek(TokenType::Lparen) {
+            return None;
+        }
+
+        let mut key = None;
+        let mut value = self.parse_loop_variable()?;
+        if self.peek_token_is(&TokenType::Comma) {
+            self.next_token();
+            key = Some(value);
+            value = self.parse_loop_variable()?;
+        }
+
+        if !self.expect_peek(TokenType::In) {
+            return None;
+        }
+        self.next_token();
+
+        let iterable = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+
+        if !self.expect_peek(TokenType::Lbrace) {
+            return None;
+        }
+
+        let body = parse_block_statement(self);
+
+        let stmt = ForStatement {
+            token,
+            key,
+            value,
+            iterable,
+            body,
+        };
+
+        Some(AllStatements::For(stmt))
+    }
+
+    /// Parses `Break` statements
+    fn parse_break_statement(&mut self) -> Option<AllStatements> {
+        let token = self.current_token.clone();
+
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        Some(AllStatements::Break(BreakStatement { token }))
+    }
+
+    /// Parses `Continue` statements
+    fn parse_continue_statement(&mut self) -> Option<AllStatements> {
+        let token = self.current_token.clone();
+
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        Some(AllStatements::Continue(ContinueStatement { token }))
+    }
+
+    fn parse_loop_variable(&mut self) -> Option<Identifier> {
+        if !self.expect_peek(TokenType::Ident) {
+            return None;
+        }
+
+        Some(Identifier {
+            token: self.current_token.clone(),
+            value: self.current_token.literal.clone(),
+        })
+    }
 }
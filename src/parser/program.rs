@@ -1,13 +1,15 @@
 use super::tracing::Tracer;
 use crate::ast::expressions::AllExpressions;
 use crate::ast::program::Program;
+use crate::diagnostics::Diagnostic;
+use crate::errors::ParseError;
 use crate::lexer::token::{eof_token, Token, TokenType};
 use crate::lexer::Lexer;
 use crate::parser::parse_expressions::{
-    parse_array_literal, parse_boolean_expression, parse_call_expression, parse_function_literal,
-    parse_grouped_expression, parse_hash_literal, parse_identifier, parse_if_expression,
-    parse_index_expressions, parse_infix_expression, parse_integer_literal, parse_null_literal,
-    parse_prefix_expression, parse_string_literal,
+    parse_array_literal, parse_boolean_expression, parse_call_expression, parse_float_literal,
+    parse_function_literal, parse_grouped_expression, parse_hash_literal, parse_identifier,
+    parse_if_expression, parse_index_expressions, parse_infix_expression, parse_integer_literal,
+    parse_null_literal, parse_prefix_expression, parse_string_literal,
 };
 
 /// A type alias for the optional boxed expression type that is commonly used in parser functions
@@ -22,7 +24,7 @@ pub type InfixParseFn = dyn Fn(&mut Parser, BoxedExpression) -> BoxedExpression;
 /// It includes the information needed for parsing as well as parser results
 pub struct Parser {
     pub l: Lexer,
-    pub errors: Vec<String>,
+    pub errors: Vec<Diagnostic<ParseError>>,
     pub tracer: Tracer,
 
     pub current_token: Token,
@@ -72,6 +74,7 @@ impl Parser {
         match token_type {
             Ident => Some(Box::new(parse_identifier)),
             Int => Some(Box::new(parse_integer_literal)),
+            Float => Some(Box::new(parse_float_literal)),
             String => Some(Box::new(parse_string_literal)),
             Bang | Minus | Plus => Some(Box::new(parse_prefix_expression)),
             True | False => Some(Box::new(parse_boolean_expression)),
@@ -90,9 +93,8 @@ impl Parser {
         use TokenType::*;
 
         match token_type {
-            Plus | Minus | Asterisk | Slash | Eq | NotEq | Lt | Gt => {
-                Some(Box::new(parse_infix_expression))
-            }
+            Plus | Minus | Asterisk | Slash | Percent | Eq | NotEq | Lt | Gt | LtEq | GtEq
+            | And | Or => Some(Box::new(parse_infix_expression)),
             Lparen => Some(Box::new(parse_call_expression)),
             Lbracket => Some(Box::new(parse_index_expressions)),
             _ => None,
@@ -106,9 +108,10 @@ mod tests {
     use std::collections::HashMap;
 
     use super::test_helpers::*;
+    use super::{Lexer, Parser};
     use crate::ast::expressions::AllExpressions;
     use crate::ast::statements::AllStatements;
-    use crate::lexer::keywords;
+    use crate::lexer::{keywords, token::TokenType};
 
     #[test]
     fn test_let_statements() {
@@ -185,6 +188,57 @@ mod tests {
         assert_eq!(stmt.body.statements.len(), 1);
     }
 
+    #[test]
+    fn test_for_statements() {
+        let input = "
+            for (x in [1, 2]) { x; }
+            for (k, v in items(1)) { let y = k; v; }
+            ";
+        let mut program = helper_prepare_parser(input);
+        assert_eq!(program.statements.len(), 2);
+
+        let AllStatements::For(stmt) = program.statements.remove(0) else {
+            panic!("{}", EXPECTED_FOR);
+        };
+        assert_eq!(stmt.token.literal, keywords::FOR);
+        assert!(stmt.key.is_none());
+        assert_eq!(stmt.value.value, "x");
+        assert!(matches!(*stmt.iterable, AllExpressions::ArrayLiteral(_)));
+        assert_eq!(stmt.body.statements.len(), 1);
+
+        let AllStatements::For(stmt) = program.statements.remove(0) else {
+            panic!("{}", EXPECTED_FOR);
+        };
+        assert_eq!(stmt.key.expect(EXPECTED_IDENT).value, "k");
+        assert_eq!(stmt.value.value, "v");
+        assert!(matches!(*stmt.iterable, AllExpressions::CallExpression(_)));
+        assert_eq!(stmt.body.statements.len(), 2);
+    }
+
+    #[test]
+    fn test_break_and_continue_statements() {
+        let input = "
+            while (true) { continue; break }
+            ";
+        let mut program = helper_prepare_parser(input);
+        assert_eq!(program.statements.len(), 1);
+
+        let AllStatements::While(mut stmt) = program.statements.remove(0) else {
+            panic!("{}", EXPECTED_WHILE);
+        };
+        assert_eq!(stmt.body.statements.len(), 2);
+
+        let AllStatements::Continue(continue_stmt) = stmt.body.statements.remove(0) else {
+            panic!("{}", EXPECTED_CONTINUE);
+        };
+        assert_eq!(continue_stmt.token.literal, keywords::CONTINUE);
+
+        let AllStatements::Break(break_stmt) = stmt.body.statements.remove(0) else {
+            panic!("{}", EXPECTED_BREAK);
+        };
+        assert_eq!(break_stmt.token.literal, keywords::BREAK);
+    }
+
     #[test]
     fn test_parse_assignment_expressions() {
         let input = "x = 10;";
@@ -196,7 +250,20 @@ mod tests {
         };
 
         assert_eq!(expr.ident.value, "x");
-        helper_test_integer_literal(&*expr.value, 10);
+        helper_test_integer_literal(&expr.value, 10);
+
+        // compound assignments are parsed as an assignment of the infix expression
+        let tests = vec![
+            ("x += 1", "x = (x + 1)\n"),
+            ("x -= y * 2;", "x = (x - (y * 2))\n"),
+            ("x *= 2", "x = (x * 2)\n"),
+            ("x /= 2", "x = (x / 2)\n"),
+            ("x %= 2", "x = (x % 2)\n"),
+        ];
+        for tc in tests {
+            let program = helper_prepare_parser(tc.0);
+            assert_eq!(tc.1, program.to_string());
+        }
     }
 
     #[test]
@@ -217,6 +284,55 @@ mod tests {
         helper_test_integer_literal(&expr, 5);
     }
 
+    #[test]
+    fn test_big_integer_literal_expression() {
+        // input, expected value
+        let test_cases = [
+            ("9223372036854775808;", "9223372036854775808"),
+            ("99999999999999999999;", "99999999999999999999"),
+        ];
+
+        for tc in test_cases {
+            let mut program = helper_prepare_parser(tc.0);
+            assert_eq!(program.statements.len(), 1);
+
+            let expr = helper_get_expression(program.statements.remove(0));
+            let AllExpressions::BigIntegerLiteral(literal) = expr else {
+                panic!("{}", EXPECTED_BIG_INTEGER);
+            };
+            assert_eq!(literal.value.to_string(), tc.1);
+        }
+
+        // the smallest integer is the negation of a literal that is one too large
+        let mut program = helper_prepare_parser("-9223372036854775808;");
+        let expr = helper_get_expression(program.statements.remove(0));
+        let AllExpressions::PrefixExpression(prefix) = expr else {
+            panic!("{}", EXPECTED_PREFIX);
+        };
+        assert!(matches!(
+            prefix.right.as_deref(),
+            Some(AllExpressions::BigIntegerLiteral(_))
+        ));
+    }
+
+    #[test]
+    fn test_float_literal_expression() {
+        // input, expected value
+        let test_cases = [("2.75;", 2.75), ("1e-3;", 0.001), ("2.5E+2;", 250.0)];
+
+        for tc in test_cases {
+            let mut program = helper_prepare_parser(tc.0);
+            assert_eq!(program.statements.len(), 1);
+
+            let expr = helper_get_expression(program.statements.remove(0));
+            let AllExpressions::FloatLiteral(float_literal) = expr else {
+                panic!("{}", EXPECTED_FLOAT);
+            };
+            assert_eq!(float_literal.value, tc.1);
+            assert_eq!(float_literal.token.literal, tc.0.trim_end_matches(';'));
+        }
+    }
+
     #[test]
     fn test_boolean_expression_statement() {
         let mut program = helper_prepare_parser("true;");
@@ -243,7 +359,7 @@ mod tests {
             assert_eq!(prefix_exp.operator, tc.1);
             let right_expr = prefix_exp.right.expect(EXPECTED_RIGHT);
 
-            helper_test_integer_literal(&*right_expr, tc.2);
+            helper_test_integer_literal(&right_expr, tc.2);
         }
     }
 
@@ -268,6 +384,11 @@ mod tests {
             ("false == false", Bool(false), "==", Bool(false)),
             ("alice * bob", Ident("alice"), "*", Ident("bob")),
             ("\"foo\" != \"bar\"", Str("foo"), "!=", Str("bar")),
+            ("true && false", Bool(true), "&&", Bool(false)),
+            ("a || b", Ident("a"), "||", Ident("b")),
+            ("5 % 5;", Int(5_i64), "%", Int(5_i64)),
+            ("5 <= 5;", Int(5_i64), "<=", Int(5_i64)),
+            ("5 >= 5;", Int(5_i64), ">=", Int(5_i64)),
         ];
         for tc in infix_tests {
             let mut program = helper_prepare_parser(tc.0);
@@ -294,6 +415,13 @@ mod tests {
             ("3 + 4; -5 * 5", "(3 + 4)\n((-5) * 5)\n"),
             ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))\n"),
             ("5 < 4 != 3 > 4", "((5 < 4) != (3 > 4))\n"),
+            ("a || b && c", "(a || (b && c))\n"),
+            ("a && b || c && d", "((a && b) || (c && d))\n"),
+            ("a == b && !c", "((a == b) && (!c))\n"),
+            ("a || b || c", "((a || b) || c)\n"),
+            ("a + b % c", "(a + (b % c))\n"),
+            ("a <= b == c >= d", "((a <= b) == (c >= d))\n"),
+            ("-1.5 * 2e3", "((-1.5) * 2000.0)\n"),
             (
                 "3 + 4 * 5 == 3 * 1 + 4 * 5",
                 "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)))\n",
@@ -389,6 +517,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_else_if_expression() {
+        use Literal::{Ident, Int};
+
+        let mut program = helper_prepare_parser("if (x) { 1 } else if (y) { 2 } else { 3 }");
+        assert_eq!(program.statements.len(), 1);
+
+        let expr = helper_get_expression(program.statements.remove(0));
+        let AllExpressions::IfExpression(if_expr) = expr else {
+            panic!("{}", EXPECTED_IF);
+        };
+        helper_test_identifier(*if_expr.condition, "x");
+
+        // the `else if` is an alternative that holds the nested if expression
+        let mut alternative = if_expr.alternative.expect(EXPECTED_ALTERNATIVE);
+        assert_eq!(alternative.token.token_type, TokenType::If);
+        assert_eq!(alternative.statements.len(), 1);
+
+        let expr = helper_get_expression(alternative.statements.remove(0));
+        let AllExpressions::IfExpression(mut nested) = expr else {
+            panic!("{}", EXPECTED_IF);
+        };
+        helper_test_literal(Ident("y"), *nested.condition);
+        let consequence = nested.consequence.statements.remove(0);
+        helper_test_literal(Int(2), helper_get_expression(consequence));
+
+        let mut alternative = nested.alternative.expect(EXPECTED_ALTERNATIVE);
+        assert_eq!(alternative.token.token_type, TokenType::Lbrace);
+        let alternative = alternative.statements.remove(0);
+        helper_test_literal(Int(3), helper_get_expression(alternative));
+    }
+
     #[test]
     fn test_functional_literal() {
         let mut program = helper_prepare_parser("fn(x, y) { x + y; }");
@@ -538,8 +698,32 @@ mod tests {
         };
 
         helper_test_identifier(*expr.left, "myArray");
-        helper_test_infix_expression(*expr.left_index, Int(1), "+", Int(1));
-        helper_test_infix_expression(*expr.right_index, Ident("x"), "-", Int(20));
+        helper_test_infix_expression(*expr.left_index.unwrap(), Int(1), "+", Int(1));
+        helper_test_infix_expression(*expr.right_index.unwrap(), Ident("x"), "-", Int(20));
+    }
+
+    #[test]
+    fn test_parse_open_ended_range_expressions() {
+        // input, has left index, has right index
+        let test_cases = [
+            ("myArray[2:]", true, false),
+            ("myArray[:3]", false, true),
+            ("myArray[:]", false, false),
+        ];
+
+        for tc in test_cases {
+            let mut program = helper_prepare_parser(tc.0);
+            assert_eq!(program.statements.len(), 1);
+
+            let AllExpressions::RangeExpression(expr) = helper_get_expression(program.statements.remove(0)) else {
+                panic!("{}", EXPECTED_RANGE_EXPRESSION);
+            };
+
+            helper_test_identifier(*expr.left.clone(), "myArray");
+            assert_eq!(expr.left_index.is_some(), tc.1);
+            assert_eq!(expr.right_index.is_some(), tc.2);
+            assert_eq!(expr.to_string(), format!("({})", tc.0));
+        }
     }
 
     #[test]
@@ -572,6 +756,62 @@ mod tests {
         };
         assert_eq!(expr.pairs.len(), 0);
     }
+
+    #[test]
+    fn test_parser_error_locations() {
+        // input, expected errors with their locations
+        let test_cases = [
+            ("let x 5;", vec!["1:7: expected '=', found integer `5`"]),
+            (
+                "let x = 1;\nputs(x, 2;",
+                vec!["2:10: expected ',' or ')', found ';'"],
+            ),
+            ("[1, 2", vec!["1:6: expected ',' or ']', found end of file"]),
+            (
+                "for (x of xs) {}",
+                vec![
+                    "1:8: expected 'in', found identifier `of`",
+                    "1:13: expected an expression, found ')'",
+                ],
+            ),
+            (
+                "1e400",
+                vec!["1:1: could not parse 1e400 as float: number too large"],
+            ),
+            (
+                "let a = 1e; a",
+                vec!["1:9: malformed float literal 1e: the exponent has no digits"],
+            ),
+            (
+                "1e+",
+                vec!["1:1: malformed float literal 1e+: the exponent has no digits"],
+            ),
+            (
+                "let s = \"a\\qb\";\nputs(\"oops);",
+                vec![
+                    "1:11: invalid escape sequence `\\q` in string",
+                    "2:6: unterminated string",
+                    "2:13: expected ',' or ')', found end of file",
+                ],
+            ),
+            (
+                "// the answer\nlet x = 42; /* unfinished /* */",
+                vec!["2:13: unterminated block comment"],
+            ),
+        ];
+
+        for tc in test_cases {
+            let mut p = Parser::new(Lexer::new(tc.0));
+            p.parse_program();
+
+            let errors: Vec<String> = p
+                .errors
+                .iter()
+                .map(|e| format!("{}: {}", e.span, e))
+                .collect();
+            assert_eq!(errors, tc.1, "input: {}", tc.0);
+        }
+    }
 }
 
 /// Contains helper functions and constants useful for testing parsing
@@ -581,6 +821,8 @@ mod test_helpers {
     use crate::ast::expressions::AllExpressions;
     use crate::ast::program::Program;
     use crate::ast::statements::AllStatements;
+    use crate::diagnostics::Diagnostic;
+    use crate::errors::ParseError;
 
     pub enum Literal<'a> {
         Int(i64),
@@ -589,14 +831,14 @@ mod test_helpers {
         Str(&'a str),
     }
 
-    pub fn helper_check_parser_errors(errors: &Vec<String>) {
+    pub fn helper_check_parser_errors(errors: &[Diagnostic<ParseError>]) {
         if errors.is_empty() {
             return;
         }
 
         let mut err_msg = String::new();
         for msg in errors {
-            err_msg.push_str(format!("\tparser error: {}\n", msg).as_str());
+            err_msg.push_str(format!("\tparser error: {}: {}\n", msg.span, msg).as_str());
         }
 
         panic!("parser has {} error(s)\n{}", errors.len(), err_msg);
@@ -659,7 +901,7 @@ mod test_helpers {
         let AllStatements::Expression(expr_stmt) = stmt else {
             panic!("{}", EXPECTED_EXPRESSION_STATEMENT); 
         };
-        return *expr_stmt.expression.expect(EXPECTED_EXPRESSION);
+        *expr_stmt.expression.expect(EXPECTED_EXPRESSION)
     }
 
     pub fn helper_test_literal(expected: Literal, expr: AllExpressions) {
@@ -675,12 +917,18 @@ mod test_helpers {
     pub const EXPECTED_LET: &str = "expected a let statement";
     pub const EXPECTED_RETURN: &str = "expected a return statement";
     pub const EXPECTED_WHILE: &str = "expected a while statement";
+    pub const EXPECTED_FOR: &str = "expected a for statement";
+    pub const EXPECTED_BREAK: &str = "expected a break statement";
+    pub const EXPECTED_CONTINUE: &str = "expected a continue statement";
     pub const EXPECTED_INTEGER: &str = "expected an integer literal";
+    pub const EXPECTED_FLOAT: &str = "expected a float literal";
+    pub const EXPECTED_BIG_INTEGER: &str = "expected a big integer literal";
     pub const EXPECTED_STRING: &str = "expected a string literal";
     pub const EXPECTED_BOOLEAN: &str = "expected a boolean expression";
     pub const EXPECTED_PREFIX: &str = "expected a prefix expression";
     pub const EXPECTED_INFIX: &str = "expected an infix expression";
     pub const EXPECTED_IF: &str = "expected an if expression";
+    pub const EXPECTED_ALTERNATIVE: &str = "expected an alternative block";
     pub const EXPECTED_FUNC: &str = "expected an function literal expression";
     pub const EXPECTED_CALL: &str = "expected a call expression";
     pub const EXPECTED_LEFT: &str = "expected the left expression to exist";
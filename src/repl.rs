@@ -1,11 +1,13 @@
 use crate::{
-    compiler::{Compiler, SymbolTable},
+    compiler::{ByteCode, Compiler, SymbolTable},
+    diagnostics::Diagnostic,
+    errors::ParseError,
     lexer::Lexer,
     object::{AllObjects, Object},
     parser::{Parser, TRACING_ENABLED},
     vm,
 };
-use clap::Parser as ClapParser;
+use clap::{ArgAction, Parser as ClapParser};
 use std::{
     io::{self, BufRead, Write},
     rc::Rc,
@@ -13,6 +15,9 @@ use std::{
 
 const PROMPT: &str = ">> ";
 
+/// Name used for the source code in diagnostics that are reported by the REPL
+const REPL_SOURCE_NAME: &str = "<repl>";
+
 /// The monkey programming language REPL (Read -> Evaluate -> Print -> Loop)
 #[derive(ClapParser)]
 #[clap(author, version, about, long_about = None)]
@@ -20,6 +25,10 @@ struct Args {
     /// Enables tracing for parsing expressions
     #[clap(short, long, value_parser, default_value_t = false)]
     tracing: bool,
+
+    /// Folds constant expressions and removes dead branches, repeat it for peephole optimizations
+    #[clap(short = 'O', action = ArgAction::Count)]
+    optimize: u8,
 }
 
 pub fn start_repl<T: BufRead, U: Write>(input: &mut T, output: &mut U) -> io::Result<()> {
@@ -48,8 +57,14 @@ pub fn start_repl<T: BufRead, U: Write>(input: &mut T, output: &mut U) -> io::Re
         }
 
         if !trimmed.is_empty() {
-            (constants, globals, symbol_table) =
-                execute_line_for_repl(&text, output, constants, globals, symbol_table)?;
+            (constants, globals, symbol_table) = execute_line_for_repl(
+                &text,
+                args.optimize,
+                output,
+                constants,
+                globals,
+                symbol_table,
+            )?;
         }
 
         text.clear();
@@ -68,12 +83,17 @@ fn greet<U: Write>(output: &mut U) -> io::Result<()> {
     Ok(())
 }
 
-fn write_parser_errors<U: Write>(errors: &[String], output: &mut U) -> io::Result<()> {
+fn write_parser_errors<U: Write>(
+    errors: &[Diagnostic<ParseError>],
+    source_name: &str,
+    source: &str,
+    output: &mut U,
+) -> io::Result<()> {
     writeln!(output, "{}", MONKEY_FACE)?;
     writeln!(output, "Woops! We ran into some monkey business here 🥴")?;
     writeln!(output, "parser Errors:")?;
     for e in errors {
-        writeln!(output, "\t- {}", e)?;
+        write!(output, "{}", e.render(source_name, source))?;
     }
     Ok(())
 }
@@ -92,35 +112,53 @@ const MONKEY_FACE: &str = r#"
            '-----'
 "#;
 
-pub fn execute_program<U: Write>(text: &str, output: &mut U) -> io::Result<()> {
+pub fn execute_program<U: Write>(
+    source_name: &str,
+    text: &str,
+    optimization_level: u8,
+    output: &mut U,
+) -> io::Result<()> {
     let l = Lexer::new(text);
     let mut p = Parser::new(l);
     let program = p.parse_program();
 
     if !p.errors.is_empty() {
-        write_parser_errors(&p.errors, output)?;
+        write_parser_errors(&p.errors, source_name, text, output)?;
         return Ok(());
     }
 
     let mut comp = Compiler::new();
+    comp.optimization_level = optimization_level;
     if let Err(e) = comp.compile(program.make_node()) {
-        write!(output, "Woops! Compilation failed:\n {}\n", e)?;
+        let rendered = e.render(source_name, text);
+        write!(output, "Woops! Compilation failed:\n{}", rendered)?;
         return Ok(());
     }
 
-    let mut machine = vm::VM::new(comp.byte_code());
+    execute_bytecode(source_name, text, comp.byte_code(), output)
+}
+
+/// Runs the compiled bytecode and writes the result to the output.
+///
+/// The source code is only used for showing the failing lines in runtime errors, so it can be
+/// empty when it's not available (e.g. when running a bytecode file).
+pub fn execute_bytecode<U: Write>(
+    source_name: &str,
+    source: &str,
+    bytecode: ByteCode,
+    output: &mut U,
+) -> io::Result<()> {
+    let mut machine = vm::VM::new(bytecode);
     if let Err(e) = machine.run() {
-        write!(output, "Woops! Executing bytecode failed:\n {}\n", e)?;
+        let mut rendered = machine.traceback().render(source_name, source);
+        rendered.push_str(&e.render(source_name, source));
+        write!(output, "Woops! Executing bytecode failed:\n{}", rendered)?;
         return Ok(());
     }
 
-    let Some(stack_top) = machine.result() else {
-        writeln!(output, "Woops! Stack top is empty")?;
-        return Ok(());
-    };
-
-    if !stack_top.is_null() {
-        writeln!(output, "{}", stack_top.inspect())?;
+    // a program that ends with a loop leaves nothing on the stack, so it has no result
+    if let Some(result) = machine.result().filter(|obj| !obj.is_null()) {
+        writeln!(output, "{}", result.inspect())?;
     }
 
     Ok(())
@@ -128,6 +166,7 @@ pub fn execute_program<U: Write>(text: &str, output: &mut U) -> io::Result<()> {
 
 pub fn execute_line_for_repl<U: Write>(
     text: &str,
+    optimization_level: u8,
     output: &mut U,
     constants: Vec<AllObjects>,
     globals: Vec<AllObjects>,
@@ -138,13 +177,15 @@ pub fn execute_line_for_repl<U: Write>(
     let program = p.parse_program();
 
     if !p.errors.is_empty() {
-        write_parser_errors(&p.errors, output)?;
+        write_parser_errors(&p.errors, REPL_SOURCE_NAME, text, output)?;
         return Ok((constants, globals, symbol_table));
     }
 
     let mut comp = Compiler::new_with_state(symbol_table, constants);
+    comp.optimization_level = optimization_level;
     if let Err(e) = comp.compile(program.make_node()) {
-        write!(output, "Woops! Compilation failed:\n {}\n", e)?;
+        let rendered = e.render(REPL_SOURCE_NAME, text);
+        write!(output, "Woops! Compilation failed:\n{}", rendered)?;
         return Ok((comp.constants, globals, comp.symbol_table));
     }
     let modified_constants = comp.constants.clone();
@@ -152,17 +193,14 @@ pub fn execute_line_for_repl<U: Write>(
 
     let mut machine = vm::VM::new_with_global_store(comp.byte_code(), globals);
     if let Err(e) = machine.run() {
-        write!(output, "Woops! Executing bytecode failed:\n {}\n", e)?;
+        let mut rendered = machine.traceback().render(REPL_SOURCE_NAME, text);
+        rendered.push_str(&e.render(REPL_SOURCE_NAME, text));
+        write!(output, "Woops! Executing bytecode failed:\n{}", rendered)?;
         return Ok((modified_constants, machine.globals, modified_symbol_table));
     }
 
-    let Some(stack_top) = machine.result() else {
-        writeln!(output, "Woops! Stack top is empty")?;
-        return Ok((modified_constants, machine.globals, modified_symbol_table));
-    };
-
-    if !stack_top.is_null() {
-        writeln!(output, "{}", stack_top.inspect())?;
+    if let Some(result) = machine.result().filter(|obj| !obj.is_null()) {
+        writeln!(output, "{}", result.inspect())?;
     }
 
     Ok((modified_constants, machine.globals, modified_symbol_table))
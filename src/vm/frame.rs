@@ -1,7 +1,4 @@
-use crate::{
-    code::Instructions,
-    object::{objects::Closure, AllObjects},
-};
+use crate::{code::Instructions, object::objects::Closure};
 
 #[derive(Clone)]
 pub struct Frame {
@@ -11,18 +8,18 @@ pub struct Frame {
     /// instruction pointer, which points the index of the currently executing opcode
     pub ip: usize,
 
-    /// holder of local variable objects
-    pub locals: Vec<AllObjects>,
+    /// stack index of the first local, which is the first argument. Locals occupy the slots from
+    /// here up to the number of locals of the function and the operands are pushed above them.
+    pub base_pointer: usize,
 }
 
 impl Frame {
-    /// Create a new frame with the compiled function and an arguments vector as the initial
-    /// locals list.
-    pub fn new(closure: Closure, arguments: Vec<AllObjects>) -> Self {
+    /// Create a new frame with the compiled function, whose locals start at the given stack index
+    pub fn new(closure: Closure, base_pointer: usize) -> Self {
         Self {
             closure,
             ip: 0,
-            locals: arguments,
+            base_pointer,
         }
     }
 
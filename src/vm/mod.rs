@@ -4,19 +4,23 @@ mod run;
 use self::frame::Frame;
 use crate::{
     compiler::ByteCode,
+    errors::RuntimeError,
     object::{
-        objects::{Boolean, Closure, CompiledFunctionObj, Null},
+        objects::{Boolean, Closure, CompiledFunctionObj, Null, Upvalue, UpvalueRef},
         AllObjects,
     },
 };
-use anyhow::{anyhow, Result};
 
-/// Maximum number of objects that can be at a given time in the stack
-const STACK_SIZE: usize = 2048;
+/// Result of executing instructions, see [`RuntimeError`]
+type Result<T> = std::result::Result<T, RuntimeError>;
 
 /// Maximum number of stack frames that can exist at a given time
 const MAX_FRAMES: usize = 1024;
 
+/// Maximum number of objects that can be at a given time in the stack, which holds the locals and
+/// the operands of every frame
+const STACK_SIZE: usize = 64 * MAX_FRAMES;
+
 /// TRUE constant
 const TRUE: AllObjects = AllObjects::Boolean(Boolean { value: true });
 
@@ -44,24 +48,33 @@ pub struct VM {
 
     /// current active frame
     frames_index: usize,
+
+    /// upvalues that still point to a local on the stack
+    open_upvalues: Vec<UpvalueRef>,
+
+    /// whether the instruction that is running was prefixed with `OP_WIDE`
+    wide: bool,
 }
 
 impl VM {
     /// Creates a new VM using the provided bytecode
     pub fn new(bytecode: ByteCode) -> Self {
-        let main_fn = CompiledFunctionObj::new(bytecode.instructions, 0);
-        let main_frame = Frame::new(Closure::new(main_fn, vec![]), vec![]);
+        let mut main_fn = CompiledFunctionObj::new(bytecode.instructions, 0);
+        main_fn.source_map = bytecode.source_map;
+        let main_frame = Frame::new(Closure::new(main_fn, vec![]), 0);
 
         let mut frames = Vec::with_capacity(MAX_FRAMES);
         frames.push(main_frame);
 
         Self {
             constants: bytecode.constants,
-            stack: Vec::with_capacity(STACK_SIZE),
+            stack: Vec::new(),
             globals: Vec::new(),
             result: None,
             frames,
             frames_index: 1,
+            open_upvalues: Vec::new(),
+            wide: false,
         }
     }
 
@@ -80,7 +93,7 @@ impl VM {
     /// Pushes the given object on to the stack and increments the stack pointer.
     fn push(&mut self, val: AllObjects) -> Result<()> {
         if self.stack.len() >= STACK_SIZE {
-            return Err(anyhow!("stack overflow"));
+            return Err(RuntimeError::StackOverflow);
         }
         self.stack.push(val);
         Ok(())
@@ -91,7 +104,7 @@ impl VM {
     /// If the stack is empty after this call and the instructions are empty, this also sets the final result to be returned.
     fn pop(&mut self) -> Result<AllObjects> {
         let Some(obj)  = self.stack.pop() else {
-            return Err(anyhow!("stack is empty"));
+            return Err(RuntimeError::StackUnderflow);
         };
 
         if self.stack.is_empty()
@@ -112,9 +125,31 @@ impl VM {
         self.frames_index += 1;
     }
 
+    /// Removes the current frame along with its locals, operands and the called function below
+    /// them from the stack.
     fn pop_frame(&mut self) -> Frame {
         self.frames_index -= 1;
-        self.frames.pop().unwrap()
+        let frame = self.frames.pop().unwrap();
+        self.close_upvalues(frame.base_pointer);
+        self.stack.truncate(frame.base_pointer - 1);
+        frame
+    }
+
+    /// Moves the locals at or above the given stack slot into their upvalues, so that closures
+    /// can keep using them after the frame that owns the locals is gone.
+    fn close_upvalues(&mut self, from_slot: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            let Upvalue::Open { slot } = *upvalue else {
+                return false;
+            };
+            if slot < from_slot {
+                return true;
+            }
+            *upvalue = Upvalue::Closed(stack.get(slot).cloned().unwrap_or(NULL));
+            false
+        });
     }
 }
 
@@ -123,8 +158,12 @@ mod tests {
     use std::collections::HashMap;
 
     use crate::{
+        code,
         compiler::{test_helpers::*, Compiler},
-        vm::VM,
+        errors::RuntimeError,
+        lexer::token::Span,
+        object::Object,
+        vm::{MAX_FRAMES, NULL, VM},
     };
 
     #[test]
@@ -165,10 +204,36 @@ mod tests {
             ("(1 < 2) == false", Bool(false)),
             ("(1 > 2) == true", Bool(false)),
             ("(1 > 2) == false", Bool(true)),
+            ("1 <= 2", Bool(true)),
+            ("2 <= 2", Bool(true)),
+            ("3 <= 2", Bool(false)),
+            ("1 >= 2", Bool(false)),
+            ("2 >= 2", Bool(true)),
+            ("true >= false", Bool(true)),
+            ("false >= true", Bool(false)),
+            ("false < true", Bool(true)),
+            ("true < true", Bool(false)),
+            ("true <= false", Bool(false)),
+            ("7 % 3", Int(1)),
+            ("-7 % 3", Int(-1)),
+            ("2 + 10 % 4 * 3", Int(8)),
+            // strings are compared lexicographically
+            (r#" "abc" == "abc" "#, Bool(true)),
+            (r#" "abc" != "abd" "#, Bool(true)),
+            (r#" "abc" < "abd" "#, Bool(true)),
+            (r#" "b" > "abc" "#, Bool(true)),
+            (r#" "ab" < "abc" "#, Bool(true)),
+            (r#" "abc" <= "abc" "#, Bool(true)),
+            (r#" "abc" >= "abd" "#, Bool(false)),
             ("-5", Int(-5)),
             ("-10", Int(-10)),
             ("-50 + 100 + -50", Int(0)),
             ("(5 + 10 * 2 + 15 / 3) * 2 + -10", Int(50)),
+            // the limits of the integers can be reached without overflowing
+            ("9223372036854775806 + 1", Int(i64::MAX)),
+            ("-9223372036854775807 - 1", Int(i64::MIN)),
+            ("-(-9223372036854775807)", Int(i64::MAX)),
+            ("(-9223372036854775807 - 1) % -1", Int(0)),
             ("!true", Bool(false)),
             ("!false", Bool(true)),
             ("!5", Bool(false)),
@@ -186,6 +251,14 @@ mod tests {
             ("if (false) { 10 }", Literal::Null),
             ("!(if (false) { 5; })", Bool(true)),
             ("if ((if (false) { 10 })) { 10 } else { 20 }", Int(20)),
+            ("if (false) { 10 } else if (true) { 20 } else { 30 }", Int(20)),
+            ("if (false) { 10 } else if (false) { 20 } else { 30 }", Int(30)),
+            ("if (false) { 10 } else if (false) { 20 }", Literal::Null),
+            (
+                "let grade = fn(n) { if (n > 89) { \"A\" } else if (n > 79) { \"B\" } else if (n > 69) { \"C\" } else { \"F\" } };
+                [grade(95), grade(85), grade(75), grade(10)]",
+                Arr(vec![Str("A"), Str("B"), Str("C"), Str("F")]),
+            ),
             ("let one = 1; one", Int(1)),
             ("let one = 1; let two = 2; one + two", Int(3)),
             ("let one = 1; let two = one + one; one + two", Int(3)),
@@ -447,32 +520,762 @@ mod tests {
                 Int(99),
             ),
         ];
-        let num_test_cases = test_cases.len();
+        run_vm_tests(test_cases);
+    }
+
+    #[test]
+    fn test_comparison_operand_order() {
+        use Literal::{Arr, Bool, Int};
+
+        // each call returns the next count, so the operands are seen in the order they run
+        let counter = "let x = 0; let f = fn() { x = x + 1; x };";
+        let test_cases = vec![
+            (format!("{counter} f() < f()"), Bool(true)),
+            (format!("{counter} f() <= f()"), Bool(true)),
+            (format!("{counter} f() > f()"), Bool(false)),
+            (format!("{counter} f() >= f()"), Bool(false)),
+            (
+                "let log = []; let g = fn(v) { push(log, v); v }; g(2) <= g(1); log".to_string(),
+                Arr(vec![Int(2), Int(1)]),
+            ),
+        ];
+
+        for (input, expected) in test_cases {
+            run_vm_tests(vec![(input.as_str(), expected)]);
+        }
+    }
+
+    #[test]
+    fn test_while_loops() {
+        use Literal::{Arr, Int};
+
+        let test_cases = vec![
+            ("while (false) { 10; } 5", Int(5)),
+            // the variables of a body that never runs are never set
+            ("while (false) { let x = 1; } let y = 2; y", Int(2)),
+            (
+                "let i = 0; while (i < 0) { let x = 1; } let y = 2; [i, y]",
+                Arr(vec![Int(0), Int(2)]),
+            ),
+            (
+                "let i = 0;
+                while (i < 10) { i = i + 1; }
+                i",
+                Int(10),
+            ),
+            (
+                "let i = 0;
+                let total = 0;
+                while (i < 3) {
+                    let j = 0;
+                    while (j < 4) {
+                        total = total + i * j;
+                        j = j + 1;
+                    }
+                    i = i + 1;
+                }
+                total",
+                Int(18),
+            ),
+            (
+                "let i = 0;
+                let evens = 0;
+                while (i < 10) {
+                    if (i / 2 * 2 == i) { evens = evens + 1; }
+                    if (true) { let unused = i; }
+                    i = i + 1;
+                }
+                evens",
+                Int(5),
+            ),
+            (
+                "let collect = fn(limit) {
+                    let out = [];
+                    while (len(out) < limit) {
+                        let next = len(out) * 10;
+                        push(out, next);
+                    }
+                    out
+                };
+                collect(3)",
+                Arr(vec![Int(0), Int(10), Int(20)]),
+            ),
+            (
+                "let counter = 0;
+                let make_runner = fn(limit) {
+                    fn() {
+                        while (counter < limit) { counter = counter + 1; }
+                        counter
+                    }
+                };
+                let runner = make_runner(7);
+                runner() + runner()",
+                Int(14),
+            ),
+            (
+                "let apply_n = fn(n, callback) {
+                    let results = [];
+                    while (len(results) < n) {
+                        push(results, callback(len(results)));
+                    }
+                    results
+                };
+                let i = 0;
+                let squares = [];
+                while (i < 2) {
+                    push(squares, apply_n(3, fn(x) { x * x + i }));
+                    i = i + 1;
+                }
+                squares",
+                Arr(vec![
+                    Arr(vec![Int(0), Int(1), Int(4)]),
+                    Arr(vec![Int(1), Int(2), Int(5)]),
+                ]),
+            ),
+        ];
+
+        run_vm_tests(test_cases);
+    }
 
-        for (i, tc) in test_cases.into_iter().enumerate() {
+    #[test]
+    fn test_for_loops() {
+        use Literal::{Arr, Int, Str};
+
+        let test_cases = vec![
+            (
+                "let total = 0; for (x in [1, 2, 3]) { total = total + x; } total",
+                Int(6),
+            ),
+            ("for (x in []) { x } 5", Int(5)),
+            // the loop variable of a loop that never runs is never set
+            (
+                "let total = 0; for (x in []) { total += 1; } let y = 2; [total, y]",
+                Arr(vec![Int(0), Int(2)]),
+            ),
+            (
+                "let out = []; for (c in \"abc\") { push(out, c); } out",
+                Arr(vec![Str("a"), Str("b"), Str("c")]),
+            ),
+            (
+                "let out = []; for (i, c in \"ab\") { push(out, [i, c]); } out",
+                Arr(vec![
+                    Arr(vec![Int(0), Str("a")]),
+                    Arr(vec![Int(1), Str("b")]),
+                ]),
+            ),
+            // hash maps are iterated in the order of their keys
+            (
+                "let out = []; for (k in {3: 0, 1: 0, 2: 0}) { push(out, k); } out",
+                Arr(vec![Int(1), Int(2), Int(3)]),
+            ),
+            (
+                "let out = []; for (k, v in {\"b\": 2, \"a\": 1}) { push(out, k + \"=\"); push(out, v); } out",
+                Arr(vec![Str("a="), Int(1), Str("b="), Int(2)]),
+            ),
+            (
+                "let out = []; for (i in range(3)) { push(out, i); } out",
+                Arr(vec![Int(0), Int(1), Int(2)]),
+            ),
+            (
+                "let out = []; for (i, x in range(5, 7)) { push(out, i * 10 + x); } out",
+                Arr(vec![Int(5), Int(16)]),
+            ),
+            ("let n = 0; for (i in range(3, 1)) { n = n + 1; } n", Int(0)),
+            ("len(range(2, 10))", Int(8)),
+            (
+                "let total = 0;
+                for (row in [[1, 2], [3, 4]]) {
+                    for (x in row) { total = total * 10 + x; }
+                }
+                total",
+                Int(1234),
+            ),
+            // loop variables are locals of the enclosing function, so closures share the last value
+            (
+                "let sum = fn(arr) { let total = 0; for (x in arr) { total = total + x; } total };
+                sum(range(101))",
+                Int(5050),
+            ),
+            (
+                "let makers = fn() {
+                    let fns = [];
+                    for (x in [1, 2]) { push(fns, fn() { x }); }
+                    fns
+                };
+                let fns = makers();
+                fns[0]() + fns[1]()",
+                Int(4),
+            ),
+            (
+                "let f = fn() { for (x in [1, 2, 3]) { if (x == 2) { return x * 100; } } 0 };
+                f()",
+                Int(200),
+            ),
+        ];
+
+        run_vm_tests(test_cases);
+    }
+
+    #[test]
+    fn test_for_loops_leave_stack_empty() {
+        let input = "let count = 0;
+            for (i in range(70000)) { i; count = count + 1; }
+            for (k, v in {1: 2}) { k + v; }";
+
+        let program = parse(input);
+        let mut comp = Compiler::new();
+        comp.compile(program.make_node()).unwrap();
+
+        let mut vm = VM::new(comp.byte_code());
+        if let Err(e) = vm.run() {
+            panic!("input: {}, vm error:  {}", input, e);
+        }
+        assert!(vm.stack.is_empty());
+        assert_eq!(vm.globals[0].inspect(), "70000");
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        use Literal::{Bool, Int, Null};
+
+        let test_cases = vec![
+            ("true && false", Bool(false)),
+            ("true && true", Bool(true)),
+            ("false || true", Bool(true)),
+            ("false || false", Bool(false)),
+            // the result is the operand that decided it
+            ("true && 5", Int(5)),
+            ("null && 5", Null),
+            ("0 && 5", Int(5)),
+            ("false || 7", Int(7)),
+            ("3 || 7", Int(3)),
+            ("null || false", Bool(false)),
+            ("1 < 2 && 2 < 3", Bool(true)),
+            ("false && true || true", Bool(true)),
+            ("true || false && false", Bool(true)),
+            ("if (1 > 2 || 2 > 1) { 10 } else { 20 }", Int(10)),
+            // the right side is only evaluated when needed
+            (
+                "let calls = 0; let f = fn() { calls = calls + 1; true };
+                false && f(); true || f(); calls",
+                Int(0),
+            ),
+            (
+                "let calls = 0; let f = fn() { calls = calls + 1; true };
+                true && f(); false || f(); calls",
+                Int(2),
+            ),
+        ];
+
+        run_vm_tests(test_cases);
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        use Literal::Int;
+
+        let test_cases = vec![
+            (
+                "let i = 0; while (true) { i = i + 1; if (i == 5) { break; } } i",
+                Int(5),
+            ),
+            (
+                "let sum = 0; let i = 0;
+                while (i < 10) { i = i + 1; if (i == 3) { continue; } sum = sum + i; }
+                sum",
+                Int(52),
+            ),
+            (
+                "let sum = 0; for (x in range(10)) { if (x == 5) { break; } sum = sum + x; } sum",
+                Int(10),
+            ),
+            (
+                "let sum = 0; for (x in [1, 2, 3, 4]) { if (x == 2) { continue; } sum = sum + x; } sum",
+                Int(8),
+            ),
+            // only the innermost loop is left
+            (
+                "let n = 0;
+                for (x in range(3)) { for (y in range(3)) { if (y > x) { break; } n = n + 1; } }
+                n",
+                Int(6),
+            ),
+            (
+                "let find = fn(xs, target) {
+                    let found = -1;
+                    for (i, x in xs) { if (x == target) { found = i; break; } }
+                    found
+                };
+                find([5, 6, 7], 6)",
+                Int(1),
+            ),
+            (
+                "let f = fn() { let i = 0; while (true) { i = i + 1; if (i > 3) { break; } } i }; f()",
+                Int(4),
+            ),
+            // the unfinished array and the sum are dropped from the stack
+            (
+                "let n = 0; for (x in range(5)) { n = n + [x, if (x == 3) { break; } else { 1 }][1]; } n",
+                Int(3),
+            ),
+            (
+                "let n = 0; for (x in range(4)) { n = n + [x, if (x == 1) { continue; } else { 1 }][1]; } n",
+                Int(3),
+            ),
+        ];
+
+        run_vm_tests(test_cases);
+    }
+
+    #[test]
+    fn test_break_and_continue_leave_stack_empty() {
+        let input = "let count = 0;
+            for (i in range(70000)) { count = count + [i, if (true) { continue; } else { 1 }][0]; }
+            while (true) { count + [1, if (true) { break; } else { 1 }][0]; }";
+
+        let program = parse(input);
+        let mut comp = Compiler::new();
+        comp.compile(program.make_node()).unwrap();
+
+        let mut vm = VM::new(comp.byte_code());
+        if let Err(e) = vm.run() {
+            panic!("input: {}, vm error:  {}", input, e);
+        }
+        assert!(vm.stack.is_empty());
+        assert_eq!(vm.globals[0].inspect(), "0");
+    }
+
+    #[test]
+    fn test_local_and_free_assignments() {
+        use Literal::{Arr, Int};
+
+        let test_cases = vec![
+            (
+                "let i = 100;
+                let f = fn() {
+                    let i = 0;
+                    i = i + 5;
+                    i
+                };
+                f() + i",
+                Int(105),
+            ),
+            (
+                "let f = fn(a) { a = a * 2; a };
+                f(21)",
+                Int(42),
+            ),
+            (
+                "let map = fn(arr, callback) {
+                    let new_arr = [];
+                    let i = 0;
+                    while (i < len(arr)) {
+                        push(new_arr, callback(arr[i]));
+                        i = i + 1;
+                    }
+                    return new_arr;
+                };
+                map([10, 20, 30], fn(x) { x + 1 })",
+                Arr(vec![Int(11), Int(21), Int(31)]),
+            ),
+            (
+                "let make = fn() {
+                    let count = 0;
+                    let inc = fn() { count = count + 1; count };
+                    let get = fn() { count };
+                    inc();
+                    inc();
+                    [count, get(), inc(), count]
+                };
+                make()",
+                Arr(vec![Int(2), Int(2), Int(3), Int(3)]),
+            ),
+            (
+                "let counter = fn() {
+                    let count = 0;
+                    [fn() { count = count + 1; count }, fn() { count }]
+                };
+                let fns = counter();
+                fns[0]();
+                fns[0]();
+                fns[1]()",
+                Int(2),
+            ),
+            (
+                "let counter = fn() {
+                    let count = 0;
+                    fn() { count = count + 1; count }
+                };
+                let a = counter();
+                let b = counter();
+                a();
+                a();
+                b();
+                [a(), b()]",
+                Arr(vec![Int(3), Int(2)]),
+            ),
+            (
+                "let outer = fn() {
+                    let x = 1;
+                    let middle = fn() { fn() { x = x + 10; x } };
+                    let inner = middle();
+                    inner();
+                    x = x + 100;
+                    [x, inner()]
+                };
+                outer()",
+                Arr(vec![Int(111), Int(121)]),
+            ),
+            (
+                "let sum = 0;
+                let adder = fn() {
+                    let total = 0;
+                    let add = fn(n) { total = total + n; sum = sum + n; };
+                    add(1);
+                    add(2);
+                    total
+                };
+                adder() + sum",
+                Int(6),
+            ),
+            (
+                "let x = 10; x += 5; x -= 3; x *= 4; x /= 6; x %= 5; x",
+                Int(3),
+            ),
+            (
+                "let s = \"mon\"; s += \"key\"; s",
+                Literal::Str("monkey"),
+            ),
+            (
+                "let f = fn(n) { let total = 0; let add = fn() { total += n; }; add(); add(); total };
+                f(4)",
+                Int(8),
+            ),
+        ];
+
+        run_vm_tests(test_cases);
+    }
+
+    #[test]
+    fn test_range_expressions() {
+        use Literal::{Arr, Int, Str};
+
+        let test_cases = vec![
+            ("[1, 2, 3, 4, 5][1:3]", Arr(vec![Int(2), Int(3)])),
+            ("[1, 2, 3, 4, 5][0 + 3:5]", Arr(vec![Int(4), Int(5)])),
+            ("[1, 2, 3][2:]", Arr(vec![Int(3)])),
+            ("[1, 2, 3][:2]", Arr(vec![Int(1), Int(2)])),
+            ("[1, 2, 3][:]", Arr(vec![Int(1), Int(2), Int(3)])),
+            ("[1, 2, 3][1:1]", Arr(vec![])),
+            ("[1, 2, 3][3:]", Arr(vec![])),
+            ("[][:]", Arr(vec![])),
+            (
+                "let arr = [1, 2, 3];
+                let sliced = arr[0:2];
+                push(sliced, 10);
+                len(arr) + len(sliced)",
+                Int(6),
+            ),
+            (r#""monkey"[1:4]"#, Str("onk")),
+            (r#""monkey"[3:]"#, Str("key")),
+            (r#""monkey"[:3]"#, Str("mon")),
+            (r#""monkey"[6:]"#, Str("")),
+            (r#""héllo"[1:2]"#, Str("é")),
+            (
+                r#"let s = "banana"; let n = 2; s[n:n + 2] + s[:1]"#,
+                Str("nab"),
+            ),
+        ];
+
+        run_vm_tests(test_cases);
+    }
+
+    #[test]
+    fn test_floats() {
+        use Literal::{Bool, Float, Int};
+
+        let test_cases = vec![
+            ("2.75", Float(2.75)),
+            ("1e-3", Float(0.001)),
+            ("0.5 + 0.25", Float(0.75)),
+            ("1.5 * 4", Float(6.0)),
+            ("7 / 2.0", Float(3.5)),
+            ("7 / 2", Int(3)),
+            ("5.5 % 2", Float(1.5)),
+            ("-2.5 - 1", Float(-3.5)),
+            ("let x = 1; x += 0.5; x", Float(1.5)),
+            // division by zero follows IEEE 754 for floats
+            ("1 / 0.0", Float(f64::INFINITY)),
+            ("-1.0 / 0", Float(f64::NEG_INFINITY)),
+            ("let nan = 0.0 / 0; nan == nan", Bool(false)),
+            ("1 == 1.0", Bool(true)),
+            ("0.1 + 0.2 == 0.3", Bool(false)),
+            ("2 > 1.5", Bool(true)),
+            ("1.5 <= 1", Bool(false)),
+            ("1 < 1.5", Bool(true)),
+            (
+                "let nan = 0.0 / 0; nan < 1 || nan <= 1 || 1 < nan",
+                Bool(false),
+            ),
+            ("2.0 >= 2", Bool(true)),
+            ("float(3)", Float(3.0)),
+            (r#"float(" 2.5 ")"#, Float(2.5)),
+            ("int(2.9)", Int(2)),
+            ("int(-2.9)", Int(-2)),
+            (r#"int("42")"#, Int(42)),
+            ("round(2.5)", Float(3.0)),
+            ("round(-2.5)", Float(-3.0)),
+            ("round(7)", Int(7)),
+            ("floor(-2.5)", Float(-3.0)),
+            ("floor(2.5)", Float(2.0)),
+            // floats and integers are different keys, while zero and negative zero are the same
+            ("len({1: 1, 1.0: 2})", Int(2)),
+            ("{1: 1, 1.0: 2}[1.0]", Int(2)),
+            ("{0.0: 1}[-0.0]", Int(1)),
+            ("let nan = 0.0 / 0; {nan: 1}[0.0 / 0]", Int(1)),
+        ];
+
+        run_vm_tests(test_cases);
+    }
+
+    #[test]
+    fn test_float_inspect() {
+        // input, expected
+        let test_cases = [
+            ("3.0", "3.0"),
+            ("10 / 4.0", "2.5"),
+            ("0.1 + 0.2", "0.30000000000000004"),
+            ("1e21", "1e21"),
+            ("1e-7", "1e-7"),
+            ("-0.0", "-0.0"),
+            ("1 / 0.0", "inf"),
+            ("0.0 / 0", "NaN"),
+        ];
+
+        for tc in test_cases {
             let program = parse(tc.0);
             let mut comp = Compiler::new();
-            if let Err(e) = comp.compile(program.make_node()) {
-                panic!("input: {}, compiler error:  {}", tc.0, e);
-            }
+            comp.compile(program.make_node()).unwrap();
+            let mut vm = VM::new(comp.byte_code());
+            vm.run().unwrap();
+            assert_eq!(vm.result().unwrap().inspect(), tc.1, "input: {}", tc.0);
+        }
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        use Literal::{Bool, Int, Str};
+
+        let test_cases = vec![
+            (r#"len("a\nb")"#, Int(3)),
+            (r#"len("\\n")"#, Int(2)),
+            (r#""say \"hi\"""#, Str("say \"hi\"")),
+            (r#""\u{1F600}" == "😀""#, Bool(true)),
+            (r#""a\tb"[1:2]"#, Str("\t")),
+        ];
+        run_vm_tests(test_cases);
+
+        // strings are quoted and escaped inside arrays and hash maps, but not on their own
+        let test_cases = [
+            (r#""line\n\"quoted\"""#, "line\n\"quoted\""),
+            (r#"["a\nb", "\\", 1]"#, r#"["a\nb", "\\", 1]"#),
+            (r#"{"k\t": "\u{7}"}"#, r#"{ "k\t":"\u{7}" }"#),
+        ];
 
+        for tc in test_cases {
+            let program = parse(tc.0);
+            let mut comp = Compiler::new();
+            comp.compile(program.make_node()).unwrap();
             let mut vm = VM::new(comp.byte_code());
-            // for debugging
-            if i == num_test_cases - 1 {
-                if let Err(e) = vm.run() {
-                    panic!("input: {}, vm error:  {}", tc.0, e);
-                }
-            } else {
-                if let Err(e) = vm.run() {
-                    panic!("input: {}, vm error:  {}", tc.0, e);
-                }
-            }
+            vm.run().unwrap();
+            assert_eq!(vm.result().unwrap().inspect(), tc.1, "input: {}", tc.0);
+        }
+    }
 
-            let stack_elem = vm.result();
-            test_expected_object(tc.1, stack_elem.unwrap());
+    #[test]
+    fn test_unicode_strings() {
+        use Literal::{Arr, Int, Str};
+
+        // builds a string of 100 characters that is long enough to get a char index
+        let long = r#"let long = ""; for (i in range(25)) { long += "aé😀" + "z"; }"#;
+        let test_cases = vec![
+            (r#"len("héllo")"#, Int(5)),
+            (r#"len("😀")"#, Int(1)),
+            // a combining mark is a character of its own
+            (r#"len("e\u{301}")"#, Int(2)),
+            (r#""héllo"[1]"#, Str("é")),
+            (r#""a😀b"[2]"#, Str("b")),
+            (r#"let s = "日本語"; s[len(s) - 1]"#, Str("語")),
+            (
+                r#"let chars = []; for (c in "e\u{301}😀") { push(chars, c) } chars"#,
+                Arr(vec![Str("e"), Str("\u{301}"), Str("😀")]),
+            ),
+            (
+                r#"let ascii = ""; for (i in range(20)) { ascii += "abcd" } ascii[79] + ascii[1:3]"#,
+                Str("dbc"),
+            ),
+        ];
+        run_vm_tests(test_cases);
+
+        let test_cases = vec![
+            ("len(long)", Int(100)),
+            ("long[0] + long[1] + long[2]", Str("aé😀")),
+            ("long[66] + long[99]", Str("😀z")),
+            ("long[62:67]", Str("😀zaé😀")),
+            ("long[97:]", Str("é😀z")),
+            ("long[100:]", Str("")),
+        ];
+        for (input, expected) in test_cases {
+            run_vm_tests(vec![(&format!("{long} {input}"), expected)]);
+        }
+    }
+
+    #[test]
+    fn test_big_integers() {
+        // input, expected
+        let test_cases = [
+            ("9223372036854775807 + 1", "9223372036854775808"),
+            ("-9223372036854775807 - 2", "-9223372036854775809"),
+            ("4611686018427387904 * 2", "9223372036854775808"),
+            ("-(-9223372036854775807 - 1)", "9223372036854775808"),
+            ("(-9223372036854775807 - 1) / -1", "9223372036854775808"),
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(30)",
+                "265252859812191058636308480000000",
+            ),
+            (
+                "let x = 9223372036854775807; x *= x; x",
+                "85070591730234615847396907784232501249",
+            ),
+            ("int(1e20)", "100000000000000000000"),
+            (
+                r#"int("-123456789012345678901234567890")"#,
+                "-123456789012345678901234567890",
+            ),
+            ("float(9223372036854775807 * 4)", "3.6893488147419103e19"),
+            // literals that don't fit in 64 bits are big integers
+            ("99999999999999999999", "99999999999999999999"),
+            ("-99999999999999999999 * 10", "-999999999999999999990"),
+            ("-9223372036854775808", "-9223372036854775808"),
+        ];
+
+        for tc in test_cases {
+            let program = parse(tc.0);
+            let mut comp = Compiler::new();
+            comp.compile(program.make_node()).unwrap();
+            let mut vm = VM::new(comp.byte_code());
+            vm.run().unwrap();
+            assert_eq!(vm.result().unwrap().inspect(), tc.1, "input: {}", tc.0);
         }
     }
 
+    #[test]
+    fn test_big_integers_mix_with_small_ones() {
+        use Literal::{Bool, Float, Int};
+
+        let big = "let big = 9223372036854775807 * 10;";
+        let test_cases = vec![
+            // results that fit in 64 bits are small integers again
+            (format!("{big} big - big"), Int(0)),
+            (format!("{big} big / 10"), Int(i64::MAX)),
+            (format!("{big} big % 3"), Int(1)),
+            (format!("{big} -big % 3"), Int(-1)),
+            (format!("{big} big > 9223372036854775807"), Bool(true)),
+            (format!("{big} -big < -9223372036854775807"), Bool(true)),
+            (format!("{big} big == 9223372036854775807 * 10"), Bool(true)),
+            (format!("{big} big != big + 1"), Bool(true)),
+            (format!("{big} big >= big"), Bool(true)),
+            (format!("{big} big < big"), Bool(false)),
+            (format!("{big} 9223372036854775807 <= big"), Bool(true)),
+            (format!("{big} big > 1e18"), Bool(true)),
+            (format!("{big} big * 0.5"), Float(46116860184273879040.0)),
+            // equal values are the same key, however they were computed
+            (
+                format!("{big} {{big: 1}}[9223372036854775807 * 10]"),
+                Int(1),
+            ),
+            (
+                "{9223372036854775807 + 1 - 1: 1}[9223372036854775807]".to_string(),
+                Int(1),
+            ),
+            (format!("{big} round(big) == big"), Bool(true)),
+            ("-9223372036854775808".to_string(), Int(i64::MIN)),
+            (
+                "99999999999999999999 - 99999999999999999998".to_string(),
+                Int(1),
+            ),
+            (
+                "9223372036854775808 == 9223372036854775807 + 1".to_string(),
+                Bool(true),
+            ),
+        ];
+
+        for (input, expected) in test_cases {
+            run_vm_tests(vec![(input.as_str(), expected)]);
+        }
+    }
+
+    #[test]
+    fn test_while_loops_leave_stack_empty() {
+        // more iterations than the stack can hold, so any leaked value would overflow it
+        let input = "let i = 0;
+            while (i < 70000) {
+                i;
+                if (i > 0) { i } else { 0 };
+                i = i + 1;
+            }";
+
+        let program = parse(input);
+        let mut comp = Compiler::new();
+        comp.compile(program.make_node()).unwrap();
+
+        let mut vm = VM::new(comp.byte_code());
+        if let Err(e) = vm.run() {
+            panic!("input: {}, vm error:  {}", input, e);
+        }
+        assert!(vm.stack.is_empty());
+        assert_eq!(vm.globals[0].inspect(), "70000");
+    }
+
+    #[test]
+    fn test_locals_live_on_the_stack() {
+        use Literal::{Arr, Int};
+
+        run_vm_tests(vec![
+            // only one of the locals is set, but both have a reserved slot
+            (
+                "let f = fn(c) { if (c) { let a = 1; a } else { let b = 2; b } };
+                [f(true), f(false)]",
+                Arr(vec![Int(1), Int(2)]),
+            ),
+            (
+                "let f = fn() { let i = 0; while (i < 3) { let last = i; i = i + 1; } last };
+                f()",
+                Int(2),
+            ),
+            // a closure keeps the local after the frame of its function is gone
+            (
+                "let make = fn(x) { let y = x * 2; fn() { x + y } };
+                let a = make(1);
+                let b = make(10);
+                a() + b()",
+                Int(33),
+            ),
+        ]);
+
+        // returns remove the locals, the operands and the called function from the stack
+        let input = "let f = fn(a, b) { let c = a + b; [c, c + 1][0] };
+            f(1, 2) + f(3, 4)";
+        let program = parse(input);
+        let mut comp = Compiler::new();
+        comp.compile(program.make_node()).unwrap();
+
+        let mut vm = VM::new(comp.byte_code());
+        vm.run().unwrap();
+        assert!(vm.stack.is_empty());
+        test_expected_object(Literal::Int(10), vm.result().unwrap());
+    }
+
     #[test]
     fn test_vm_fails() {
         let test_cases = vec![
@@ -493,6 +1296,61 @@ mod tests {
                 "len(\"one\", \"two\")",
                 "wrong number of arguments: want=1, got=2",
             ),
+            (
+                "[1, 2, 3][1:4]",
+                "slice end index 4 out of range for length 3",
+            ),
+            (
+                "[1, 2, 3][5:]",
+                "slice start index 5 out of range for length 3",
+            ),
+            (
+                "[1, 2, 3][5:9]",
+                "slice start index 5 out of range for length 3",
+            ),
+            (
+                "[1, 2, 3][2:1]",
+                "slice start index 2 is greater than end index 1",
+            ),
+            (
+                "\"abc\"[0:10]",
+                "slice end index 10 out of range for length 3",
+            ),
+            (
+                "[1, 2, 3][-1:]",
+                "slice index should be a non-negative integer",
+            ),
+            (
+                "[1, 2, 3][\"a\":]",
+                "slice index should be an integer, got STRING",
+            ),
+            (
+                "{1: 2}[0:1]",
+                "range indexing is only supported for arrays and strings, got HASH_MAP",
+            ),
+            (
+                "for (x in 10) { x }",
+                "iteration is only supported for arrays, strings, hash-maps and ranges, got INTEGER",
+            ),
+            ("range(1, 2, 3)", "wrong number of arguments: want=2, got=3"),
+            ("range(\"a\")", "argument to `range` not supported, got STRING"),
+            ("1 / 0", "division by zero"),
+            ("1 % 0", "division by zero"),
+            ("let x = 5; x /= 0;", "division by zero"),
+            ("(9223372036854775807 + 1) / 0", "division by zero"),
+            (r#""héllo"[5]"#, "index 5 out of bounds for length 5"),
+            (r#""abc"[-1]"#, "index should be a non-negative integer"),
+            (r#""abc"["a"]"#, "index should be an integer, got STRING"),
+            (
+                "1.5 + \"a\"",
+                "arithmetic operations are only supported between strings or numbers, got FLOAT and STRING",
+            ),
+            ("int(1.0 / 0)", "cannot convert inf to INTEGER"),
+            (r#"float("abc")"#, r#"cannot convert "abc" to FLOAT"#),
+            (r#"int("1.5")"#, r#"cannot convert "1.5" to INTEGER"#),
+            ("round(\"a\")", "argument to `round` not supported, got STRING"),
+            ("-true", "expected a number, found true"),
+            (r#"-"a""#, "expected a number, found a"),
         ];
 
         for tc in test_cases {
@@ -509,4 +1367,228 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_runtime_error_locations() {
+        // input, expected location of the error
+        let test_cases = [
+            ("let x = 1;\nlet y = x - \"a\";", Span::new(2, 11, 21, 1)),
+            (
+                "let f = fn(a) {\n  len(a)\n};\nf(1);",
+                Span::new(2, 6, 21, 1),
+            ),
+            ("[1, 2][\n  5:]", Span::new(1, 7, 6, 1)),
+        ];
+
+        for tc in test_cases {
+            let program = parse(tc.0);
+            let mut comp = Compiler::new();
+            comp.compile(program.make_node()).unwrap();
+
+            let mut vm = VM::new(comp.byte_code());
+            let diagnostic = vm.run().unwrap_err();
+            assert_eq!(diagnostic.span, tc.1, "input: {}", tc.0);
+        }
+    }
+
+    #[test]
+    fn test_runtime_tracebacks() {
+        let input = "let apply = fn(f, x) { f(x) };
+let inc = fn(x) { x + \"a\" };
+let run = fn() { apply(inc, 1) };
+run();";
+        let program = parse(input);
+        let mut comp = Compiler::new();
+        comp.compile(program.make_node()).unwrap();
+
+        let mut vm = VM::new(comp.byte_code());
+        assert!(vm.run().is_err());
+
+        // function, line of the call or the failing instruction
+        let expected = [("<main>", 4), ("run", 3), ("apply", 1), ("inc", 2)];
+        let frames = vm.traceback().frames;
+        assert_eq!(frames.len(), expected.len());
+        for (frame, want) in frames.iter().zip(expected) {
+            assert_eq!(frame.function, want.0);
+            assert_eq!(frame.span.unwrap().line, want.1);
+        }
+
+        // the offset points to the start of the instruction, even when the failing instruction
+        // was partially executed
+        let main_instructions = &vm.frames[0].closure.func.instructions;
+        assert_eq!(main_instructions[frames[0].offset], code::OP_CALL);
+        let apply_instructions = &vm.frames[2].closure.func.instructions;
+        assert_eq!(apply_instructions[frames[2].offset], code::OP_CALL);
+    }
+
+    #[test]
+    fn test_shared_function_tracebacks() {
+        let input = "let first = fn(x) { x + true };
+let second = fn(x) { x + true };
+second(1);";
+
+        for level in [0, 2] {
+            let mut comp = Compiler::new();
+            comp.optimization_level = level;
+            comp.compile(parse(input).make_node()).unwrap();
+            let bytecode = comp.byte_code();
+            assert_eq!(bytecode.constants.len(), 2); // the shared function and `1`
+            let bytecode = crate::compiler::ByteCode::deserialize(&bytecode.serialize()).unwrap();
+
+            let mut vm = VM::new(bytecode);
+            let err = vm.run().unwrap_err();
+            assert_eq!(err.span.line, 2);
+
+            // function, line of the call or the failing instruction
+            let expected = [("<main>", 3), ("second", 2)];
+            let frames = vm.traceback().frames;
+            assert_eq!(frames.len(), expected.len());
+            for (frame, want) in frames.iter().zip(expected) {
+                assert_eq!(frame.function, want.0);
+                assert_eq!(frame.span.unwrap().line, want.1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        let input = "let countdown = fn(n) { if (n == 0) { 0 } else { countdown(n - 1) } };";
+        run_vm_tests(vec![
+            (&format!("{input} countdown(1000)"), Literal::Int(0)),
+            (
+                "let f = fn() { return 1; 2 }; return f() + 1; 5",
+                Literal::Int(2),
+            ),
+        ]);
+
+        let program = parse(&format!("{input} countdown(100000)"));
+        let mut comp = Compiler::new();
+        comp.compile(program.make_node()).unwrap();
+
+        let mut vm = VM::new(comp.byte_code());
+        let err = vm.run().unwrap_err();
+        assert_eq!(
+            err.error,
+            RuntimeError::CallStackOverflow {
+                max_frames: MAX_FRAMES
+            }
+        );
+        assert_eq!(vm.traceback().frames.len(), MAX_FRAMES);
+    }
+
+    #[test]
+    fn test_optimized_programs() {
+        let test_cases = [
+            "1 + 2 * 3 - 4 / 2 % 3",
+            r#""a" + "b" == "ab""#,
+            "-(2.5 * 2) < -4 == !false",
+            "9223372036854775807 + 1",
+            "-9223372036854775807 - 1",
+            "-9223372036854775808 + 99999999999999999999 * 2",
+            "let x = 2; x * (3 + 4) - -x",
+            "if (1 > 2) { 10 } else if (2 > 1) { 20 } else { 30 }",
+            "if (false) { 10 }",
+            "if (true) { let y = 5; }",
+            // the variables of a removed branch are still defined, but never set
+            "if (false) { let x = 1; } let y = 2; y",
+            "while (false) { let x = 1; } let y = 2; y",
+            "let f = fn() { 1; while (false) { 2; } }; f()",
+            // a program that ends with a loop has no result
+            "5; while (false) {}",
+            "5; while (false) { 6; } let x = 1; while (1 > 2) { x }",
+            "5; if (false) { 6 }",
+            "5; if (false) { 6; while (false) {} }",
+            "5; for (x in []) { x }",
+            "let i = 0; while (true) { i += 1; if (i == 3) { break; } } i",
+            "let n = 0; while (n < 5 && true) { n += 1; if (false) { break; } } n",
+            "null || 0 && 5",
+            "false && 1 / 0",
+            "let f = fn(x) { x; if (x) { x + 1 } }; [f(1), f(false)]",
+            "let f = fn(a, b) { a && b && !a || b }; [f(1, 2), f(false, 2), f(0, null)]",
+            // errors stay runtime errors
+            "1 / 0",
+            "1 + true",
+            r#""a" - "b""#,
+            "-true",
+        ];
+
+        for input in test_cases {
+            let results: Vec<String> = [0, 1, 2]
+                .into_iter()
+                .map(|level| {
+                    let mut comp = Compiler::new();
+                    comp.optimization_level = level;
+                    comp.compile(parse(input).make_node()).unwrap();
+                    let mut vm = VM::new(comp.byte_code());
+                    match vm.run() {
+                        // no result and a null result are both printed as nothing
+                        Ok(_) => vm.result().map_or(NULL.inspect(), |obj| obj.inspect()),
+                        Err(e) => format!("{}: {}", e.span, e),
+                    }
+                })
+                .collect();
+            assert_eq!(results[0], results[1], "input: {}", input);
+            assert_eq!(results[0], results[2], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_wide_operands() {
+        let params: Vec<String> = (0..300).map(|i| format!("a{}", i)).collect();
+        let args: Vec<String> = (0..300).map(|i| i.to_string()).collect();
+        let (params, args) = (params.join(", "), args.join(", "));
+
+        // the closure captures 300 free variables and every local after the parameters is wide
+        let input = format!(
+            "let f = fn({params}) {{
+                let total = fn() {{ let s = 0; for (x in [{params}]) {{ s += x; }} s }};
+                let b = 1;
+                b += total();
+                for (v in [1, 2]) {{ b += v; }}
+                b
+            }};
+            f({args})"
+        );
+
+        for level in [0, 2] {
+            let mut comp = Compiler::new();
+            comp.optimization_level = level;
+            comp.compile(parse(&input).make_node()).unwrap();
+
+            let mut vm = VM::new(comp.byte_code());
+            vm.run().unwrap();
+            test_expected_object(Literal::Int(44854), vm.result().unwrap());
+        }
+
+        // the traceback points to the prefix of a wide call
+        let input = format!("let f = fn({params}) {{ a299 + true }}; f({args})");
+        let mut comp = Compiler::new();
+        comp.compile(parse(&input).make_node()).unwrap();
+
+        let mut vm = VM::new(comp.byte_code());
+        assert!(vm.run().is_err());
+        let frames = vm.traceback().frames;
+        assert_eq!(frames.len(), 2);
+        let main_instructions = &vm.frames[0].closure.func.instructions;
+        assert_eq!(main_instructions[frames[0].offset], code::OP_WIDE);
+        assert_eq!(main_instructions[frames[0].offset + 1], code::OP_CALL);
+    }
+
+    fn run_vm_tests(test_cases: Vec<(&str, Literal)>) {
+        for tc in test_cases {
+            let program = parse(tc.0);
+            let mut comp = Compiler::new();
+            if let Err(e) = comp.compile(program.make_node()) {
+                panic!("input: {}, compiler error:  {}", tc.0, e);
+            }
+
+            let mut vm = VM::new(comp.byte_code());
+            if let Err(e) = vm.run() {
+                panic!("input: {}, vm error:  {}", tc.0, e);
+            }
+
+            let stack_elem = vm.result();
+            test_expected_object(tc.1, stack_elem.unwrap());
+        }
+    }
 }
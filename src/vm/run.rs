@@ -1,72 +1,209 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use super::{frame::Frame, FALSE, NULL, TRUE, VM};
+use super::{frame::Frame, Result, FALSE, MAX_FRAMES, NULL, TRUE, VM};
 use crate::{
     code::{self, *},
+    diagnostics::{Diagnostic, TraceFrame, Traceback},
+    errors::RuntimeError,
     object::{
+        bigint::BigInt,
         builtins::get_builtin_function,
-        objects::{ArrayObj, Closure, HashMapObj, Integer, StringObj},
+        objects::{
+            ArrayObj, Closure, Float, FreeVariable, HashMapObj, Integer, IteratorObj, StringObj,
+            Upvalue, UpvalueRef,
+        },
         AllObjects, Object, ObjectType,
     },
 };
-use anyhow::{anyhow, Result};
 
 impl VM {
     /// Runs the bytecode instructions from start to finish.
-    pub fn run(&mut self) -> Result<()> {
+    ///
+    /// A runtime error points to the source code of the instruction that raised it, while the
+    /// calls that led to it can be obtained with [`VM::traceback`].
+    pub fn run(&mut self) -> std::result::Result<(), Diagnostic<RuntimeError>> {
+        self.execute().map_err(|error| {
+            let innermost = self.traceback().frames.pop();
+            let span = innermost.and_then(|frame| frame.span).unwrap_or_default();
+            Diagnostic::new(error, span)
+        })
+    }
+
+    /// Returns the function calls that are in progress, which are the calls that led to the error
+    /// after a failed run.
+    pub fn traceback(&self) -> Traceback {
+        let innermost = self.frames_index - 1;
+        let frames = self.frames[..self.frames_index]
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                let func = &frame.closure.func;
+                let function = match func.name.as_str() {
+                    _ if i == 0 => "<main>".to_string(),
+                    "" => "<anonymous>".to_string(),
+                    name => name.to_string(),
+                };
+                // the outer frames are past their call instruction already
+                let ip = if i == innermost {
+                    frame.ip
+                } else {
+                    frame.ip - 1
+                };
+                let offset = code::instruction_start(&func.instructions, ip);
+                TraceFrame {
+                    function,
+                    offset,
+                    span: func.source_map.lookup(offset),
+                }
+            })
+            .collect();
+
+        Traceback { frames }
+    }
+
+    /// Executes instructions until the main frame runs out of them.
+    ///
+    /// Calls and returns switch the current frame instead of recursing, so the depth of the
+    /// Monkey call stack is only bound by `MAX_FRAMES`.
+    fn execute(&mut self) -> Result<()> {
         while self.current_frame().ip < self.current_frame().instructions().len() {
             let ip = self.current_frame().ip;
             let op = self.current_frame().instructions()[ip];
 
-            match op {
-                OP_CONSTANT => self.run_constant_instruction()?,
-                OP_ADD | OP_SUB | OP_MUL | OP_DIV => self.run_arithmetic_operations(op)?,
-                OP_EQUAL | OP_NOT_EQUAL | OP_GREATER_THAN => self.run_boolean_operations(op)?,
-                OP_TRUE => self.push(TRUE)?,
-                OP_FALSE => self.push(FALSE)?,
-                OP_MINUS => self.run_prefix_minus()?,
-                OP_BANG => self.run_prefix_bang()?,
-                OP_SET_GLOBAL => self.run_set_global_instruction()?,
-                OP_GET_GLOBAL => self.run_get_global_instruction()?,
-                OP_SET_LOCAL => self.run_set_local_instruction()?,
-                OP_GET_LOCAL => self.run_get_local_instruction()?,
-                OP_ARRAY => self.run_array_literal_instruction()?,
-                OP_HASH => self.run_hash_literal_instruction()?,
-                OP_INDEX => self.run_index_expression()?,
-                OP_CLOSURE => self.run_closure_instruction()?,
-                OP_GET_FREE => self.run_get_free()?,
-                OP_CALL => self.run_call_expression()?,
-                OP_ASSIGN_GLOBAL => self.run_assign_global_instruction()?,
-                OP_CURRENT_CLOSURE => self.run_current_closure_instruction()?,
-                OP_GET_BUILTIN => self.run_get_builtin()?,
-                OP_RETURN_VALUE => {
-                    self.pop_frame();
-                }
-                OP_RETURN => {
-                    self.pop_frame();
-                    self.push(NULL)?;
-                }
-                OP_POP => {
-                    self.pop()?;
-                }
-                OP_JUMP_NOT_TRUTHY => self.run_jump_not_truthy_instruction()?,
-                OP_JUMP => self.run_jump_instruction()?,
-                OP_NULL => self.push(NULL)?,
-                _ => {}
-            }
-            self.current_frame().ip += 1;
+            self.run_instruction(op)?;
         }
 
         Ok(())
     }
 
+    /// Executes a single instruction and moves the instruction pointer to the next one.
+    fn run_instruction(&mut self, op: Opcode) -> Result<()> {
+        match op {
+            OP_CONSTANT => self.run_constant_instruction()?,
+            OP_ADD | OP_SUB | OP_MUL | OP_DIV | OP_MOD => self.run_arithmetic_operations(op)?,
+            OP_EQUAL
+            | OP_NOT_EQUAL
+            | OP_GREATER_THAN
+            | OP_GREATER_THAN_OR_EQUAL
+            | OP_LESS_THAN
+            | OP_LESS_THAN_OR_EQUAL => self.run_boolean_operations(op)?,
+            OP_TRUE => self.push(TRUE)?,
+            OP_FALSE => self.push(FALSE)?,
+            OP_MINUS => self.run_prefix_minus()?,
+            OP_BANG => self.run_prefix_bang()?,
+            OP_SET_GLOBAL => self.run_set_global_instruction()?,
+            OP_GET_GLOBAL => self.run_get_global_instruction()?,
+            OP_SET_LOCAL => self.run_set_local_instruction()?,
+            OP_GET_LOCAL => self.run_get_local_instruction()?,
+            OP_ARRAY => self.run_array_literal_instruction()?,
+            OP_HASH => self.run_hash_literal_instruction()?,
+            OP_INDEX => self.run_index_expression()?,
+            OP_SLICE => self.run_slice_expression()?,
+            OP_CLOSURE => self.run_closure_instruction()?,
+            OP_GET_FREE => self.run_get_free()?,
+            OP_ASSIGN_GLOBAL => self.run_assign_global_instruction()?,
+            OP_ASSIGN_LOCAL => self.run_assign_local_instruction()?,
+            OP_ASSIGN_FREE => self.run_assign_free_instruction()?,
+            OP_CURRENT_CLOSURE => self.run_current_closure_instruction()?,
+            OP_GET_BUILTIN => self.run_get_builtin()?,
+            OP_GET_ITER => self.run_get_iter_instruction()?,
+            OP_ITER_NEXT => {
+                // ip is set to the next instruction or the end of the loop
+                return self.run_iter_next_instruction();
+            }
+            OP_CALL => {
+                // ip is set to the start of the called function or the next instruction
+                return self.run_call_expression();
+            }
+            OP_RETURN_VALUE | OP_RETURN if self.frames_index == 1 => {
+                // a return outside of functions ends the program with the returned value
+                let value = if op == OP_RETURN_VALUE {
+                    self.pop()?
+                } else {
+                    NULL
+                };
+                self.result = Some(value);
+                self.current_frame().ip = self.current_frame().instructions().len();
+                return Ok(());
+            }
+            OP_RETURN_VALUE => {
+                let return_value = self.pop()?;
+                self.pop_frame();
+                // the caller's ip already points to the instruction after the call
+                return self.push(return_value);
+            }
+            OP_RETURN => {
+                self.pop_frame();
+                return self.push(NULL);
+            }
+            OP_POP => {
+                self.pop()?;
+            }
+            OP_JUMP_NOT_TRUTHY => {
+                // ip is already pointing to the next instruction
+                return self.run_jump_not_truthy_instruction();
+            }
+            OP_JUMP_NOT_TRUTHY_OR_POP => return self.run_jump_or_pop_instruction(false),
+            OP_JUMP_TRUTHY_OR_POP => return self.run_jump_or_pop_instruction(true),
+            OP_JUMP => {
+                self.run_jump_instruction();
+                return Ok(());
+            }
+            OP_NULL => self.push(NULL)?,
+            OP_WIDE => {
+                // the prefix only widens the operands of the instruction right after it
+                self.current_frame().ip += 1;
+                let ip = self.current_frame().ip;
+                let op = self.current_frame().instructions()[ip];
+                self.wide = true;
+                let result = self.run_instruction(op);
+                self.wide = false;
+                return result;
+            }
+            _ => {}
+        }
+        self.current_frame().ip += 1;
+        Ok(())
+    }
+
+    /// Reads the single byte operand at the given distance from the opcode of the current
+    /// instruction, which takes two bytes when the instruction is prefixed with `OP_WIDE`
+    fn read_narrow_operand(&mut self, distance: usize) -> usize {
+        let wide = self.wide;
+        let ip = self.current_frame().ip;
+        let operand = &self.current_frame().instructions()[(ip + distance)..];
+        if wide {
+            code::helpers::read_u16(operand)
+        } else {
+            code::helpers::read_u8(operand)
+        }
+    }
+
+    /// Returns the number of bytes taken by a single byte operand of the current instruction
+    fn narrow_width(&self) -> usize {
+        if self.wide {
+            2
+        } else {
+            1
+        }
+    }
+
     fn run_arithmetic_operations(&mut self, op: Opcode) -> Result<()> {
         let right = self.pop()?;
         let left = self.pop()?;
+        self.push(Self::arithmetic(op, left, right)?)
+    }
 
+    /// Returns the result of an arithmetic instruction on the operands, which the compiler also
+    /// uses to fold constant expressions
+    pub(crate) fn arithmetic(
+        op: Opcode,
+        left: AllObjects,
+        right: AllObjects,
+    ) -> Result<AllObjects> {
         if left.is_string() && right.is_string() {
             if op != OP_ADD {
-                return Err(anyhow!("incorrect operation on strings"));
+                return Err(RuntimeError::UnsupportedStringOperation);
             }
             let right_val = match right {
                 AllObjects::StringObj(v) => v,
@@ -77,55 +214,111 @@ impl VM {
                 _ => unreachable!(),
             };
             let concatenated = format!("{}{}", left_val.value, right_val.value);
-            return self.push(AllObjects::StringObj(StringObj::new(&concatenated)));
+            return Ok(AllObjects::StringObj(StringObj::new(&concatenated)));
         }
 
         if left.is_integer() && right.is_integer() {
-            let right_value = match right {
-                AllObjects::Integer(v) => v,
-                _ => unreachable!(),
-            };
-            let left_value = match left {
-                AllObjects::Integer(v) => v,
+            return Self::integer_arithmetic(op, left, right);
+        }
+
+        // an integer combined with a float is converted to a float
+        if let (Some(l), Some(r)) = (left.as_f64(), right.as_f64()) {
+            let value = match op {
+                OP_ADD => l + r,
+                OP_SUB => l - r,
+                OP_MUL => l * r,
+                OP_DIV => l / r,
+                OP_MOD => l % r,
                 _ => unreachable!(),
             };
+            return Ok(AllObjects::Float(Float { value }));
+        }
+
+        Err(RuntimeError::UnsupportedArithmeticOperands {
+            left: left.object_type(),
+            right: right.object_type(),
+        })
+    }
+
+    /// Runs the operation on integers, which is done on big integers if either of them is big or
+    /// the result overflows an `i64`
+    fn integer_arithmetic(op: Opcode, l: AllObjects, r: AllObjects) -> Result<AllObjects> {
+        if let (AllObjects::Integer(left), AllObjects::Integer(right)) = (&l, &r) {
+            let (l, r) = (left.value, right.value);
+            if (op == OP_DIV || op == OP_MOD) && r == 0 {
+                return Err(RuntimeError::DivisionByZero);
+            }
             let result = match op {
-                OP_ADD => left_value.value + right_value.value,
-                OP_SUB => left_value.value - right_value.value,
-                OP_MUL => left_value.value * right_value.value,
-                OP_DIV => left_value.value / right_value.value,
+                OP_ADD => l.checked_add(r),
+                OP_SUB => l.checked_sub(r),
+                OP_MUL => l.checked_mul(r),
+                OP_DIV => l.checked_div(r),
+                // `i64::MIN % -1` is zero, even though computing it overflows
+                OP_MOD => Some(l.wrapping_rem(r)),
                 _ => unreachable!(),
             };
-            return self.push(AllObjects::Integer(Integer { value: result }));
+            if let Some(value) = result {
+                return Ok(AllObjects::Integer(Integer { value }));
+            }
         }
 
-        Err(anyhow!(
-            "arithmetic operations are only supported between strings or integers"
-        ))
+        let (Some(left), Some(right)) = (l.as_big_int(), r.as_big_int()) else {
+            unreachable!()
+        };
+        let value = match op {
+            OP_ADD => &left + &right,
+            OP_SUB => &left - &right,
+            OP_MUL => &left * &right,
+            OP_DIV | OP_MOD => {
+                let Some((quotient, remainder)) = left.div_rem(&right) else {
+                    return Err(RuntimeError::DivisionByZero);
+                };
+                if op == OP_DIV {
+                    quotient
+                } else {
+                    remainder
+                }
+            }
+            _ => unreachable!(),
+        };
+        Ok(AllObjects::from_big_int(value))
     }
 
     fn run_boolean_operations(&mut self, op: Opcode) -> Result<()> {
         let right = self.pop()?;
         let left = self.pop()?;
+        self.push(Self::comparison(op, left, right)?)
+    }
 
-        if left.is_integer() && right.is_integer() {
-            return self.run_comparison_for_ints(op, left, right);
-        }
-        if left.is_boolean() && right.is_boolean() {
-            return self.run_comparison_for_bools(op, left, right);
-        }
-        Err(anyhow!(
-            "left {} and right {} operand types doesn't match",
-            left.object_type(),
-            right.object_type()
-        ))
+    /// Returns the result of a comparison instruction on the operands, which the compiler also
+    /// uses to fold constant expressions
+    pub(crate) fn comparison(
+        op: Opcode,
+        left: AllObjects,
+        right: AllObjects,
+    ) -> Result<AllObjects> {
+        let result = if left.is_integer() && right.is_integer() {
+            Self::compare_ints(op, left, right)
+        } else if let (Some(l), Some(r)) = (left.as_f64(), right.as_f64()) {
+            Self::compare_floats(op, l, r)
+        } else if left.is_boolean() && right.is_boolean() {
+            Self::compare_bools(op, left, right)
+        } else if left.is_string() && right.is_string() {
+            Self::compare_strings(op, left, right)
+        } else {
+            return Err(RuntimeError::MismatchedOperands {
+                left: left.object_type(),
+                right: right.object_type(),
+            });
+        };
+        Ok(Self::get_bool_constant(result))
     }
 
     fn run_constant_instruction(&mut self) -> Result<()> {
         let ip = self.current_frame().ip;
         let const_index = code::helpers::read_u16(&self.current_frame().instructions()[(ip + 1)..]);
         if self.constants.get(const_index).is_none() {
-            return Err(anyhow!("constant at the index {const_index} not found"));
+            return Err(RuntimeError::ConstantNotFound { index: const_index });
         }
         self.push(self.constants[const_index].clone())?;
         self.current_frame().ip += 2;
@@ -138,12 +331,12 @@ impl VM {
             code::helpers::read_u16(&self.current_frame().instructions()[(ip + 1)..]);
         self.current_frame().ip += 2;
 
+        // the globals defined before this one may never be set, e.g. in a loop that never runs
         let last_pushed = self.pop()?;
-        if self.globals.get(global_index).is_none() {
-            self.globals.push(last_pushed);
-        } else {
-            self.globals[global_index] = last_pushed;
+        if self.globals.len() <= global_index {
+            self.globals.resize(global_index + 1, NULL);
         }
+        self.globals[global_index] = last_pushed;
 
         Ok(())
     }
@@ -155,7 +348,7 @@ impl VM {
 
         let last_pushed = self.pop()?;
         if self.globals.get(var_index).is_none() {
-            return Err(anyhow!("variable at index {var_index} not found"));
+            return Err(RuntimeError::VariableNotFound { index: var_index });
         } else {
             self.globals[var_index] = last_pushed;
         }
@@ -164,29 +357,66 @@ impl VM {
         Ok(())
     }
 
-    fn run_set_local_instruction(&mut self) -> Result<()> {
-        let ip = self.current_frame().ip;
-        let local_index = code::helpers::read_u8(&self.current_frame().instructions()[(ip + 1)..]);
-        self.current_frame().ip += 1;
+    fn run_assign_local_instruction(&mut self) -> Result<()> {
+        let local_index = self.read_narrow_operand(1);
+        self.current_frame().ip += self.narrow_width();
 
         let last_pushed = self.pop()?;
-        if self.current_frame().locals.get(local_index).is_none() {
-            self.current_frame().locals.push(last_pushed);
-        } else {
-            self.current_frame().locals[local_index] = last_pushed;
+        let slot = self.current_frame().base_pointer + local_index;
+        let Some(local) = self.stack.get_mut(slot) else {
+            return Err(RuntimeError::VariableNotFound { index: local_index });
+        };
+        *local = last_pushed;
+
+        self.push(NULL)?; // assignment is an expression and will return null
+        Ok(())
+    }
+
+    fn run_assign_free_instruction(&mut self) -> Result<()> {
+        let free_index = self.read_narrow_operand(1);
+        self.current_frame().ip += self.narrow_width();
+
+        let last_pushed = self.pop()?;
+        let upvalue = self.current_upvalue(free_index)?;
+        let mut upvalue = upvalue.borrow_mut();
+        match &mut *upvalue {
+            Upvalue::Open { slot } => {
+                let Some(local) = self.stack.get_mut(*slot) else {
+                    return Err(RuntimeError::VariableNotFound { index: *slot });
+                };
+                *local = last_pushed;
+            }
+            Upvalue::Closed(v) => *v = last_pushed,
         }
+        drop(upvalue);
+
+        self.push(NULL)?;
+        Ok(())
+    }
+
+    fn run_set_local_instruction(&mut self) -> Result<()> {
+        let local_index = self.read_narrow_operand(1);
+        self.current_frame().ip += self.narrow_width();
+
+        let last_pushed = self.pop()?;
+        // the slots of all the locals are reserved when the function is called
+        let slot = self.current_frame().base_pointer + local_index;
+        let Some(local) = self.stack.get_mut(slot) else {
+            return Err(RuntimeError::VariableNotFound { index: local_index });
+        };
+        *local = last_pushed;
 
         Ok(())
     }
 
     fn run_get_builtin(&mut self) -> Result<()> {
-        let ip = self.current_frame().ip;
-        let builtin_index =
-            code::helpers::read_u8(&self.current_frame().instructions()[(ip + 1)..]);
-        self.current_frame().ip += 1;
+        let builtin_index = self.read_narrow_operand(1);
+        self.current_frame().ip += self.narrow_width();
 
         let Some(func) = get_builtin_function(builtin_index) else {
-            return Err(anyhow!("builtin function with index {builtin_index} not found"));
+            return Err(RuntimeError::BuiltinNotFound {
+                index: builtin_index,
+            });
         };
 
         self.push(func)?;
@@ -199,21 +429,22 @@ impl VM {
             code::helpers::read_u16(&self.current_frame().instructions()[(ip + 1)..]);
         self.current_frame().ip += 2;
         let Some(v) = self.globals.get(global_index) else {
-            return Err(anyhow!("variable at index {global_index} not found"));
+            return Err(RuntimeError::VariableNotFound {
+                index: global_index,
+            });
         };
         self.push(v.clone())?;
         Ok(())
     }
 
     fn run_get_local_instruction(&mut self) -> Result<()> {
-        let ip = self.current_frame().ip;
-        let local_index = code::helpers::read_u8(&self.current_frame().instructions()[(ip + 1)..]);
-        self.current_frame().ip += 1;
-        let Some(v) = self.current_frame().locals.get(local_index) else {
-            return Err(anyhow!("variable at index {local_index} not found"));
+        let local_index = self.read_narrow_operand(1);
+        self.current_frame().ip += self.narrow_width();
+        let slot = self.current_frame().base_pointer + local_index;
+        let Some(v) = self.stack.get(slot) else {
+            return Err(RuntimeError::VariableNotFound { index: local_index });
         };
-        let cloned = v.clone();
-        self.push(cloned)?;
+        self.push(v.clone())?;
         Ok(())
     }
 
@@ -233,6 +464,7 @@ impl VM {
         Ok(())
     }
 
+    #[allow(clippy::mutable_key_type)]
     fn run_hash_literal_instruction(&mut self) -> Result<()> {
         let ip = self.current_frame().ip;
         let map_len = code::helpers::read_u16(&self.current_frame().instructions()[(ip + 1)..]);
@@ -253,25 +485,42 @@ impl VM {
         let index = self.pop()?;
         let indexable = self.pop()?;
 
-        if indexable.object_type() == ObjectType::Array {
+        if matches!(
+            indexable,
+            AllObjects::ArrayObj(_) | AllObjects::StringObj(_)
+        ) {
             let index = match index {
                 AllObjects::Integer(v) => v,
-                _ => return Err(anyhow!("index should be an integer")),
+                v => {
+                    let object_type = v.object_type();
+                    return Err(RuntimeError::InvalidIndex { object_type });
+                }
             };
             let index_usize: usize = match index.value.try_into() {
                 Ok(v) => v,
-                Err(_) => return Err(anyhow!("index should be a positive integer")),
+                Err(_) => return Err(RuntimeError::NegativeIndex { index: index.value }),
             };
 
-            let arr = match indexable {
-                AllObjects::ArrayObj(v) => v,
+            // strings are indexed by their characters, which are returned as strings
+            let (value, length) = match indexable {
+                AllObjects::ArrayObj(arr) => {
+                    let borrowed = arr.elements.borrow();
+                    (borrowed.get(index_usize).cloned(), borrowed.len())
+                }
+                AllObjects::StringObj(s) => {
+                    let ch = s.char_at(index_usize);
+                    let value = ch.map(|c| AllObjects::StringObj(StringObj::new(&c.to_string())));
+                    (value, s.char_len())
+                }
                 _ => unreachable!(),
             };
-            let borrowed = arr.elements.borrow();
-            let Some(value) = borrowed.get(index_usize) else {
-                return Err(anyhow!("index out of bounds"));
+            let Some(value) = value else {
+                return Err(RuntimeError::IndexOutOfBounds {
+                    index: index_usize,
+                    length,
+                });
             };
-            self.push(value.clone())?;
+            self.push(value)?;
             return Ok(());
         }
 
@@ -289,9 +538,65 @@ impl VM {
             return Ok(());
         }
 
-        Err(anyhow!(
-            "indexing is only supported for arrays and hash-maps"
-        ))
+        Err(RuntimeError::NotIndexable {
+            object_type: indexable.object_type(),
+        })
+    }
+
+    fn run_slice_expression(&mut self) -> Result<()> {
+        let end = self.pop()?;
+        let start = self.pop()?;
+        let sliceable = self.pop()?;
+
+        match sliceable {
+            AllObjects::ArrayObj(arr) => {
+                let borrowed = arr.elements.borrow();
+                let (start, end) = Self::slice_bounds(start, end, borrowed.len())?;
+                let sliced = borrowed[start..end].to_vec();
+                drop(borrowed);
+                self.push(AllObjects::ArrayObj(ArrayObj::new(sliced)))
+            }
+            AllObjects::StringObj(s) => {
+                let (start, end) = Self::slice_bounds(start, end, s.char_len())?;
+                let sliced = StringObj::new(s.char_slice(start, end));
+                self.push(AllObjects::StringObj(sliced))
+            }
+            v => Err(RuntimeError::NotSliceable {
+                object_type: v.object_type(),
+            }),
+        }
+    }
+
+    /// Validates the bounds of a range expression against the length of the sliceable and returns them
+    /// as usize values. Null bounds default to the start and the end of the sliceable.
+    fn slice_bounds(start: AllObjects, end: AllObjects, length: usize) -> Result<(usize, usize)> {
+        let to_bound = |obj: AllObjects, default: usize| -> Result<usize> {
+            match obj {
+                AllObjects::Null(_) => Ok(default),
+                AllObjects::Integer(v) => match v.value.try_into() {
+                    Ok(v) => Ok(v),
+                    Err(_) => Err(RuntimeError::NegativeSliceIndex { index: v.value }),
+                },
+                v => Err(RuntimeError::InvalidSliceIndex {
+                    object_type: v.object_type(),
+                }),
+            }
+        };
+
+        let start = to_bound(start, 0)?;
+        let end = to_bound(end, length)?;
+
+        if start > length {
+            return Err(RuntimeError::SliceStartOutOfRange { start, length });
+        }
+        if end > length {
+            return Err(RuntimeError::SliceEndOutOfRange { end, length });
+        }
+        if start > end {
+            return Err(RuntimeError::SliceStartAfterEnd { start, end });
+        }
+
+        Ok((start, end))
     }
 
     fn run_jump_not_truthy_instruction(&mut self) -> Result<()> {
@@ -301,45 +606,139 @@ impl VM {
         };
 
         if !condition.value {
-            let ip = self.current_frame().ip;
-            let jump_position =
-                code::helpers::read_u16(&self.current_frame().instructions()[(ip + 1)..]);
-            self.current_frame().ip = jump_position - 1; // since ip gets incremented at the end of the loop
+            self.run_jump_instruction();
             return Ok(());
         }
 
-        // consume the jump instruction
-        self.current_frame().ip += 2;
+        // skip over the opcode and the jump operand
+        self.current_frame().ip += 3;
+
+        Ok(())
+    }
+
+    /// Jumps if the truthiness of the object on top of the stack is the given one, keeping it as
+    /// the result of `&&` or `||`. Otherwise the object is popped and the right side is evaluated.
+    fn run_jump_or_pop_instruction(&mut self, jump_if_truthy: bool) -> Result<()> {
+        let Some(top) = self.stack.last() else {
+            return Err(RuntimeError::StackUnderflow);
+        };
+        let truthy = match Self::cast_obj_to_bool(top.clone()) {
+            AllObjects::Boolean(v) => v.value,
+            _ => unreachable!(),
+        };
+
+        if truthy == jump_if_truthy {
+            self.run_jump_instruction();
+            return Ok(());
+        }
+
+        self.pop()?;
+        // skip over the opcode and the jump operand
+        self.current_frame().ip += 3;
 
         Ok(())
     }
 
-    fn run_jump_instruction(&mut self) -> Result<()> {
+    /// Moves the instruction pointer to the jump target.
+    ///
+    /// Jump targets can be zero (a loop at the very start of a function), so the target is set
+    /// directly and the main loop must not increment the instruction pointer afterwards.
+    fn run_jump_instruction(&mut self) {
         let ip = self.current_frame().ip;
         let jump_position =
             code::helpers::read_u16(&self.current_frame().instructions()[(ip + 1)..]);
-        self.current_frame().ip = jump_position - 1; // since ip gets incremented at in the loop
+        self.current_frame().ip = jump_position;
+    }
+
+    fn run_get_iter_instruction(&mut self) -> Result<()> {
+        let iterable = self.pop()?;
+        let object_type = iterable.object_type();
+        let Some(iterator) = IteratorObj::new(iterable) else {
+            return Err(RuntimeError::NotIterable { object_type });
+        };
+        self.push(AllObjects::Iterator(iterator))
+    }
+
+    /// Pushes the next item of the iterator on top of the stack, or removes the exhausted iterator
+    /// and jumps to the end of the loop.
+    fn run_iter_next_instruction(&mut self) -> Result<()> {
+        let ip = self.current_frame().ip;
+        let exit_position =
+            code::helpers::read_u16(&self.current_frame().instructions()[(ip + 1)..]);
+        let num_vars = self.read_narrow_operand(3);
+
+        let iterator = match self.stack.last_mut() {
+            Some(AllObjects::Iterator(v)) => v,
+            Some(v) => {
+                let object_type = v.object_type();
+                return Err(RuntimeError::NotIterable { object_type });
+            }
+            None => return Err(RuntimeError::StackUnderflow),
+        };
+        let yields_keys = iterator.yields_keys();
+
+        let Some((key, value)) = iterator.next() else {
+            // the iterator isn't a result, so it's not removed with `pop`
+            self.stack.pop();
+            self.current_frame().ip = exit_position;
+            return Ok(());
+        };
+
+        match num_vars {
+            1 if yields_keys => self.push(key)?,
+            1 => self.push(value)?,
+            _ => {
+                self.push(key)?;
+                self.push(value)?;
+            }
+        }
+
+        // skip over the opcode and the operands
+        self.current_frame().ip += 3 + self.narrow_width();
         Ok(())
     }
 
     fn run_closure_instruction(&mut self) -> Result<()> {
         let ip = self.current_frame().ip;
         let const_index = code::helpers::read_u16(&self.current_frame().instructions()[(ip + 1)..]);
-        let num_free = code::helpers::read_u8(&self.current_frame().instructions()[(ip + 3)..]);
-        self.current_frame().ip += 3;
+        let num_free = self.read_narrow_operand(3);
+        self.current_frame().ip += 2 + self.narrow_width();
 
-        let func = match self.constants.get(const_index) {
+        let mut func = match self.constants.get(const_index) {
             Some(obj) => match obj {
                 AllObjects::CompiledFunction(v) => v,
-                v => return Err(anyhow!("not a function: {}", v.inspect())),
+                v => return Err(RuntimeError::NotAFunction { found: v.inspect() }),
             },
-            None => return Err(anyhow!("constant at index {const_index} not found")),
+            None => return Err(RuntimeError::ConstantNotFound { index: const_index }),
         }
         .to_owned();
 
+        // the constant may be shared with identical functions that have other names and locations
+        let start = ip - usize::from(self.wide);
+        let source_map = &self.current_frame().closure.func.source_map;
+        if let Some(site) = source_map.function_at(start).cloned() {
+            func.name = site.name;
+            func.source_map = site.source_map;
+        }
+
+        if func.free_variables.len() != num_free {
+            return Err(RuntimeError::WrongNumberOfFreeVariables {
+                want: num_free,
+                got: func.free_variables.len(),
+            });
+        }
+
         let mut free_vars = Vec::with_capacity(num_free);
-        for _ in 0..num_free {
-            free_vars.push(self.pop()?);
+        for free_variable in &func.free_variables {
+            let upvalue = match *free_variable {
+                FreeVariable::Local(index) => self.capture_local(index),
+                FreeVariable::Free(index) => self.current_upvalue(index)?,
+                FreeVariable::CurrentClosure => {
+                    let current = AllObjects::Closure(self.current_frame().closure.clone());
+                    Rc::new(RefCell::new(Upvalue::Closed(current)))
+                }
+            };
+            free_vars.push(upvalue);
         }
 
         let closure = Closure::new(func, free_vars);
@@ -348,54 +747,93 @@ impl VM {
     }
 
     fn run_get_free(&mut self) -> Result<()> {
-        let ip = self.current_frame().ip;
-        let free_index = code::helpers::read_u8(&self.current_frame().instructions()[(ip + 1)..]);
-        self.current_frame().ip += 1;
+        let free_index = self.read_narrow_operand(1);
+        self.current_frame().ip += self.narrow_width();
 
-        let current_closure = &self.current_frame().closure;
-        let Some(free_var) = current_closure.free.get(free_index) else {
-            return Err(anyhow!("free variable at index {free_index} not found"));
+        let upvalue = self.current_upvalue(free_index)?;
+        let free_var = match &*upvalue.borrow() {
+            Upvalue::Open { slot } => match self.stack.get(*slot) {
+                Some(v) => v.clone(),
+                None => return Err(RuntimeError::VariableNotFound { index: *slot }),
+            },
+            Upvalue::Closed(v) => v.clone(),
         };
-        let free_var = free_var.to_owned();
         self.push(free_var)?;
 
         Ok(())
     }
 
+    /// Returns the upvalue of the current closure at the given free variable index
+    fn current_upvalue(&mut self, free_index: usize) -> Result<UpvalueRef> {
+        let current_closure = &self.current_frame().closure;
+        let Some(upvalue) = current_closure.free.get(free_index) else {
+            return Err(RuntimeError::FreeVariableNotFound { index: free_index });
+        };
+        Ok(upvalue.clone())
+    }
+
+    /// Returns an open upvalue for the local of the current frame at the given index. An existing
+    /// upvalue is reused, so that all closures capturing the same variable share it.
+    fn capture_local(&mut self, index: usize) -> UpvalueRef {
+        let slot = self.current_frame().base_pointer + index;
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| *upvalue.borrow() == Upvalue::Open { slot });
+        if let Some(upvalue) = existing {
+            return upvalue.clone();
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open { slot }));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
+
     fn run_call_expression(&mut self) -> Result<()> {
-        let ip = self.current_frame().ip;
-        let num_args = code::helpers::read_u8(&self.current_frame().instructions()[(ip + 1)..]);
-        self.current_frame().ip += 1;
+        let num_args = self.read_narrow_operand(1);
 
-        let mut local_args = (0..num_args)
-            .filter_map(|_| self.pop().ok())
-            .collect::<Vec<AllObjects>>();
-        local_args.reverse();
+        // the called function is below its arguments, which become the first locals of the frame
+        let Some(callee_slot) = self.stack.len().checked_sub(num_args + 1) else {
+            return Err(RuntimeError::StackUnderflow);
+        };
+        // the slot is dropped along with the frame, so the callee doesn't need to be cloned
+        let callee = std::mem::replace(&mut self.stack[callee_slot], NULL);
 
-        match self.pop()? {
+        match callee {
             AllObjects::Closure(c) => {
-                if local_args.len() != c.func.num_args {
-                    return Err(anyhow!(
-                        "wrong number of arguments: want={}, got={}",
-                        c.func.num_args,
-                        local_args.len()
-                    ));
+                if num_args != c.func.num_args {
+                    return Err(RuntimeError::WrongNumberOfArguments {
+                        want: c.func.num_args,
+                        got: num_args,
+                    });
+                }
+                if self.frames_index >= MAX_FRAMES {
+                    return Err(RuntimeError::CallStackOverflow {
+                        max_frames: MAX_FRAMES,
+                    });
+                }
+                for _ in num_args..c.func.num_locals {
+                    self.push(NULL)?;
                 }
-                self.push_frame(Frame::new(c, local_args));
-                self.run()?;
+
+                // the caller continues after the call once the function returns
+                self.current_frame().ip += 1 + self.narrow_width();
+                self.push_frame(Frame::new(c, callee_slot + 1));
             }
             AllObjects::BuiltinFunction(builtin) => {
-                if local_args.len() != builtin.num_params && builtin.num_params != usize::MAX {
-                    return Err(anyhow!(
-                        "wrong number of arguments: want={}, got={}",
-                        builtin.num_params,
-                        local_args.len()
-                    ));
+                if num_args != builtin.num_params && builtin.num_params != usize::MAX {
+                    return Err(RuntimeError::WrongNumberOfArguments {
+                        want: builtin.num_params,
+                        got: num_args,
+                    });
                 }
-                let result = (builtin.func)(local_args)?;
+                let args = self.stack.split_off(callee_slot + 1);
+                self.stack.pop();
+                let result = (builtin.func)(args)?;
                 self.push(result)?;
+                self.current_frame().ip += 1 + self.narrow_width();
             }
-            v => return Err(anyhow!("expected a function, found {}", v.inspect())),
+            v => return Err(RuntimeError::NotCallable { found: v.inspect() }),
         };
 
         Ok(())
@@ -407,60 +845,112 @@ impl VM {
         Ok(())
     }
 
-    fn run_comparison_for_ints(&mut self, op: Opcode, l: AllObjects, r: AllObjects) -> Result<()> {
+    fn compare_ints(op: Opcode, l: AllObjects, r: AllObjects) -> bool {
+        let ordering = match (&l, &r) {
+            (AllObjects::Integer(left), AllObjects::Integer(right)) => left.value.cmp(&right.value),
+            _ => l.as_big_int().cmp(&r.as_big_int()),
+        };
+        match op {
+            OP_EQUAL => ordering.is_eq(),
+            OP_NOT_EQUAL => ordering.is_ne(),
+            OP_GREATER_THAN => ordering.is_gt(),
+            OP_GREATER_THAN_OR_EQUAL => ordering.is_ge(),
+            OP_LESS_THAN => ordering.is_lt(),
+            OP_LESS_THAN_OR_EQUAL => ordering.is_le(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Compares floats, or an integer with a float, where NaN is neither equal to nor ordered
+    /// with any value
+    fn compare_floats(op: Opcode, left: f64, right: f64) -> bool {
+        match op {
+            OP_EQUAL => left == right,
+            OP_NOT_EQUAL => left != right,
+            OP_GREATER_THAN => left > right,
+            OP_GREATER_THAN_OR_EQUAL => left >= right,
+            OP_LESS_THAN => left < right,
+            OP_LESS_THAN_OR_EQUAL => left <= right,
+            _ => unreachable!(),
+        }
+    }
+
+    fn compare_bools(op: Opcode, l: AllObjects, r: AllObjects) -> bool {
         let left = match l {
-            AllObjects::Integer(v) => v,
+            AllObjects::Boolean(v) => v,
             _ => unreachable!(),
         };
         let right = match r {
-            AllObjects::Integer(v) => v,
+            AllObjects::Boolean(v) => v,
             _ => unreachable!(),
         };
-        let result = match op {
+        match op {
             OP_EQUAL => left.value == right.value,
             OP_NOT_EQUAL => left.value != right.value,
-            OP_GREATER_THAN => left.value > right.value,
+            OP_GREATER_THAN => left.value & !right.value,
+            OP_GREATER_THAN_OR_EQUAL => left.value | !right.value,
+            OP_LESS_THAN => !left.value & right.value,
+            OP_LESS_THAN_OR_EQUAL => !left.value | right.value,
             _ => unreachable!(),
-        };
-        self.push(Self::get_bool_constant(result))
+        }
     }
 
-    fn run_comparison_for_bools(&mut self, op: Opcode, l: AllObjects, r: AllObjects) -> Result<()> {
+    /// Compares strings lexicographically, by their unicode code points
+    fn compare_strings(op: Opcode, l: AllObjects, r: AllObjects) -> bool {
         let left = match l {
-            AllObjects::Boolean(v) => v,
+            AllObjects::StringObj(v) => v,
             _ => unreachable!(),
         };
         let right = match r {
-            AllObjects::Boolean(v) => v,
+            AllObjects::StringObj(v) => v,
             _ => unreachable!(),
         };
-        let result = match op {
+        match op {
             OP_EQUAL => left.value == right.value,
             OP_NOT_EQUAL => left.value != right.value,
-            OP_GREATER_THAN => left.value & !right.value,
+            OP_GREATER_THAN => left.value > right.value,
+            OP_GREATER_THAN_OR_EQUAL => left.value >= right.value,
+            OP_LESS_THAN => left.value < right.value,
+            OP_LESS_THAN_OR_EQUAL => left.value <= right.value,
             _ => unreachable!(),
-        };
-        self.push(Self::get_bool_constant(result))
+        }
     }
 
     fn run_prefix_minus(&mut self) -> Result<()> {
-        let right = match self.pop()? {
-            AllObjects::Integer(v) => v,
-            v => return Err(anyhow!("expected an INTEGER, found {}", v.inspect())),
+        let operand = self.pop()?;
+        self.push(Self::negate(operand)?)
+    }
+
+    /// Returns the result of a prefix minus on the operand, which the compiler also uses to fold
+    /// constant expressions
+    pub(crate) fn negate(operand: AllObjects) -> Result<AllObjects> {
+        let negated = match operand {
+            AllObjects::Integer(v) => match v.value.checked_neg() {
+                Some(value) => AllObjects::Integer(Integer { value }),
+                // only `i64::MIN` overflows, as its positive doesn't fit in an `i64`
+                None => AllObjects::from_big_int(-&BigInt::from(v.value)),
+            },
+            AllObjects::BigInteger(v) => AllObjects::from_big_int(-v.value.as_ref()),
+            AllObjects::Float(v) => AllObjects::Float(Float { value: -v.value }),
+            v => return Err(RuntimeError::InvalidNegationOperand { found: v.inspect() }),
         };
-        self.push(AllObjects::Integer(Integer {
-            value: -right.value,
-        }))
+        Ok(negated)
     }
 
     fn run_prefix_bang(&mut self) -> Result<()> {
-        let result = match self.pop()? {
+        let operand = self.pop()?;
+        self.push(Self::not(operand))
+    }
+
+    /// Returns the result of a prefix bang on the operand, which the compiler also uses to fold
+    /// constant expressions
+    pub(crate) fn not(operand: AllObjects) -> AllObjects {
+        match operand {
             TRUE => FALSE,
             FALSE => TRUE,
             NULL => TRUE,
             _ => FALSE,
-        };
-        self.push(result)
+        }
     }
 
     fn cast_obj_to_bool(obj: AllObjects) -> AllObjects {